@@ -0,0 +1,163 @@
+//! The audio mixer.
+//!
+//! Every sound plays on one of the buses of the [`AudioMixer`], whose volumes come from
+//! the settings and are scaled by the master bus. The music is ducked while the game is
+//! paused, fading from one volume to the other rather than jumping. What music plays is
+//! up to the [`music`](crate::music) module.
+
+use crate::constants::*;
+use crate::input::{Action, InputMap};
+use crate::settings::Settings;
+use crate::ui::{game::GameMode, GameState};
+use bevy::audio::Volume;
+use bevy::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Bus {
+    /// Scales every other bus.
+    Master,
+    Music,
+    /// The sounds of the game.
+    Sfx,
+    /// The sounds of the buttons.
+    Ui,
+}
+
+#[derive(Debug, Resource)]
+pub struct AudioMixer {
+    master: f32,
+    music: f32,
+    sfx: f32,
+    ui: f32,
+    muted: bool,
+    /// How much of its volume the music plays at, lowered while the game is paused.
+    duck: f32,
+}
+
+impl AudioMixer {
+    pub fn new(settings: &Settings, muted: bool) -> Self {
+        let mut mixer = AudioMixer {
+            master: 1.0,
+            music: 1.0,
+            sfx: 1.0,
+            ui: 1.0,
+            muted,
+            duck: 1.0,
+        };
+        mixer.apply(settings);
+        mixer
+    }
+
+    /// Takes the volumes of the buses from `settings`.
+    pub fn apply(&mut self, settings: &Settings) {
+        self.master = settings.master_volume;
+        self.music = settings.music_volume;
+        self.sfx = settings.sfx_volume;
+        self.ui = settings.ui_volume;
+    }
+
+    /// The volume of `bus` alone.
+    pub fn bus_volume(&self, bus: Bus) -> f32 {
+        match bus {
+            Bus::Master => self.master,
+            Bus::Music => self.music,
+            Bus::Sfx => self.sfx,
+            Bus::Ui => self.ui,
+        }
+    }
+
+    /// The volume the sounds of `bus` play at, silent while muted.
+    pub fn volume(&self, bus: Bus) -> f32 {
+        match (self.muted, bus) {
+            (true, _) => 0.0,
+            (false, Bus::Master) => self.master,
+            (false, bus) => self.master * self.bus_volume(bus),
+        }
+    }
+
+    /// The volume the music plays at, ducked while the game is paused.
+    pub fn music_volume(&self) -> f32 {
+        self.volume(Bus::Music) * self.duck
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    /// Plays `source` once on `bus`.
+    pub fn play(&self, commands: &mut Commands, source: Handle<AudioSource>, bus: Bus) {
+        let volume = self.volume(bus);
+        if volume <= 0.0 {
+            return;
+        }
+        commands.spawn(AudioBundle {
+            source,
+            settings: PlaybackSettings::DESPAWN.with_volume(Volume::new_relative(volume)),
+        });
+    }
+}
+
+/// Moves `value` toward `target` by at most `step`.
+pub(crate) fn approach(value: f32, target: f32, step: f32) -> f32 {
+    if value < target {
+        (value + step).min(target)
+    } else {
+        (value - step).max(target)
+    }
+}
+
+/// Fades the music down while the game is paused, and back up when it resumes.
+pub fn duck_music(
+    time: Res<Time>,
+    mut mixer: ResMut<AudioMixer>,
+    game_state: Res<State<GameState>>,
+    game_mode: Res<State<GameMode>>,
+) {
+    let paused = *game_state.get() == GameState::Game && *game_mode.get() == GameMode::Pause;
+    let target = if paused { MUSIC_DUCK_VOLUME } else { 1.0 };
+    if mixer.duck != target {
+        mixer.duck = approach(
+            mixer.duck,
+            target,
+            time.delta_seconds() / MUSIC_FADE_DURATION,
+        );
+    }
+}
+
+/// Mutes or unmutes every sound.
+pub fn toggle_mute(
+    keyboard_input: Res<Input<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut mixer: ResMut<AudioMixer>,
+) {
+    if input_map.just_pressed(Action::Mute, &keyboard_input) {
+        mixer.toggle_mute();
+        info!("Sound {}", if mixer.is_muted() { "off" } else { "on" });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_master_bus_scales_the_others() {
+        let settings = Settings {
+            master_volume: 0.5,
+            sfx_volume: 0.6,
+            ..default()
+        };
+        let mut mixer = AudioMixer::new(&settings, false);
+        assert_eq!(mixer.volume(Bus::Master), 0.5);
+        assert_eq!(mixer.volume(Bus::Sfx), 0.3);
+        assert_eq!(mixer.bus_volume(Bus::Sfx), 0.6);
+
+        mixer.toggle_mute();
+        assert_eq!(mixer.volume(Bus::Sfx), 0.0);
+        assert_eq!(mixer.bus_volume(Bus::Sfx), 0.6);
+    }
+}
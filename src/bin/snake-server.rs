@@ -0,0 +1,36 @@
+//! A leaderboard server for the snake games of the local network.
+
+use clap::Parser;
+use snake::server::{serve, Store};
+use std::path::PathBuf;
+
+/// Ranks the scores the games send to it, after playing their replays again.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "0.0.0.0:8080")]
+    address: String,
+
+    /// SQLite database of the scores [default: leaderboard.sqlite in the data directory]
+    #[arg(long, value_name = "FILE")]
+    database: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let path = args
+        .database
+        .unwrap_or_else(|| snake::data_dir().join("leaderboard.sqlite"));
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let store = Store::open(&path).unwrap_or_else(|e| {
+        eprintln!("error: cannot open {}: {e}", path.display());
+        std::process::exit(1);
+    });
+    if let Err(e) = serve(&args.address, store) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
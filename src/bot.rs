@@ -0,0 +1,439 @@
+//! Computer players.
+//!
+//! A bot only looks at the [`SnakeGame`] and answers with the direction its snake should
+//! turn to, just like the keyboard fills [`PlayerInput`](crate::logic::PlayerInput). Its
+//! games are therefore recorded and replayed as any other.
+
+use crate::level::Level;
+use crate::rules::{Boundary, Position, SnakeDirection, SnakeGame};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+const DIRECTIONS: [SnakeDirection; 4] = [
+    SnakeDirection::Up,
+    SnakeDirection::Right,
+    SnakeDirection::Down,
+    SnakeDirection::Left,
+];
+
+pub trait SnakeBot: Send + Sync {
+    /// The direction the snake of `player` turns to on the next tick, `None` to go
+    /// straight on.
+    fn next_direction(&mut self, game: &SnakeGame, player: usize) -> Option<SnakeDirection>;
+}
+
+/// The built-in bots.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, clap::ValueEnum, Serialize, Deserialize,
+)]
+pub enum BotKind {
+    /// Heads straight for the apple.
+    Greedy,
+    /// Takes the shortest path to the apple when it can still reach its tail afterwards.
+    #[default]
+    Bfs,
+    /// Follows a cycle through every cell of the grid.
+    Cycle,
+}
+
+impl BotKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BotKind::Greedy => "Greedy",
+            BotKind::Bfs => "BFS",
+            BotKind::Cycle => "Cycle",
+        }
+    }
+
+    pub fn create(self) -> Box<dyn SnakeBot> {
+        match self {
+            BotKind::Greedy => Box::new(GreedyBot),
+            BotKind::Bfs => Box::new(BfsBot),
+            BotKind::Cycle => Box::<CycleBot>::default(),
+        }
+    }
+}
+
+/// Moves to the neighbouring cell closest to the apple, only avoiding to die on the
+/// next tick. It soon traps itself in its own tail.
+#[derive(Debug, Default)]
+pub struct GreedyBot;
+
+impl SnakeBot for GreedyBot {
+    fn next_direction(&mut self, game: &SnakeGame, player: usize) -> Option<SnakeDirection> {
+        let blocked = blocked_cells(game);
+        let apple = game.apple()?;
+        moves(game, player)
+            .filter(|(_, cell)| !blocked.contains(cell))
+            .min_by_key(|(_, cell)| distance(game, *cell, apple))
+            .map(|(direction, _)| direction)
+    }
+}
+
+/// Takes the shortest path to the apple, unless its tail would be out of reach once
+/// there. It then follows its tail the long way round, and as a last resort moves to the
+/// side with the most room.
+#[derive(Debug, Default)]
+pub struct BfsBot;
+
+impl SnakeBot for BfsBot {
+    fn next_direction(&mut self, game: &SnakeGame, player: usize) -> Option<SnakeDirection> {
+        let snake = &game.snakes()[player];
+        let blocked = blocked_cells(game);
+
+        if let Some(apple) = game.apple() {
+            if let Some(path) = find_path(game, snake.head(), snake.direction(), apple, &blocked) {
+                if tail_reachable_after(game, player, &path, &blocked) {
+                    return Some(path[0].0);
+                }
+            }
+        }
+
+        // Following the tail the long way round leaves the apple time to show up
+        // somewhere safer, where the shortest way would loop around the same cells
+        let tail = *snake.body().back().unwrap();
+        let follow = moves(game, player)
+            .filter(|(_, cell)| !blocked.contains(cell))
+            .filter_map(|(direction, cell)| {
+                let path = find_path(game, cell, direction, tail, &blocked)?;
+                Some((direction, path.len()))
+            })
+            .max_by_key(|(_, len)| *len);
+        if let Some((direction, _)) = follow.filter(|_| tail != snake.head()) {
+            return Some(direction);
+        }
+
+        moves(game, player)
+            .filter(|(_, cell)| !blocked.contains(cell))
+            .max_by_key(|(_, cell)| room(game, *cell, &blocked))
+            .map(|(direction, _)| direction)
+    }
+}
+
+/// Goes through every cell of the grid in the same order, so it never runs into its
+/// tail and fills the whole grid. Such a cycle only exists on levels without obstacles
+/// or portals; elsewhere, or when the cycle is blocked, it plays like the [`BfsBot`]
+/// until its body lies along the cycle again.
+#[derive(Debug, Default)]
+pub struct CycleBot {
+    cycle: Option<Option<HamiltonianCycle>>,
+    fallback: BfsBot,
+}
+
+/// A cycle through the cells of a level. No cycle goes through every cell of a grid
+/// with two odd sides, so there the cycle leaves out a `spare` cell, which takes the
+/// place of one of the cells of the cycle while the apple is on it.
+#[derive(Debug)]
+struct HamiltonianCycle {
+    cells: Vec<Position>,
+    /// The place in `cells` of each cell, the spare one sharing the place it can take.
+    index: HashMap<Position, usize>,
+    spare: Option<Position>,
+}
+
+impl HamiltonianCycle {
+    fn new(level: &Level) -> Option<Self> {
+        if !level.obstacles.is_empty() || !level.portals.is_empty() {
+            return None;
+        }
+        let (width, height) = (level.width, level.height);
+        let (cells, spare) = if height % 2 == 0 {
+            (zigzag(width, height), None)
+        } else if width % 2 == 0 {
+            let cells = zigzag(height, width)
+                .into_iter()
+                .map(|position| Position::new(position.y, position.x))
+                .collect();
+            (cells, None)
+        } else {
+            odd_zigzag(width, height)
+        };
+        let mut index: HashMap<Position, usize> = cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| (*cell, i))
+            .collect();
+        if let Some((spare, replaced)) = spare {
+            index.insert(spare, index[&replaced]);
+        }
+        Some(HamiltonianCycle {
+            cells,
+            index,
+            spare: spare.map(|(spare, _)| spare),
+        })
+    }
+
+    /// Swaps the spare cell into the cycle when the apple is on it, unless the cell it
+    /// replaces is still `blocked` by a snake.
+    fn reach_spare(&mut self, apple: Option<Position>, blocked: &HashSet<Position>) {
+        let Some(spare) = self.spare.filter(|spare| Some(*spare) == apple) else {
+            return;
+        };
+        let place = self.index[&spare];
+        if !blocked.contains(&self.cells[place]) {
+            self.spare = Some(std::mem::replace(&mut self.cells[place], spare));
+        }
+    }
+
+    /// Whether `body`, head first, lies along the cycle going round in `reversed` order.
+    fn follows(&self, body: &VecDeque<Position>, reversed: bool) -> bool {
+        let len = self.cells.len();
+        body.iter().zip(body.iter().skip(1)).all(|(cell, behind)| {
+            let (i, j) = (self.index[cell], self.index[behind]);
+            match reversed {
+                false => j == (i + len - 1) % len,
+                true => j == (i + 1) % len,
+            }
+        })
+    }
+
+    fn next(&self, position: Position, reversed: bool) -> Position {
+        let len = self.cells.len();
+        let i = self.index[&position];
+        match reversed {
+            false => self.cells[(i + 1) % len],
+            true => self.cells[(i + len - 1) % len],
+        }
+    }
+}
+
+/// A cycle through a `width` x `height` grid, `height` being even: along the bottom row,
+/// back and forth through the other rows but the first column, then down that column.
+fn zigzag(width: usize, height: usize) -> Vec<Position> {
+    let (width, height) = (width as i32, height as i32);
+    let mut cells: Vec<Position> = (0..width).map(|x| Position::new(x, 0)).collect();
+    for y in 1..height {
+        if y % 2 == 1 {
+            cells.extend((1..width).rev().map(|x| Position::new(x, y)));
+        } else {
+            cells.extend((1..width).map(|x| Position::new(x, y)));
+        }
+    }
+    cells.extend((1..height).rev().map(|y| Position::new(0, y)));
+    cells
+}
+
+/// A cycle through a `width` x `height` grid with two odd sides but its top left corner:
+/// the [`zigzag`] of the rows below the top one, which goes up to the top row and back
+/// between every other pair of cells. Also returns the corner, which can take the place
+/// of the cell below its right neighbour.
+fn odd_zigzag(width: usize, height: usize) -> (Vec<Position>, Option<(Position, Position)>) {
+    let (right, top) = (width as i32 - 1, height as i32 - 1);
+    let mut cells = vec![];
+    for cell in zigzag(width, height - 1) {
+        cells.push(cell);
+        // The row below the top one goes from right to left
+        if cell.y == top - 1 && cell.x >= 2 && (right - cell.x) % 2 == 0 {
+            cells.extend([Position::new(cell.x, top), Position::new(cell.x - 1, top)]);
+        }
+    }
+    (
+        cells,
+        Some((Position::new(0, top), Position::new(1, top - 1))),
+    )
+}
+
+impl SnakeBot for CycleBot {
+    fn next_direction(&mut self, game: &SnakeGame, player: usize) -> Option<SnakeDirection> {
+        let snake = &game.snakes()[player];
+        let cycle = self
+            .cycle
+            .get_or_insert_with(|| HamiltonianCycle::new(game.level()));
+        if let Some(cycle) = cycle {
+            let blocked = blocked_cells(game);
+            cycle.reach_spare(game.apple(), &blocked);
+            // A short snake can go either way round, a longer one the way its body lies
+            // along the cycle, if it does after the moves of the fallback
+            for reversed in [false, true] {
+                if !cycle.follows(snake.body(), reversed) {
+                    continue;
+                }
+                let next = cycle.next(snake.head(), reversed);
+                let step =
+                    moves(game, player).find(|(_, cell)| *cell == next && !blocked.contains(cell));
+                if let Some((direction, _)) = step {
+                    return Some(direction);
+                }
+            }
+        }
+        self.fallback.next_direction(game, player)
+    }
+}
+
+/// The moves the snake of `player` is allowed to make without running into a wall or
+/// an obstacle, with the cell each leads to.
+fn moves(game: &SnakeGame, player: usize) -> impl Iterator<Item = (SnakeDirection, Position)> + '_ {
+    let snake = &game.snakes()[player];
+    DIRECTIONS
+        .into_iter()
+        .filter(move |direction| *direction != snake.direction().opposite())
+        .filter_map(move |direction| {
+            game.next_cell(snake.head(), direction)
+                .filter(|cell| !game.is_obstacle(*cell))
+                .map(|cell| (direction, cell))
+        })
+}
+
+/// The cells still occupied by the snakes after the next tick. Tails are left out
+/// since they move on, unless their snake eats.
+fn blocked_cells(game: &SnakeGame) -> HashSet<Position> {
+    game.snakes()
+        .iter()
+        .flat_map(|snake| snake.body().iter().take(snake.body().len() - 1))
+        .copied()
+        .collect()
+}
+
+/// The number of moves between two cells, ignoring obstacles and portals.
+fn distance(game: &SnakeGame, a: Position, b: Position) -> usize {
+    let dx = a.x.abs_diff(b.x) as usize;
+    let dy = a.y.abs_diff(b.y) as usize;
+    match game.boundary() {
+        Boundary::Walls => dx + dy,
+        Boundary::Wrap => dx.min(game.width() - dx) + dy.min(game.height() - dy),
+    }
+}
+
+/// The shortest path from `from` to `to` avoiding `blocked`, as the moves to make and
+/// the cells they lead to. The first move cannot go back on `direction`.
+fn find_path(
+    game: &SnakeGame,
+    from: Position,
+    direction: SnakeDirection,
+    to: Position,
+    blocked: &HashSet<Position>,
+) -> Option<Vec<(SnakeDirection, Position)>> {
+    let mut previous: HashMap<Position, (SnakeDirection, Position)> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+    while let Some(cell) = queue.pop_front() {
+        if cell == to && cell != from {
+            let mut path = vec![];
+            let mut cell = to;
+            while cell != from {
+                let (direction, before) = previous[&cell];
+                path.push((direction, cell));
+                cell = before;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for next_direction in DIRECTIONS {
+            if cell == from && next_direction == direction.opposite() {
+                continue;
+            }
+            let Some(next) = game.next_cell(cell, next_direction) else {
+                continue;
+            };
+            if game.is_obstacle(next)
+                || (blocked.contains(&next) && next != to)
+                || next == from
+                || previous.contains_key(&next)
+            {
+                continue;
+            }
+            previous.insert(next, (next_direction, cell));
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+/// Whether the snake of `player` could still reach its tail after following `path` to
+/// the apple, so that it does not trap itself there.
+fn tail_reachable_after(
+    game: &SnakeGame,
+    player: usize,
+    path: &[(SnakeDirection, Position)],
+    blocked: &HashSet<Position>,
+) -> bool {
+    let snake = &game.snakes()[player];
+    // The snake grows by one cell when it eats the apple at the end of the path
+    let body: Vec<Position> = path
+        .iter()
+        .rev()
+        .map(|(_, cell)| *cell)
+        .chain(snake.body().iter().copied())
+        .take(snake.body().len() + 1)
+        .collect();
+    let (head, tail) = (body[0], *body.last().unwrap());
+    let own: HashSet<Position> = snake.body().iter().copied().collect();
+    let blocked: HashSet<Position> = blocked
+        .iter()
+        .filter(|cell| !own.contains(cell))
+        .chain(&body[..body.len() - 1])
+        .copied()
+        .collect();
+    let direction = path.last().unwrap().0;
+    find_path(game, head, direction, tail, &blocked).is_some()
+}
+
+/// The number of cells reachable from `from` without going through `blocked`.
+fn room(game: &SnakeGame, from: Position, blocked: &HashSet<Position>) -> usize {
+    let mut seen = HashSet::from([from]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(cell) = queue.pop_front() {
+        for direction in DIRECTIONS {
+            let Some(next) = game.next_cell(cell, direction) else {
+                continue;
+            };
+            if !game.is_obstacle(next) && !blocked.contains(&next) && seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    seen.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::GameRng;
+    use crate::replay::Replay;
+    use crate::rules::{DeathCause, StepOutcome};
+
+    /// Lets a bot of `kind` play alone on `level`, returning how its game ended.
+    fn play(kind: BotKind, level: Level, max_ticks: usize) -> (SnakeGame, Vec<StepOutcome>) {
+        let mut bots = [kind.create()];
+        let replay = Replay::from_bots(3, level, Boundary::Walls, &mut bots, max_ticks);
+        let simulation = replay.simulate();
+        (simulation.game, simulation.outcomes)
+    }
+
+    #[test]
+    fn greedy_bot_avoids_the_walls() {
+        let mut rng = GameRng::new(0);
+        let mut game = SnakeGame::new(3, 3, SnakeDirection::Up, &mut *rng);
+        game.step(None, &mut *rng);
+        assert_ne!(GreedyBot.next_direction(&game, 0), Some(SnakeDirection::Up));
+    }
+
+    #[test]
+    fn bfs_bot_eats_apples() {
+        let level = Level::open(10, 10, SnakeDirection::Up);
+        let (game, outcomes) = play(BotKind::Bfs, level, 2000);
+        assert!(game.score() >= 20, "scored {}", game.score());
+        assert_ne!(outcomes, [StepOutcome::Died(DeathCause::Tail)]);
+    }
+
+    #[test]
+    fn cycle_bot_wins_on_every_grid() {
+        for (width, height) in [(6, 6), (5, 8), (8, 5), (3, 3), (5, 7), (17, 17)] {
+            let level = Level::open(width, height, SnakeDirection::Up);
+            let (game, outcomes) = play(BotKind::Cycle, level, 100_000);
+            assert_eq!(outcomes, [StepOutcome::Won], "on {width}x{height}");
+            assert_eq!(game.body().len(), width * height);
+        }
+    }
+
+    #[test]
+    fn cycle_bot_only_follows_the_cycle_along_its_body() {
+        let cycle = HamiltonianCycle::new(&Level::open(4, 4, SnakeDirection::Up)).unwrap();
+        let along: VecDeque<Position> = cycle.cells[..3].iter().rev().copied().collect();
+        assert!(cycle.follows(&along, false));
+        assert!(!cycle.follows(&along, true));
+        // As the fallback may leave it
+        let across = [(1, 1), (1, 0), (0, 0)].map(|(x, y)| Position::new(x, y));
+        assert!(!cycle.follows(&VecDeque::from(across), false));
+        assert!(!cycle.follows(&VecDeque::from(across), true));
+    }
+}
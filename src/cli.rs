@@ -0,0 +1,98 @@
+//! Command-line interface of the `snake` binary.
+
+use crate::bot::BotKind;
+use crate::config::GameConfig;
+use crate::difficulty::{Difficulty, Pace};
+use clap::{ArgGroup, Parser};
+use std::path::PathBuf;
+
+/// The snake game written with the Bevy engine.
+#[derive(Debug, Parser)]
+#[command(version, about, group(ArgGroup::new("source").args(["replay", "bot"])))]
+pub struct Cli {
+    /// Number of columns of the grid
+    #[arg(long)]
+    pub width: Option<usize>,
+
+    /// Number of rows of the grid
+    #[arg(long)]
+    pub height: Option<usize>,
+
+    /// Number of moves of the snake per second
+    #[arg(long)]
+    pub speed: Option<f32>,
+
+    /// Speed of the games [default: normal]
+    #[arg(long, value_enum)]
+    pub difficulty: Option<Difficulty>,
+
+    /// Speed the snake up as it eats
+    #[arg(long)]
+    pub accelerate: bool,
+
+    /// Seed of the games, a random one is drawn for each game if none is given
+    #[arg(long, env = "SNAKE_SEED")]
+    pub seed: Option<u64>,
+
+    /// Configuration file [default: config.ron in the data directory]
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Disable music and sounds
+    #[arg(long)]
+    pub mute: bool,
+
+    /// Watch a replay file instead of going to the menu
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    /// Let a bot play the first snake, or the second one in versus games
+    #[arg(long, value_enum)]
+    pub bot: Option<BotKind>,
+
+    /// Simulate the replay, or a game of the bot, without opening a window and print
+    /// its results
+    #[arg(long, requires = "source")]
+    pub headless: bool,
+
+    /// Address of the leaderboard server to send scores to
+    #[arg(long, value_name = "URL")]
+    pub leaderboard: Option<String>,
+
+    /// Start in fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Go straight to the menu
+    #[arg(long)]
+    pub skip_splash: bool,
+}
+
+impl Cli {
+    /// The pace of the games started from the menu.
+    pub fn pace(&self) -> Pace {
+        Pace {
+            difficulty: self.difficulty.unwrap_or_default(),
+            accelerate: self.accelerate,
+        }
+    }
+
+    /// Overrides the values of `config` with the ones given on the command line.
+    pub fn apply(&self, config: &mut GameConfig) {
+        if let Some(width) = self.width {
+            config.grid_width = width;
+        }
+        if let Some(height) = self.height {
+            config.grid_height = height;
+        }
+        if let Some(speed) = self.speed {
+            config.refresh_rate = speed;
+        }
+        if let Some(url) = &self.leaderboard {
+            config.leaderboard_url = Some(url.clone());
+        }
+        if self.skip_splash {
+            config.splash_screen_duration = 0.0;
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Runtime configuration of the game.
+//!
+//! The configuration is read from a RON file at startup. Every field is optional and
+//! falls back to the matching value of [`crate::constants`].
+
+use crate::constants::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const MAX_GRID_SIZE: usize = 100;
+
+#[derive(Clone, Debug, Resource, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub grid_width: usize,
+    pub grid_height: usize,
+    /// Number of moves of the snake per second.
+    pub refresh_rate: f32,
+    /// Number of apples between two speed-ups of accelerating games.
+    pub speed_up_every: usize,
+    /// How many times faster accelerating games get at each speed-up.
+    pub speed_up_factor: f32,
+    /// The shortest time between two moves of accelerating games, in seconds.
+    pub min_tick_interval: f32,
+    pub tile_size: Vec2,
+    pub snake_size: Vec2,
+    pub apple_size: Vec2,
+    pub wall_thickness: f32,
+    pub splash_screen_duration: f32,
+    pub results_screen_duration: f32,
+    pub colors: ColorConfig,
+    /// Address of the leaderboard server scores are sent to, such as
+    /// `"http://192.168.1.10:8080"`.
+    pub leaderboard_url: Option<String>,
+}
+
+/// Colors are written as hexadecimal strings such as `"00FF00"`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    #[serde(with = "hex_color")]
+    pub background: Color,
+    #[serde(with = "hex_color")]
+    pub wall: Color,
+    #[serde(with = "hex_color")]
+    pub head: Color,
+    #[serde(with = "hex_color")]
+    pub tail: Color,
+    /// The head of the second snake of versus games.
+    #[serde(with = "hex_color")]
+    pub rival_head: Color,
+    #[serde(with = "hex_color")]
+    pub rival_tail: Color,
+    #[serde(with = "hex_color")]
+    pub apple: Color,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            grid_width: GRID_WIDTH,
+            grid_height: GRID_HEIGHT,
+            refresh_rate: REFRESH_RATE,
+            speed_up_every: SPEED_UP_EVERY,
+            speed_up_factor: SPEED_UP_FACTOR,
+            min_tick_interval: MIN_TICK_INTERVAL,
+            tile_size: TILE_SIZE,
+            snake_size: SNAKE_SIZE,
+            apple_size: APPLE_SIZE,
+            wall_thickness: WALL_THICKNESS,
+            splash_screen_duration: SPLASH_SCREEN_DURATION,
+            results_screen_duration: RESULTS_SCREEN_DURATION,
+            colors: ColorConfig::default(),
+            leaderboard_url: None,
+        }
+    }
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        ColorConfig {
+            background: BACKGROUND_COLOR,
+            wall: WALL_COLOR,
+            head: HEAD_COLOR,
+            tail: TAIL_COLOR,
+            rival_head: RIVAL_HEAD_COLOR,
+            rival_tail: RIVAL_TAIL_COLOR,
+            apple: APPLE_COLOR,
+        }
+    }
+}
+
+impl ColorConfig {
+    /// The colors of the head and of the tail of the snake of `player`.
+    pub fn snake(&self, player: usize) -> (Color, Color) {
+        match player {
+            0 => (self.head, self.tail),
+            _ => (self.rival_head, self.rival_tail),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    Invalid { field: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read {}: {e}", path.display()),
+            ConfigError::Parse(path, e) => write!(f, "{}:{e}", path.display()),
+            ConfigError::Invalid { field, reason } => {
+                write!(f, "invalid value for `{field}`: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl GameConfig {
+    /// The configuration file used when none is given.
+    pub fn default_path() -> PathBuf {
+        crate::data_dir().join("config.ron")
+    }
+
+    /// Reads and validates the configuration file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let config: GameConfig =
+            ron::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads the configuration file at `path`, or returns the default configuration if
+    /// there is no such file.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        match Self::load(&path) {
+            Err(ConfigError::Io(_, e)) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            result => result,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field, reason: &str| {
+            Err(ConfigError::Invalid {
+                field,
+                reason: reason.to_string(),
+            })
+        };
+        let grid_range = format!("must be between 2 and {MAX_GRID_SIZE}");
+        if !(2..=MAX_GRID_SIZE).contains(&self.grid_width) {
+            return invalid("grid_width", &grid_range);
+        }
+        if !(2..=MAX_GRID_SIZE).contains(&self.grid_height) {
+            return invalid("grid_height", &grid_range);
+        }
+        if !(self.refresh_rate.is_finite() && self.refresh_rate > 0.0) {
+            return invalid("refresh_rate", "must be a positive number");
+        }
+        if self.speed_up_every == 0 {
+            return invalid("speed_up_every", "must be at least 1");
+        }
+        if !(self.speed_up_factor.is_finite() && self.speed_up_factor >= 1.0) {
+            return invalid("speed_up_factor", "must be at least 1");
+        }
+        if !(self.min_tick_interval.is_finite() && self.min_tick_interval > 0.0) {
+            return invalid("min_tick_interval", "must be a positive number");
+        }
+        if !(self.tile_size.is_finite() && self.tile_size.min_element() > 0.0) {
+            return invalid("tile_size", "must be positive");
+        }
+        for (field, size) in [
+            ("snake_size", self.snake_size),
+            ("apple_size", self.apple_size),
+        ] {
+            if !(size.is_finite() && size.min_element() > 0.0) {
+                return invalid(field, "must be positive");
+            }
+            if size.cmpgt(self.tile_size).any() {
+                return invalid(field, "cannot be larger than `tile_size`");
+            }
+        }
+        for (field, value) in [
+            ("wall_thickness", self.wall_thickness),
+            ("splash_screen_duration", self.splash_screen_duration),
+            ("results_screen_duration", self.results_screen_duration),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return invalid(field, "cannot be negative");
+            }
+        }
+        Ok(())
+    }
+}
+
+mod hex_color {
+    use bevy::prelude::Color;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = color.as_rgba_u8();
+        let hex = if a == u8::MAX {
+            format!("{r:02X}{g:02X}{b:02X}")
+        } else {
+            format!("{r:02X}{g:02X}{b:02X}{a:02X}")
+        };
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        Color::hex(&hex).map_err(|e| D::Error::custom(format!("invalid color {hex:?}: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_fall_back_to_the_defaults() {
+        let config: GameConfig =
+            ron::from_str("(grid_width: 9, colors: (apple: \"FF0000\"))").unwrap();
+        assert_eq!(config.grid_width, 9);
+        assert_eq!(config.grid_height, GRID_HEIGHT);
+        assert_eq!(config.colors.apple, Color::rgb_u8(255, 0, 0));
+        assert_eq!(config.colors.head, HEAD_COLOR);
+        assert!(config.validate().is_ok());
+        assert!(GameConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let config = GameConfig {
+            grid_width: 1,
+            ..default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid {
+                field: "grid_width",
+                ..
+            })
+        ));
+        let config = GameConfig {
+            snake_size: TILE_SIZE * 2.0,
+            ..default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid {
+                field: "snake_size",
+                ..
+            })
+        ));
+        assert!(ron::from_str::<GameConfig>("(speed: 3.0)").is_err());
+    }
+}
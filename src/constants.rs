@@ -3,38 +3,91 @@ use bevy::prelude::*;
 
 pub const WALL_THICKNESS: f32 = 5.0;
 pub const WALL_COLOR: Color = Color::rgb(0.5, 0.5, 0.5);
+pub const WRAP_WALL_ALPHA: f32 = 0.3;
 
 pub const BACKGROUND_COLOR: Color = Color::BLACK;
 pub const TILE_SIZE: Vec2 = Vec2::new(20.0, 20.0);
 pub const REFRESH_RATE: f32 = 7.0;
+/// Accelerating games speed up every this many apples.
+pub const SPEED_UP_EVERY: usize = 5;
+pub const SPEED_UP_FACTOR: f32 = 1.1;
+/// The shortest time between two moves of accelerating games, in seconds.
+pub const MIN_TICK_INTERVAL: f32 = 0.04;
 pub const GRID_WIDTH: usize = 17;
 pub const GRID_HEIGHT: usize = 17;
 
 pub const SCOREBOARD_FONT_SIZE: f32 = 28.0;
+pub const SCOREBOARD_GAP: f32 = 40.0;
 pub const TEXT_COLOR: Color = Color::rgb(0.0, 0.7, 0.0);
 pub const MENU_TEXT_COLOR: Color = Color::GREEN;
 pub const RESULTS_TEXT_COLOR: Color = Color::rgb(0.8, 0.8, 0.8);
 pub const TEXT_BUTTON_SIZE: f32 = 40.0;
 pub const BUTTON_WIDTH: f32 = 250.0;
 pub const BUTTON_HEIGHT: f32 = 65.0;
-pub const BUTTON_MARGIN: f32 = 20.0;
+pub const BUTTON_MARGIN: f32 = 15.0;
+pub const PAUSE_BUTTON_WIDTH: f32 = 340.0;
 pub const MENU_TITLE_SIZE: f32 = 40.0;
+pub const MENU_TITLE_MARGIN: f32 = 50.0;
+pub const OPTION_TEXT_SIZE: f32 = 20.0;
+pub const OPTION_BUTTON_WIDTH: f32 = 110.0;
+pub const OPTION_BUTTON_HEIGHT: f32 = 45.0;
+pub const OPTION_BUTTON_MARGIN: f32 = 5.0;
 pub const RESULTS_TEXT_SIZE: f32 = 30.0;
+pub const HIGH_SCORE_TEXT_SIZE: f32 = 20.0;
+pub const HIGH_SCORE_COLUMN_GAP: f32 = 40.0;
+pub const CONTROL_LABEL_WIDTH: f32 = 110.0;
+pub const CONTROL_KEYS_WIDTH: f32 = 170.0;
+pub const CONTROL_BUTTON_WIDTH: f32 = 70.0;
+pub const CONTROL_BUTTON_HEIGHT: f32 = 26.0;
+pub const CONTROL_BUTTON_MARGIN: f32 = 2.0;
+pub const CONTROL_TITLE_MARGIN: f32 = 20.0;
+pub const CONTROL_MENU_BUTTON_HEIGHT: f32 = 50.0;
+pub const CONTROL_STATUS_SIZE: f32 = 14.0;
+pub const REPLAY_HELP_FONT_SIZE: f32 = 12.0;
+pub const EFFECTS_FONT_SIZE: f32 = 16.0;
+pub const EDITOR_TEXT_SIZE: f32 = 14.0;
+pub const EDITOR_BUTTON_WIDTH: f32 = 80.0;
+pub const EDITOR_BUTTON_HEIGHT: f32 = 30.0;
+pub const EDITOR_BUTTON_MARGIN: f32 = 5.0;
+pub const EDITOR_GRID_COLOR: Color = Color::rgb(0.12, 0.12, 0.12);
+/// Cells are drawn a bit smaller than the tiles so that the grid shows.
+pub const EDITOR_CELL_SCALE: f32 = 0.9;
 
 pub const SNAKE_SIZE: Vec2 = Vec2::new(17.5, 17.5);
 pub const INITIAL_SNAKE_DIRECTION: SnakeDirection = SnakeDirection::Up;
 pub const HEAD_COLOR: Color = Color::rgb(0.0, 1.0, 0.0);
 pub const TAIL_COLOR: Color = Color::rgb(0.0, 0.4, 0.0);
+pub const RIVAL_HEAD_COLOR: Color = Color::rgb(0.0, 0.6, 1.0);
+pub const RIVAL_TAIL_COLOR: Color = Color::rgb(0.0, 0.25, 0.5);
 
 pub const APPLE_COLOR: Color = Color::rgb(1.0, 0.0, 0.0);
 pub const APPLE_SIZE: Vec2 = Vec2::new(12.0, 12.0);
+pub const GOLDEN_APPLE_COLOR: Color = Color::rgb(1.0, 0.84, 0.0);
+pub const SHRINK_PILL_COLOR: Color = Color::rgb(0.8, 0.3, 1.0);
+pub const SLOW_TIME_COLOR: Color = Color::rgb(0.3, 0.8, 1.0);
+pub const GHOST_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.6);
+pub const MULTIPLIER_COLOR: Color = Color::rgb(1.0, 0.5, 0.0);
+/// Pickups blink during their last ticks on the grid.
+pub const PICKUP_BLINK_TICKS: u32 = 10;
+/// The seconds time-attack games can last.
+pub const TIME_LIMITS: [u32; 2] = [60, 120];
 
 pub const WINDOW_PADDING: f32 = 50.0;
 pub const MENU_WIDTH: f32 = 500.0;
 pub const MENU_HEIGHT: f32 = 500.0;
+/// Pixels a menu taller than the window scrolls for each line of the mouse wheel.
+pub const MENU_SCROLL_SPEED: f32 = 40.0;
 
 pub const SPLASH_SCREEN_DURATION: f32 = 2.0;
 pub const RESULTS_SCREEN_DURATION: f32 = 2.0;
+/// How much of its volume the music keeps while the game is paused.
+pub const MUSIC_DUCK_VOLUME: f32 = 1.0 / 3.0;
+/// The seconds the music takes to fade down when the game is paused, or back up.
+pub const MUSIC_FADE_DURATION: f32 = 0.4;
+/// The seconds the music takes to crossfade from the tracks of a screen to the next ones.
+pub const MUSIC_CROSSFADE_DURATION: f32 = 1.5;
+/// How many times faster, or slower, than its tempo the music can play.
+pub const MUSIC_MAX_SPEED: f32 = 1.5;
 
 pub const NORMAL_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 pub const HOVERED_BUTTON: Color = Color::rgb(0.35, 0.35, 0.35);
@@ -42,3 +95,6 @@ pub const HOVERED_PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
 pub const PRESSED_BUTTON: Color = Color::rgb(0.45, 0.85, 0.45);
 
 pub const WINDOW_VISIBLE_DELAY: u32 = 3;
+
+/// How far the left stick of a gamepad must be pushed to steer, between 0 and 1.
+pub const GAMEPAD_DEADZONE: f32 = 0.5;
@@ -0,0 +1,340 @@
+//! The daily challenge.
+//!
+//! Everyone playing on the same day gets the same game: the date, in UTC so that it is
+//! the same all around the world, seeds the game and picks one of the bundled levels
+//! and a [`Modifier`]. The best score of each day is kept in `daily.ron` in the data
+//! directory, and the results screen gives a [`ResultCode`] to share with friends.
+
+use crate::difficulty::Difficulty;
+use crate::level::LevelAsset;
+use crate::logic::{Assisted, Game, GameEvent, GameOptions};
+use crate::persist;
+use crate::replay::{Replay, ReplayRecorder};
+use crate::rules::{Boundary, Ruleset, SnakeDirection};
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A day of the proleptic Gregorian calendar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// The current day in UTC.
+    pub fn today() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Date::from_days((seconds / 86_400) as i64)
+    }
+
+    /// The day `days` days after 1970-01-01, after Howard Hinnant's `civil_from_days`.
+    pub fn from_days(days: i64) -> Self {
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        // Months start in March, so that the leap day comes last
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Date {
+            year: year as i32,
+            month: month as u32,
+            day: day as u32,
+        }
+    }
+
+    /// The date as the digits `YYYYMMDD`.
+    pub fn number(self) -> u32 {
+        self.year as u32 * 10_000 + self.month * 100 + self.day
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// The twist of the rules of a daily challenge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Modifier {
+    Wrap,
+    PowerUps,
+    Accelerate,
+    Hard,
+    TimeAttack,
+    Survival,
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 6] = [
+        Modifier::Wrap,
+        Modifier::PowerUps,
+        Modifier::Accelerate,
+        Modifier::Hard,
+        Modifier::TimeAttack,
+        Modifier::Survival,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Modifier::Wrap => "Wrap",
+            Modifier::PowerUps => "Power-ups",
+            Modifier::Accelerate => "Accelerating",
+            Modifier::Hard => "Hard",
+            Modifier::TimeAttack => "Time attack",
+            Modifier::Survival => "Survival",
+        }
+    }
+}
+
+/// The game of a day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyChallenge {
+    pub date: Date,
+    pub seed: u64,
+    /// The index of the level among the bundled ones, an open grid if there is none.
+    pub level: Option<usize>,
+    pub modifier: Modifier,
+}
+
+impl DailyChallenge {
+    /// The challenge of `date`, played on one of `levels` bundled levels.
+    pub fn of(date: Date, levels: usize) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(u64::from(date.number()));
+        DailyChallenge {
+            date,
+            seed: rng.gen(),
+            level: (levels > 0).then(|| rng.gen_range(0..levels)),
+            modifier: Modifier::ALL[rng.gen_range(0..Modifier::ALL.len())],
+        }
+    }
+
+    /// The options of the challenge, played on `level`.
+    pub fn options(&self, level: Option<AssetId<LevelAsset>>) -> GameOptions {
+        let mut options = GameOptions { level, ..default() };
+        match self.modifier {
+            Modifier::Wrap => options.boundary = Boundary::Wrap,
+            Modifier::PowerUps => options.power_ups = true,
+            Modifier::Accelerate => options.pace.accelerate = true,
+            Modifier::Hard => options.pace.difficulty = Difficulty::Hard,
+            Modifier::TimeAttack => options.ruleset = Ruleset::TimeAttack,
+            Modifier::Survival => options.ruleset = Ruleset::Survival,
+        }
+        options
+    }
+}
+
+/// The daily challenge being played, from the `Daily` button of the menu until the
+/// player goes back to the menu.
+#[derive(Resource)]
+pub struct DailyGame {
+    pub challenge: DailyChallenge,
+    /// The options the game is played with, instead of the ones picked in the menu.
+    pub options: GameOptions,
+}
+
+/// A hash of the inputs of a game, which tells whether two games were played the same.
+/// It is FNV-1a, which unlike the hasher of the standard library never changes.
+pub fn replay_hash(replay: &Replay) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    let bytes = replay.seed.to_le_bytes().into_iter().chain(
+        replay
+            .inputs
+            .iter()
+            .chain(&replay.rival_inputs)
+            .map(|input| match input {
+                None => 0,
+                Some(SnakeDirection::Left) => 1,
+                Some(SnakeDirection::Right) => 2,
+                Some(SnakeDirection::Up) => 3,
+                Some(SnakeDirection::Down) => 4,
+            }),
+    );
+    for byte in bytes {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// The short code of a daily result, `YYYYMMDD-SCORE-HASH`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResultCode {
+    pub date: Date,
+    pub score: usize,
+    /// The [`replay_hash`] of the game.
+    pub hash: u32,
+}
+
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}-{:08X}", self.date.number(), self.score, self.hash)
+    }
+}
+
+/// The best result of a day.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyBest {
+    pub score: usize,
+    pub code: String,
+}
+
+/// The best result of each day a challenge was played, by date.
+#[derive(Clone, Debug, Default, Resource, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DailyBests(BTreeMap<String, DailyBest>);
+
+impl DailyBests {
+    pub fn best(&self, date: Date) -> Option<&DailyBest> {
+        self.0.get(&date.to_string())
+    }
+
+    /// Keeps `code` if it beats the best result of its day, and returns whether it did.
+    pub fn record(&mut self, code: ResultCode) -> bool {
+        if self
+            .best(code.date)
+            .is_some_and(|best| best.score >= code.score)
+        {
+            return false;
+        }
+        self.0.insert(
+            code.date.to_string(),
+            DailyBest {
+                score: code.score,
+                code: code.to_string(),
+            },
+        );
+        true
+    }
+
+    /// Reads the results at `path`, starting over if there is no such file.
+    pub fn load_or_recover(path: impl AsRef<Path>) -> Self {
+        persist::load_or_recover(path)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        persist::save_atomic(self, path)
+    }
+}
+
+pub fn daily_bests_path() -> PathBuf {
+    crate::data_dir().join("daily.ron")
+}
+
+pub fn load_daily_bests(mut commands: Commands) {
+    commands.insert_resource(DailyBests::load_or_recover(daily_bests_path()));
+}
+
+/// The result of the daily challenge just played, shown on the results screen.
+#[derive(Resource)]
+pub struct DailyResult {
+    pub code: ResultCode,
+    /// The best score of the day, this one included.
+    pub best: usize,
+    pub new_best: bool,
+}
+
+/// Keeps the result of a daily challenge when it ends, unless a bot played it. Runs
+/// before the replay of the game is saved, to hash it.
+pub fn record_daily(
+    mut commands: Commands,
+    mut events: EventReader<GameEvent>,
+    daily: Option<Res<DailyGame>>,
+    game: Res<Game>,
+    recorder: Res<ReplayRecorder>,
+    mut bests: ResMut<DailyBests>,
+    assisted: Query<(), With<Assisted>>,
+) {
+    if events.read().last().is_none() {
+        return;
+    }
+    let (Some(daily), Some(replay)) = (daily, recorder.0.as_ref()) else {
+        return;
+    };
+    if !assisted.is_empty() {
+        return;
+    }
+    let code = ResultCode {
+        date: daily.challenge.date,
+        score: game.score(),
+        hash: replay_hash(replay),
+    };
+    info!("Daily result: {code}");
+    let new_best = bests.record(code);
+    if new_best {
+        let path = daily_bests_path();
+        if let Err(e) = bests.save(&path) {
+            error!("Cannot save the daily results to {}: {e}", path.display());
+        }
+    }
+    let best = bests.best(code.date).map_or(code.score, |best| best.score);
+    commands.insert_resource(DailyResult {
+        code,
+        best,
+        new_best,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> Date {
+        Date { year, month, day }
+    }
+
+    #[test]
+    fn counts_days_from_the_epoch() {
+        assert_eq!(Date::from_days(0), date(1970, 1, 1));
+        assert_eq!(Date::from_days(-1), date(1969, 12, 31));
+        assert_eq!(Date::from_days(11_016), date(2000, 2, 29));
+        assert_eq!(Date::from_days(11_017), date(2000, 3, 1));
+        assert_eq!(Date::from_days(20_744), date(2026, 10, 18));
+        assert_eq!(date(2026, 10, 18).to_string(), "2026-10-18");
+    }
+
+    #[test]
+    fn challenges_only_depend_on_the_date() {
+        let today = date(2026, 10, 18);
+        assert_eq!(DailyChallenge::of(today, 5), DailyChallenge::of(today, 5));
+        assert_ne!(
+            DailyChallenge::of(today, 5).seed,
+            DailyChallenge::of(date(2026, 10, 19), 5).seed
+        );
+        assert_eq!(DailyChallenge::of(today, 0).level, None);
+    }
+
+    #[test]
+    fn keeps_the_best_result_of_each_day() {
+        let code = ResultCode {
+            date: date(2026, 10, 18),
+            score: 12,
+            hash: 0xBEEF,
+        };
+        assert_eq!(code.to_string(), "20261018-12-0000BEEF");
+
+        let mut bests = DailyBests::default();
+        assert!(bests.record(code));
+        assert!(!bests.record(ResultCode { score: 12, ..code }));
+        assert!(bests.record(ResultCode { score: 13, ..code }));
+        assert_eq!(bests.best(code.date).unwrap().code, "20261018-13-0000BEEF");
+        assert_eq!(bests.best(date(2026, 10, 19)), None);
+    }
+}
@@ -0,0 +1,164 @@
+//! How fast the snakes move: the difficulty presets, and the games where they speed up
+//! as they eat.
+//!
+//! Each preset scales the configured `refresh_rate`. The pace of a game is part of its
+//! score category, so scores of different speeds are never compared.
+
+use crate::config::GameConfig;
+use crate::rules::Ruleset;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, clap::ValueEnum,
+)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Insane,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Insane => "Insane",
+        }
+    }
+
+    /// How many times faster than the configured `refresh_rate` the snakes move.
+    pub fn speed_scale(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.7,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.5,
+            Difficulty::Insane => 2.2,
+        }
+    }
+}
+
+/// The speed settings of a game.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Pace {
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    /// The snakes move faster every `speed_up_every` apples.
+    #[serde(default)]
+    pub accelerate: bool,
+}
+
+impl Pace {
+    pub fn name(self) -> String {
+        match self.accelerate {
+            true => format!("{} accelerating", self.difficulty.name()),
+            false => self.difficulty.name().to_string(),
+        }
+    }
+
+    /// The pace games of `ruleset` are played at. Time-attack games never speed up, so
+    /// that their clock, which counts the moves, keeps time.
+    pub fn for_ruleset(self, ruleset: Ruleset) -> Self {
+        match ruleset {
+            Ruleset::TimeAttack => Pace {
+                accelerate: false,
+                ..self
+            },
+            _ => self,
+        }
+    }
+
+    /// The moves per second the snakes start at, for a configured `refresh_rate`.
+    pub fn start_rate(self, refresh_rate: f32) -> f32 {
+        refresh_rate * self.difficulty.speed_scale()
+    }
+
+    /// The time between two moves, in seconds, once the best snake has eaten `apples`
+    /// apples. Accelerating games are `speed_up_factor` times faster every
+    /// `speed_up_every` apples, until the moves are `min_tick_interval` apart.
+    pub fn tick_interval(self, config: &GameConfig, apples: usize) -> f32 {
+        let interval = 1.0 / self.start_rate(config.refresh_rate);
+        if !self.accelerate {
+            return interval;
+        }
+        let speed_ups = (apples / config.speed_up_every).min(i32::MAX as usize) as i32;
+        // Presets already faster than the minimum keep their speed
+        let min = config.min_tick_interval.min(interval);
+        (interval / config.speed_up_factor.powi(speed_ups)).max(min)
+    }
+}
+
+/// The pace of the running game, or of the replay being watched.
+#[derive(Clone, Copy, Debug, Default, Resource, Deref)]
+pub struct GamePace(pub Pace);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pace(difficulty: Difficulty, accelerate: bool) -> Pace {
+        Pace {
+            difficulty,
+            accelerate,
+        }
+    }
+
+    #[test]
+    fn presets_scale_the_refresh_rate() {
+        let config = GameConfig {
+            refresh_rate: 10.0,
+            ..default()
+        };
+        assert_eq!(
+            pace(Difficulty::Normal, false).tick_interval(&config, 50),
+            0.1
+        );
+        let easy = pace(Difficulty::Easy, false).tick_interval(&config, 0);
+        let insane = pace(Difficulty::Insane, false).tick_interval(&config, 0);
+        assert!(easy > 0.1 && insane < 0.1);
+    }
+
+    #[test]
+    fn accelerating_games_speed_up_down_to_the_minimum() {
+        let config = GameConfig {
+            refresh_rate: 10.0,
+            speed_up_every: 5,
+            speed_up_factor: 2.0,
+            min_tick_interval: 0.02,
+            ..default()
+        };
+        let pace = pace(Difficulty::Normal, true);
+        assert_eq!(pace.tick_interval(&config, 4), 0.1);
+        assert_eq!(pace.tick_interval(&config, 5), 0.05);
+        assert_eq!(pace.tick_interval(&config, 10), 0.025);
+        assert_eq!(pace.tick_interval(&config, 15), 0.02);
+        assert_eq!(pace.tick_interval(&config, usize::MAX), 0.02);
+
+        // Presets already faster than the minimum keep their speed
+        let config = GameConfig {
+            min_tick_interval: 1.0,
+            ..config
+        };
+        assert_eq!(pace.tick_interval(&config, 20), 0.1);
+    }
+
+    #[test]
+    fn time_attack_games_keep_their_speed() {
+        let hard = pace(Difficulty::Hard, true);
+        assert_eq!(
+            hard.for_ruleset(Ruleset::TimeAttack),
+            pace(Difficulty::Hard, false)
+        );
+        assert_eq!(hard.for_ruleset(Ruleset::Zen), hard);
+    }
+}
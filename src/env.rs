@@ -0,0 +1,419 @@
+//! A reinforcement-learning environment in the style of Gym.
+//!
+//! [`SnakeEnv`] drives a [`SnakeGame`] directly, without any Bevy app or window, so
+//! agents can be trained on thousands of games per second. Its games follow the same
+//! rules and are seeded the same way as the ones of the game, so an episode can be
+//! played again from its seed with `--seed`.
+
+use crate::config::ColorConfig;
+use crate::constants::INITIAL_SNAKE_DIRECTION;
+use crate::level::{Level, LevelError};
+use crate::logic::portal_color;
+use crate::rules::{Boundary, Position, SnakeDirection, SnakeGame, StepOutcome};
+use bevy::prelude::Color;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// The discrete actions of the agent, `None` going straight on.
+pub const ACTIONS: [Option<SnakeDirection>; 5] = [
+    None,
+    Some(SnakeDirection::Up),
+    Some(SnakeDirection::Down),
+    Some(SnakeDirection::Left),
+    Some(SnakeDirection::Right),
+];
+
+/// Number of planes of [`Observation::Grid`]: head, body, apple, obstacles and portals.
+pub const GRID_CHANNELS: usize = 5;
+
+/// Length of [`Observation::Features`].
+pub const FEATURE_COUNT: usize = 11;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ObservationKind {
+    #[default]
+    Grid,
+    Features,
+    Rgb,
+}
+
+/// What the agent sees of the game after each step.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Observation {
+    /// [`GRID_CHANNELS`] planes of `height` x `width` cells, each cell being `1.0` when
+    /// it holds what the plane stands for. Rows go from the top of the grid down.
+    Grid(Vec<f32>),
+    /// The danger of moving up, down, left and right, the direction of the snake one-hot
+    /// encoded in the same order, the offset to the apple and the length of the snake,
+    /// all between `-1.0` and `1.0`.
+    Features(Vec<f32>),
+    /// The grid drawn with the colors of the game, `cell_pixels` pixels a side per cell,
+    /// as rows of RGB bytes from the top of the grid down.
+    Rgb(Vec<u8>),
+}
+
+/// The reward of each outcome of a step.
+#[derive(Clone, Copy, Debug)]
+pub struct Rewards {
+    pub apple: f32,
+    pub death: f32,
+    /// Filling the whole grid, instead of the reward of the last apple.
+    pub win: f32,
+    /// Given on every step, usually a small negative value to hurry the agent.
+    pub step: f32,
+}
+
+impl Default for Rewards {
+    fn default() -> Self {
+        Rewards {
+            apple: 1.0,
+            death: -1.0,
+            win: 10.0,
+            step: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EnvConfig {
+    pub boundary: Boundary,
+    pub observation: ObservationKind,
+    pub rewards: Rewards,
+    /// Size of the side of a cell in [`Observation::Rgb`] frames.
+    pub cell_pixels: usize,
+    pub colors: ColorConfig,
+    /// Episodes are cut short after that many steps without eating, so that agents
+    /// going round in circles do not play forever.
+    pub max_steps_without_apple: Option<usize>,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            boundary: Boundary::default(),
+            observation: ObservationKind::default(),
+            rewards: Rewards::default(),
+            cell_pixels: 4,
+            colors: ColorConfig::default(),
+            max_steps_without_apple: None,
+        }
+    }
+}
+
+/// Details about the last step.
+#[derive(Clone, Copy, Debug)]
+pub struct Info {
+    pub outcome: StepOutcome,
+    pub score: usize,
+    /// Number of steps since the last reset.
+    pub ticks: usize,
+    /// The episode was cut short by [`EnvConfig::max_steps_without_apple`].
+    pub truncated: bool,
+}
+
+pub struct SnakeEnv {
+    level: Level,
+    config: EnvConfig,
+    game: SnakeGame,
+    rng: ChaCha8Rng,
+    ticks: usize,
+    hungry_ticks: usize,
+    done: bool,
+}
+
+impl SnakeEnv {
+    /// Creates an environment on `level`, ready for an episode with seed `0`.
+    pub fn new(level: Level, config: EnvConfig) -> Result<Self, LevelError> {
+        level.validate()?;
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let game = SnakeGame::from_level(level.clone(), &mut rng).with_boundary(config.boundary);
+        Ok(SnakeEnv {
+            level,
+            config,
+            game,
+            rng,
+            ticks: 0,
+            hungry_ticks: 0,
+            done: false,
+        })
+    }
+
+    /// Creates an environment on an empty `width` x `height` grid.
+    pub fn open(width: usize, height: usize, config: EnvConfig) -> Result<Self, LevelError> {
+        Self::new(Level::open(width, height, INITIAL_SNAKE_DIRECTION), config)
+    }
+
+    pub fn game(&self) -> &SnakeGame {
+        &self.game
+    }
+
+    pub fn config(&self) -> &EnvConfig {
+        &self.config
+    }
+
+    /// The dimensions of the observations of the environment.
+    pub fn observation_shape(&self) -> Vec<usize> {
+        let (width, height) = (self.level.width, self.level.height);
+        let pixels = self.config.cell_pixels;
+        match self.config.observation {
+            ObservationKind::Grid => vec![GRID_CHANNELS, height, width],
+            ObservationKind::Features => vec![FEATURE_COUNT],
+            ObservationKind::Rgb => vec![height * pixels, width * pixels, 3],
+        }
+    }
+
+    /// Starts a new episode, the game being the one `--seed` gives with the same level.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        self.game = SnakeGame::from_level(self.level.clone(), &mut self.rng)
+            .with_boundary(self.config.boundary);
+        self.ticks = 0;
+        self.hungry_ticks = 0;
+        self.done = false;
+        self.observe()
+    }
+
+    /// Plays one tick, turning first if `action` is a valid direction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the episode is over and the environment was not reset.
+    pub fn step(&mut self, action: Option<SnakeDirection>) -> (Observation, f32, bool, Info) {
+        assert!(
+            !self.done,
+            "the episode is over, the environment must be reset"
+        );
+        let outcome = self.game.step(action, &mut self.rng);
+        self.ticks += 1;
+        self.hungry_ticks = match outcome {
+            StepOutcome::AteApple => 0,
+            _ => self.hungry_ticks + 1,
+        };
+
+        let rewards = &self.config.rewards;
+        let reward = rewards.step
+            + match outcome {
+                // The games of the environment are standard ones without power-ups
+                StepOutcome::Moved
+                | StepOutcome::Picked(_)
+                | StepOutcome::TimeUp
+                | StepOutcome::Finished => 0.0,
+                StepOutcome::AteApple => rewards.apple,
+                StepOutcome::Won => rewards.win,
+                StepOutcome::Died(_) | StepOutcome::Bumped(_) => rewards.death,
+            };
+        let truncated = !outcome.is_over()
+            && self
+                .config
+                .max_steps_without_apple
+                .is_some_and(|max| self.hungry_ticks >= max);
+        self.done = outcome.is_over() || truncated;
+
+        let info = Info {
+            outcome,
+            score: self.game.score(),
+            ticks: self.ticks,
+            truncated,
+        };
+        (self.observe(), reward, self.done, info)
+    }
+
+    /// The observation of the current state, of the configured kind.
+    pub fn observe(&self) -> Observation {
+        match self.config.observation {
+            ObservationKind::Grid => Observation::Grid(self.grid()),
+            ObservationKind::Features => Observation::Features(self.features()),
+            ObservationKind::Rgb => Observation::Rgb(self.rgb()),
+        }
+    }
+
+    /// The index of `position` in a plane of rows going from the top of the grid down.
+    fn cell_index(&self, position: Position) -> usize {
+        let row = self.level.height - 1 - position.y as usize;
+        row * self.level.width + position.x as usize
+    }
+
+    /// See [`Observation::Grid`].
+    pub fn grid(&self) -> Vec<f32> {
+        let plane = self.level.width * self.level.height;
+        let mut grid = vec![0.0; GRID_CHANNELS * plane];
+        let mut set = |channel: usize, position: Position| {
+            grid[channel * plane + self.cell_index(position)] = 1.0;
+        };
+        for (i, position) in self.game.body().iter().enumerate() {
+            set(if i == 0 { 0 } else { 1 }, *position);
+        }
+        if let Some(apple) = self.game.apple() {
+            set(2, apple);
+        }
+        for obstacle in &self.level.obstacles {
+            set(3, *obstacle);
+        }
+        for position in self.level.portals.iter().flatten() {
+            set(4, *position);
+        }
+        grid
+    }
+
+    /// See [`Observation::Features`].
+    pub fn features(&self) -> Vec<f32> {
+        use SnakeDirection::*;
+        let head = self.game.head();
+        let body = self.game.body();
+        let flag = |value: bool| if value { 1.0 } else { 0.0 };
+        let mut features = Vec::with_capacity(FEATURE_COUNT);
+        // A move is dangerous when stepping the game with it would end it, and every
+        // move is once the game is over
+        for direction in [Up, Down, Left, Right] {
+            features.push(flag(self.game.is_over() || self.game.would_die(direction)));
+        }
+        for direction in [Up, Down, Left, Right] {
+            features.push(flag(self.game.direction() == direction));
+        }
+        let (dx, dy) = match self.game.apple() {
+            Some(apple) => (apple.x - head.x, apple.y - head.y),
+            None => (0, 0),
+        };
+        features.push(dx as f32 / self.level.width as f32);
+        features.push(dy as f32 / self.level.height as f32);
+        features.push(body.len() as f32 / (self.level.width * self.level.height) as f32);
+        features
+    }
+
+    /// See [`Observation::Rgb`].
+    pub fn rgb(&self) -> Vec<u8> {
+        let (width, height) = (self.level.width, self.level.height);
+        let pixels = self.config.cell_pixels;
+        let colors = &self.config.colors;
+        let bytes = |color: Color| {
+            let [r, g, b, _] = color.as_rgba_u8();
+            [r, g, b]
+        };
+
+        let mut cells = vec![bytes(colors.background); width * height];
+        for obstacle in &self.level.obstacles {
+            cells[self.cell_index(*obstacle)] = bytes(colors.wall);
+        }
+        for (index, ends) in self.level.portals.iter().enumerate() {
+            for position in ends {
+                cells[self.cell_index(*position)] = bytes(portal_color(index));
+            }
+        }
+        if let Some(apple) = self.game.apple() {
+            cells[self.cell_index(apple)] = bytes(colors.apple);
+        }
+        for (i, position) in self.game.body().iter().enumerate().rev() {
+            let color = if i == 0 { colors.head } else { colors.tail };
+            cells[self.cell_index(*position)] = bytes(color);
+        }
+
+        let mut frame = Vec::with_capacity(width * height * pixels * pixels * 3);
+        for row in 0..height * pixels {
+            for column in 0..width * pixels {
+                frame.extend(cells[row / pixels * width + column / pixels]);
+            }
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(observation: ObservationKind) -> SnakeEnv {
+        let config = EnvConfig {
+            observation,
+            ..EnvConfig::default()
+        };
+        SnakeEnv::open(7, 5, config).unwrap()
+    }
+
+    /// Plays a fixed sequence of actions from `seed`, returning every step.
+    fn episode(env: &mut SnakeEnv, seed: u64) -> Vec<(Observation, f32, bool)> {
+        let mut steps = vec![(env.reset(seed), 0.0, false)];
+        for action in ACTIONS.iter().cycle().take(40) {
+            let (observation, reward, done, _) = env.step(*action);
+            steps.push((observation, reward, done));
+            if done {
+                break;
+            }
+        }
+        steps
+    }
+
+    #[test]
+    fn episodes_only_depend_on_the_seed() {
+        let mut env = env(ObservationKind::Grid);
+        let first = episode(&mut env, 5);
+        episode(&mut env, 6);
+        assert_eq!(episode(&mut env, 5), first);
+        let apples: Vec<_> = (0..10)
+            .map(|seed| {
+                env.reset(seed);
+                env.game().apple()
+            })
+            .collect();
+        assert!(apples.iter().any(|apple| *apple != apples[0]));
+    }
+
+    #[test]
+    fn observations_match_their_shape() {
+        for kind in [
+            ObservationKind::Grid,
+            ObservationKind::Features,
+            ObservationKind::Rgb,
+        ] {
+            let mut env = env(kind);
+            let len = match env.reset(0) {
+                Observation::Grid(grid) => grid.len(),
+                Observation::Features(features) => features.len(),
+                Observation::Rgb(frame) => frame.len(),
+            };
+            assert_eq!(len, env.observation_shape().iter().product::<usize>());
+        }
+        assert_eq!(env(ObservationKind::Grid).observation_shape(), [5, 5, 7]);
+        assert_eq!(env(ObservationKind::Rgb).observation_shape(), [20, 28, 3]);
+    }
+
+    #[test]
+    fn the_grid_shows_the_snake_and_the_apple() {
+        let env = env(ObservationKind::Grid);
+        let Observation::Grid(grid) = env.observe() else {
+            unreachable!()
+        };
+        let plane = 7 * 5;
+        let head = env.cell_index(env.game().head());
+        let apple = env.cell_index(env.game().apple().unwrap());
+        assert_eq!(grid[head], 1.0);
+        assert_eq!(grid[2 * plane + apple], 1.0);
+        assert_eq!(grid.iter().sum::<f32>(), 2.0);
+    }
+
+    #[test]
+    fn the_features_flag_the_moves_that_end_the_game() {
+        for boundary in [Boundary::Walls, Boundary::Wrap] {
+            let config = EnvConfig {
+                boundary,
+                observation: ObservationKind::Features,
+                ..EnvConfig::default()
+            };
+            let mut env = SnakeEnv::open(7, 5, config).unwrap();
+            env.reset(0);
+            let mut observation = env.step(Some(SnakeDirection::Right)).0;
+            while env.game().head().x < 6 {
+                observation = env.step(None).0;
+            }
+            let Observation::Features(features) = observation else {
+                unreachable!()
+            };
+            // Going back on itself goes straight on, into the wall
+            let danger = if boundary == Boundary::Walls {
+                1.0
+            } else {
+                0.0
+            };
+            assert_eq!(features[..4], [0.0, 0.0, danger, danger]);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Gamepads: steering the snakes, and the player each connected gamepad plays for.
+//!
+//! Gamepads are given to the players in the order they are plugged in. A gamepad that
+//! is unplugged leaves its player free for the next one, so the other gamepads keep
+//! their players.
+
+use crate::constants::GAMEPAD_DEADZONE;
+use crate::logic::{Player, PlayerInput, SnakeDirection};
+use crate::ui::game::GameMode;
+use crate::ui::menu::Focused;
+use bevy::input::gamepad::{GamepadConnection, GamepadConnectionEvent};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// The gamepad of each player, `None` for the players whose gamepad was unplugged.
+#[derive(Debug, Default, Resource)]
+pub struct GamepadPlayers(Vec<Option<Gamepad>>);
+
+impl GamepadPlayers {
+    /// The player `gamepad` plays for, if it is connected.
+    pub fn player(&self, gamepad: Gamepad) -> Option<usize> {
+        self.0.iter().position(|slot| *slot == Some(gamepad))
+    }
+
+    fn connect(&mut self, gamepad: Gamepad) -> usize {
+        if let Some(player) = self.player(gamepad) {
+            return player;
+        }
+        match self.0.iter().position(Option::is_none) {
+            Some(player) => {
+                self.0[player] = Some(gamepad);
+                player
+            }
+            None => {
+                self.0.push(Some(gamepad));
+                self.0.len() - 1
+            }
+        }
+    }
+
+    fn disconnect(&mut self, gamepad: Gamepad) -> Option<usize> {
+        let player = self.player(gamepad)?;
+        self.0[player] = None;
+        Some(player)
+    }
+}
+
+pub fn assign_gamepads(
+    mut events: EventReader<GamepadConnectionEvent>,
+    mut players: ResMut<GamepadPlayers>,
+) {
+    for event in events.read() {
+        match &event.connection {
+            GamepadConnection::Connected(info) => {
+                let player = players.connect(event.gamepad);
+                info!("{} plays for player {}", info.name, player + 1);
+            }
+            GamepadConnection::Disconnected => {
+                if let Some(player) = players.disconnect(event.gamepad) {
+                    info!("The gamepad of player {} was unplugged", player + 1);
+                }
+            }
+        }
+    }
+}
+
+/// Whether `button_type` was just pressed on any gamepad.
+pub fn just_pressed(
+    gamepads: &Gamepads,
+    buttons: &Input<GamepadButton>,
+    button_type: GamepadButtonType,
+) -> bool {
+    gamepads
+        .iter()
+        .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button_type)))
+}
+
+/// The direction held on the D-pad or on the left stick of `gamepad`. The stick only
+/// counts beyond [`GAMEPAD_DEADZONE`], along its main axis.
+pub fn held_direction(
+    gamepad: Gamepad,
+    buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+) -> Option<SnakeDirection> {
+    use SnakeDirection::*;
+    for (button_type, direction) in [
+        (GamepadButtonType::DPadUp, Up),
+        (GamepadButtonType::DPadDown, Down),
+        (GamepadButtonType::DPadLeft, Left),
+        (GamepadButtonType::DPadRight, Right),
+    ] {
+        if buttons.pressed(GamepadButton::new(gamepad, button_type)) {
+            return Some(direction);
+        }
+    }
+    let axis = |axis_type| {
+        axes.get(GamepadAxis::new(gamepad, axis_type))
+            .unwrap_or(0.0)
+    };
+    let (x, y) = (
+        axis(GamepadAxisType::LeftStickX),
+        axis(GamepadAxisType::LeftStickY),
+    );
+    if x.abs().max(y.abs()) < GAMEPAD_DEADZONE {
+        None
+    } else if x.abs() > y.abs() {
+        Some(if x > 0.0 { Right } else { Left })
+    } else {
+        Some(if y > 0.0 { Up } else { Down })
+    }
+}
+
+/// Turns the directions held on the gamepads into presses, for the menus: a direction
+/// is only pressed again once it was let go.
+#[derive(Default)]
+pub struct DirectionPresses(HashMap<Gamepad, Option<SnakeDirection>>);
+
+impl DirectionPresses {
+    /// The direction just pressed on any gamepad.
+    pub fn read(
+        &mut self,
+        gamepads: &Gamepads,
+        buttons: &Input<GamepadButton>,
+        axes: &Axis<GamepadAxis>,
+    ) -> Option<SnakeDirection> {
+        let mut pressed = None;
+        for gamepad in gamepads.iter() {
+            let held = held_direction(gamepad, buttons, axes);
+            let previous = self.0.insert(gamepad, held).flatten();
+            if held.is_some() && held != previous {
+                pressed = held;
+            }
+        }
+        pressed
+    }
+}
+
+/// Steers the snakes with the gamepads of their players, and pauses the game with
+/// `Start`. Every gamepad steers the snake of single-player games.
+pub fn gamepad_input(
+    gamepad_players: Res<GamepadPlayers>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut players: Query<(&Player, &mut PlayerInput)>,
+    current_state: Res<State<GameMode>>,
+    mut next_state: ResMut<NextState<GameMode>>,
+    focused: Query<(), With<Focused>>,
+) {
+    let start = gamepad_players.0.iter().flatten().any(|gamepad| {
+        buttons.just_pressed(GamepadButton::new(*gamepad, GamepadButtonType::Start))
+    });
+    // Toggle the pause, unless a button of the pause screen was picked, as the pause
+    // key does
+    if start {
+        if let GameMode::Running = current_state.get() {
+            next_state.set(GameMode::Pause);
+        } else if focused.is_empty() {
+            next_state.set(GameMode::Running);
+        }
+    }
+
+    if let GameMode::Running = current_state.get() {
+        let versus = players.iter().len() > 1;
+        for (player, mut player_input) in &mut players {
+            for (i, gamepad) in gamepad_players.0.iter().enumerate() {
+                let Some(gamepad) = gamepad.filter(|_| !versus || i == player.0) else {
+                    continue;
+                };
+                if let Some(direction) = held_direction(gamepad, &buttons, &axes) {
+                    player_input.push(direction);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unplugged_gamepads_free_their_player() {
+        let mut players = GamepadPlayers::default();
+        assert_eq!(players.connect(Gamepad::new(3)), 0);
+        assert_eq!(players.connect(Gamepad::new(5)), 1);
+        assert_eq!(players.connect(Gamepad::new(3)), 0);
+        assert_eq!(players.disconnect(Gamepad::new(3)), Some(0));
+        assert_eq!(players.player(Gamepad::new(5)), Some(1));
+        assert_eq!(players.connect(Gamepad::new(7)), 0);
+        assert_eq!(players.disconnect(Gamepad::new(3)), None);
+    }
+
+    #[test]
+    fn the_stick_follows_its_main_axis() {
+        let gamepad = Gamepad::new(0);
+        let buttons = Input::<GamepadButton>::default();
+        let mut axes = Axis::<GamepadAxis>::default();
+        let mut tilt = |x: f32, y: f32| {
+            axes.set(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX), x);
+            axes.set(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY), y);
+            held_direction(gamepad, &buttons, &axes)
+        };
+        assert_eq!(tilt(0.0, 0.0), None);
+        assert_eq!(tilt(GAMEPAD_DEADZONE / 2.0, 0.0), None);
+        assert_eq!(tilt(0.9, -0.4), Some(SnakeDirection::Right));
+        assert_eq!(tilt(0.3, -0.8), Some(SnakeDirection::Down));
+    }
+}
@@ -0,0 +1,355 @@
+//! The keys bound to each action of the game.
+//!
+//! Every action can have several keys. The bindings are changed in the `Controls`
+//! screen of the menu and saved in the data directory with the other [`persist`] files.
+//! The keys the menus use to move between their buttons, press them and go back are kept
+//! out of the bindings.
+
+use crate::logic::SnakeDirection;
+use crate::persist;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// The moves of the second player of versus games, which also steer the snake of
+    /// single-player games.
+    RivalUp,
+    RivalDown,
+    RivalLeft,
+    RivalRight,
+    /// Pauses or resumes the game, or a replay.
+    Pause,
+    /// Starts the running game over.
+    Restart,
+    /// Silences every sound, or brings them back.
+    Mute,
+    Quit,
+    /// Hands the snake of the first player over to a bot, or takes it back.
+    Autopilot,
+    /// Plays a single tick of a paused replay.
+    Step,
+    /// Fast-forwards a replay while held.
+    FastForward,
+}
+
+/// The keys of the menus, which cannot be bound: `⌫` also leaves replays and tests of
+/// the editor.
+pub const RESERVED_KEYS: [KeyCode; 4] = [
+    KeyCode::Tab,
+    KeyCode::Return,
+    KeyCode::NumpadEnter,
+    KeyCode::Back,
+];
+
+impl Action {
+    pub const ALL: [Action; 15] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::RivalUp,
+        Action::RivalDown,
+        Action::RivalLeft,
+        Action::RivalRight,
+        Action::Pause,
+        Action::Restart,
+        Action::Mute,
+        Action::Quit,
+        Action::Autopilot,
+        Action::Step,
+        Action::FastForward,
+    ];
+
+    /// The moves of each player of versus games.
+    pub const MOVES: [[Action; 4]; 2] = [
+        [Action::Up, Action::Down, Action::Left, Action::Right],
+        [
+            Action::RivalUp,
+            Action::RivalDown,
+            Action::RivalLeft,
+            Action::RivalRight,
+        ],
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Left => "Left",
+            Action::Right => "Right",
+            Action::RivalUp => "P2 Up",
+            Action::RivalDown => "P2 Down",
+            Action::RivalLeft => "P2 Left",
+            Action::RivalRight => "P2 Right",
+            Action::Pause => "Pause",
+            Action::Restart => "Restart",
+            Action::Mute => "Mute",
+            Action::Quit => "Quit",
+            Action::Autopilot => "Bot",
+            Action::Step => "Step",
+            Action::FastForward => "Forward",
+        }
+    }
+
+    /// The direction the snake turns to, for the moves.
+    pub fn direction(self) -> Option<SnakeDirection> {
+        match self {
+            Action::Up | Action::RivalUp => Some(SnakeDirection::Up),
+            Action::Down | Action::RivalDown => Some(SnakeDirection::Down),
+            Action::Left | Action::RivalLeft => Some(SnakeDirection::Left),
+            Action::Right | Action::RivalRight => Some(SnakeDirection::Right),
+            _ => None,
+        }
+    }
+}
+
+/// A key cannot be bound to two actions, nor to any action if the menus use it.
+#[derive(Debug)]
+pub enum Conflict {
+    /// The key is already bound to `action`.
+    Bound {
+        key: KeyCode,
+        action: Action,
+    },
+    Reserved(KeyCode),
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::Bound { key, action } => {
+                write!(f, "{} is used by {}", key_name(*key), action.name())
+            }
+            Conflict::Reserved(key) => write!(f, "{} is used by the menus", key_name(*key)),
+        }
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+/// The keys of each action.
+#[derive(Clone, Debug, PartialEq, Resource, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InputMap {
+    bindings: BTreeMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = [
+            (Action::Up, vec![KeyCode::Up]),
+            (Action::Down, vec![KeyCode::Down]),
+            (Action::Left, vec![KeyCode::Left]),
+            (Action::Right, vec![KeyCode::Right]),
+            (Action::RivalUp, vec![KeyCode::P]),
+            (Action::RivalDown, vec![KeyCode::I]),
+            (Action::RivalLeft, vec![KeyCode::U]),
+            (Action::RivalRight, vec![KeyCode::E]),
+            (Action::Pause, vec![KeyCode::Space]),
+            (Action::Restart, vec![KeyCode::R]),
+            (Action::Mute, vec![KeyCode::M]),
+            (Action::Quit, vec![KeyCode::Escape]),
+            (Action::Autopilot, vec![KeyCode::A]),
+            (Action::Step, vec![KeyCode::N]),
+            (Action::FastForward, vec![KeyCode::F]),
+        ];
+        InputMap {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+/// The name of `key` shown to the players.
+pub fn key_name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    // The digits are `Key0` to `Key9`
+    match name.strip_prefix("Key") {
+        Some(digit) if !digit.is_empty() => digit.to_string(),
+        _ => name,
+    }
+}
+
+impl InputMap {
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The names of the keys of `action`, separated by commas.
+    pub fn key_names(&self, action: Action) -> String {
+        let names: Vec<String> = self.keys(action).iter().map(|key| key_name(*key)).collect();
+        names.join(", ")
+    }
+
+    pub fn pressed(&self, action: Action, input: &Input<KeyCode>) -> bool {
+        input.any_pressed(self.keys(action).iter().copied())
+    }
+
+    pub fn just_pressed(&self, action: Action, input: &Input<KeyCode>) -> bool {
+        input.any_just_pressed(self.keys(action).iter().copied())
+    }
+
+    /// The action `key` is bound to, if any.
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|action| self.keys(*action).contains(&key))
+    }
+
+    fn check(&self, action: Action, key: KeyCode) -> Result<(), Conflict> {
+        if RESERVED_KEYS.contains(&key) {
+            return Err(Conflict::Reserved(key));
+        }
+        match self.action(key) {
+            Some(other) if other != action => Err(Conflict::Bound { key, action: other }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Makes `key` the only key of `action`.
+    pub fn bind(&mut self, action: Action, key: KeyCode) -> Result<(), Conflict> {
+        self.check(action, key)?;
+        self.bindings.insert(action, vec![key]);
+        Ok(())
+    }
+
+    /// Adds `key` to the keys of `action`.
+    pub fn add(&mut self, action: Action, key: KeyCode) -> Result<(), Conflict> {
+        self.check(action, key)?;
+        let keys = self.bindings.entry(action).or_default();
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+        Ok(())
+    }
+
+    /// Gives back their default keys to the actions left without any, and drops the
+    /// keys bound twice or used by the menus, in case the file was edited by hand or
+    /// saved before some actions were added.
+    fn sanitize(&mut self) {
+        let defaults = InputMap::default();
+        let mut bound = RESERVED_KEYS.to_vec();
+        for action in Action::ALL {
+            let keys = self.bindings.entry(action).or_default();
+            keys.retain(|key| {
+                let duplicate = bound.contains(key);
+                bound.push(*key);
+                !duplicate
+            });
+        }
+        // Defaults taken by another action are left out
+        for action in Action::ALL {
+            let keys = self.bindings.entry(action).or_default();
+            if keys.is_empty() {
+                for key in defaults.keys(action) {
+                    if !bound.contains(key) {
+                        keys.push(*key);
+                        bound.push(*key);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the key bindings at `path`, using the default ones if there is no such
+    /// file.
+    pub fn load_or_recover(path: impl AsRef<Path>) -> Self {
+        let mut input_map: InputMap = persist::load_or_recover(path);
+        input_map.sanitize();
+        input_map
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        persist::save_atomic(self, path)
+    }
+}
+
+pub fn input_map_path() -> PathBuf {
+    crate::data_dir().join("controls.ron")
+}
+
+pub fn load_input_map(mut commands: Commands) {
+    commands.insert_resource(InputMap::load_or_recover(input_map_path()));
+}
+
+pub fn quit(
+    keyboard_input: Res<Input<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    if input_map.just_pressed(Action::Quit, &keyboard_input) {
+        app_exit_events.send(AppExit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_and_adds_keys() {
+        let mut input_map = InputMap::default();
+        input_map.bind(Action::Up, KeyCode::W).unwrap();
+        assert_eq!(input_map.keys(Action::Up), [KeyCode::W]);
+        input_map.add(Action::Up, KeyCode::K).unwrap();
+        input_map.add(Action::Up, KeyCode::K).unwrap();
+        assert_eq!(input_map.keys(Action::Up), [KeyCode::W, KeyCode::K]);
+        assert_eq!(input_map.action(KeyCode::K), Some(Action::Up));
+        // The old key is free again
+        assert_eq!(input_map.action(KeyCode::Up), None);
+        input_map.bind(Action::Down, KeyCode::Up).unwrap();
+        assert_eq!(input_map.key_names(Action::Up), "W, K");
+    }
+
+    #[test]
+    fn refuses_conflicting_keys() {
+        let mut input_map = InputMap::default();
+        assert!(matches!(
+            input_map.bind(Action::Pause, KeyCode::R),
+            Err(Conflict::Bound {
+                key: KeyCode::R,
+                action: Action::Restart
+            })
+        ));
+        assert!(matches!(
+            input_map.add(Action::Up, KeyCode::P),
+            Err(Conflict::Bound {
+                action: Action::RivalUp,
+                ..
+            })
+        ));
+        for key in RESERVED_KEYS {
+            assert!(matches!(
+                input_map.add(Action::Quit, key),
+                Err(Conflict::Reserved(_))
+            ));
+        }
+        assert_eq!(input_map, InputMap::default());
+    }
+
+    #[test]
+    fn sanitizes_edited_files() {
+        let mut input_map: InputMap =
+            ron::from_str("{Up: [W, Return], Down: [W, S], Pause: [A], Quit: []}").unwrap();
+        input_map.sanitize();
+        assert_eq!(input_map.keys(Action::Up), [KeyCode::W]);
+        assert_eq!(input_map.keys(Action::Down), [KeyCode::S]);
+        assert_eq!(input_map.keys(Action::Quit), [KeyCode::Escape]);
+        // A missing action does not get a default key taken by another one
+        assert_eq!(input_map.keys(Action::Pause), [KeyCode::A]);
+        assert!(input_map.keys(Action::Autopilot).is_empty());
+        assert_eq!(input_map.keys(Action::Step), [KeyCode::N]);
+        for action in Action::MOVES.into_iter().flatten() {
+            assert!(!input_map.keys(action).is_empty());
+        }
+    }
+}
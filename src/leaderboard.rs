@@ -0,0 +1,397 @@
+//! The client of the leaderboard server, and what it shares with the server.
+//!
+//! The `snake-server` binary ranks the games sent to it by the players of the local
+//! network. Each score comes with the replay of its game, which the server plays again
+//! to check the score, so only games on open grids can be ranked: their rules are the
+//! same for everyone.
+//!
+//! The API is plain JSON over HTTP:
+//!
+//! - `POST /scores` takes a [`Submission`] and answers with a [`Submitted`].
+//! - `GET /scores?boundary=walls&width=17&height=17&limit=10` answers with the best
+//!   [`Ranking`]s of that game mode and grid size. The games are normal standard ones
+//!   without power-ups, unless `difficulty` (`easy`, `normal`, `hard` or `insane`),
+//!   `accelerate`, `power_ups`, `ruleset` (`standard`, `time_attack`, `survival` or
+//!   `zen`), `time_limit` or `refresh_rate` (the moves per second the difficulty
+//!   scales) say otherwise.
+//!
+//! Errors are answered with an [`ErrorResponse`].
+
+use crate::difficulty::Difficulty;
+use crate::level::LevelError;
+use crate::replay::Replay;
+use crate::rules::{Boundary, Ruleset, TimeLimit};
+use crate::scores::{clean_initials, ScoreCategory};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+
+/// Number of rankings shown in the menu.
+pub const RANKINGS_LIMIT: usize = 10;
+/// Longest game the server plays again, in ticks, whatever the size of its grid.
+pub const MAX_REPLAY_TICKS: usize = 400_000;
+/// Most work the server does to play a replay again, in ticks times cells: a tick may
+/// go through every cell of the grid, to place an apple or check the body of the snake.
+pub const MAX_REPLAY_COST: usize = 200_000_000;
+
+/// Longest game the server plays again on a `width` by `height` grid, in ticks: as many
+/// moves as the snake needs to go through every cell, for each cell, within
+/// [`MAX_REPLAY_TICKS`] and [`MAX_REPLAY_COST`].
+pub fn max_replay_ticks(width: usize, height: usize) -> usize {
+    let cells = width.saturating_mul(height).max(1);
+    cells
+        .saturating_mul(cells)
+        .min(MAX_REPLAY_TICKS)
+        .min(MAX_REPLAY_COST / cells)
+}
+
+/// A score sent to the server.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Submission {
+    /// The initials of the player.
+    pub name: String,
+    pub score: usize,
+    pub replay: Replay,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Submitted {
+    /// The rank of the score in its table, starting from 1.
+    pub rank: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ranking {
+    pub rank: usize,
+    pub name: String,
+    pub score: usize,
+    /// The seed of the game, to play it again with `--seed`.
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// Why the server refuses a score.
+#[derive(Debug)]
+pub enum Rejection {
+    InvalidName,
+    Versus,
+    InvalidLevel(LevelError),
+    NotOpen,
+    InvalidSpeed,
+    InvalidTimeLimit,
+    TooLong { max: usize },
+    Unfinished,
+    ScoreMismatch { claimed: usize, actual: usize },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::InvalidName => write!(f, "names are 1 to 3 letters or digits"),
+            Rejection::Versus => write!(f, "versus games are not ranked"),
+            Rejection::InvalidLevel(e) => write!(f, "invalid level: {e}"),
+            Rejection::NotOpen => write!(f, "only games on open grids are ranked"),
+            Rejection::InvalidSpeed => write!(f, "the speed must be a positive number"),
+            Rejection::InvalidTimeLimit => {
+                write!(f, "the time limit does not match the ruleset or the speed")
+            }
+            Rejection::TooLong { max } => write!(f, "the game is longer than {max} ticks"),
+            Rejection::Unfinished => write!(f, "the game of the replay is not over"),
+            Rejection::ScoreMismatch { claimed, actual } => {
+                write!(f, "the replay scores {actual}, not {claimed}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+/// Plays the game of `submission` again and returns the table its score goes to, or
+/// why the score cannot be ranked.
+pub fn verify(submission: &Submission) -> Result<ScoreCategory, Rejection> {
+    if submission.name.is_empty() || clean_initials(&submission.name) != submission.name {
+        return Err(Rejection::InvalidName);
+    }
+    let replay = &submission.replay;
+    if replay.versus {
+        return Err(Rejection::Versus);
+    }
+    let level = replay.level();
+    level.validate().map_err(Rejection::InvalidLevel)?;
+    if !level.is_open() {
+        return Err(Rejection::NotOpen);
+    }
+    if !(replay.refresh_rate.is_finite() && replay.refresh_rate > 0.0) {
+        return Err(Rejection::InvalidSpeed);
+    }
+    // The clock counts the moves made at the starting speed, which time-attack games
+    // keep, so the moves of its seconds follow from the pace
+    let time_limit = match (replay.ruleset, replay.time_limit) {
+        (Ruleset::TimeAttack, Some(limit)) => Some(TimeLimit::new(
+            limit.seconds,
+            replay.pace.start_rate(replay.refresh_rate),
+        )),
+        (Ruleset::TimeAttack, None) => return Err(Rejection::InvalidTimeLimit),
+        _ => None,
+    };
+    if replay.time_limit != time_limit || replay.pace != replay.pace.for_ruleset(replay.ruleset) {
+        return Err(Rejection::InvalidTimeLimit);
+    }
+    let max = max_replay_ticks(level.width, level.height);
+    if replay.inputs.len() > max {
+        return Err(Rejection::TooLong { max });
+    }
+
+    let simulation = replay.simulate();
+    if !simulation.game.is_over() {
+        return Err(Rejection::Unfinished);
+    }
+    let actual = simulation.game.score();
+    if actual != submission.score {
+        return Err(Rejection::ScoreMismatch {
+            claimed: submission.score,
+            actual,
+        });
+    }
+    // The speed cannot be checked, the rules do not depend on it
+    Ok(ScoreCategory::of(
+        &simulation.game,
+        replay.pace,
+        replay.refresh_rate,
+    ))
+}
+
+/// The name of `boundary` in queries.
+pub fn boundary_name(boundary: Boundary) -> &'static str {
+    match boundary {
+        Boundary::Walls => "walls",
+        Boundary::Wrap => "wrap",
+    }
+}
+
+pub fn parse_boundary(name: &str) -> Option<Boundary> {
+    match name {
+        "walls" => Some(Boundary::Walls),
+        "wrap" => Some(Boundary::Wrap),
+        _ => None,
+    }
+}
+
+/// The name of `difficulty` in queries.
+pub fn difficulty_name(difficulty: Difficulty) -> &'static str {
+    match difficulty {
+        Difficulty::Easy => "easy",
+        Difficulty::Normal => "normal",
+        Difficulty::Hard => "hard",
+        Difficulty::Insane => "insane",
+    }
+}
+
+pub fn parse_difficulty(name: &str) -> Option<Difficulty> {
+    Difficulty::ALL
+        .into_iter()
+        .find(|difficulty| difficulty_name(*difficulty) == name)
+}
+
+/// The name of `ruleset` in queries.
+pub fn ruleset_name(ruleset: Ruleset) -> &'static str {
+    match ruleset {
+        Ruleset::Standard => "standard",
+        Ruleset::TimeAttack => "time_attack",
+        Ruleset::Survival => "survival",
+        Ruleset::Zen => "zen",
+    }
+}
+
+pub fn parse_ruleset(name: &str) -> Option<Ruleset> {
+    Ruleset::ALL
+        .into_iter()
+        .find(|ruleset| ruleset_name(*ruleset) == name)
+}
+
+#[derive(Debug)]
+pub enum LeaderboardError {
+    /// The server answered with an error.
+    Rejected(String),
+    Transport(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for LeaderboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeaderboardError::Rejected(e) => write!(f, "the server refused the request: {e}"),
+            LeaderboardError::Transport(e) => write!(f, "cannot reach the server: {e}"),
+            LeaderboardError::Io(e) => write!(f, "invalid answer from the server: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LeaderboardError {}
+
+impl From<ureq::Error> for LeaderboardError {
+    fn from(error: ureq::Error) -> Self {
+        match error {
+            ureq::Error::Status(code, response) => {
+                let reason = response
+                    .into_json::<ErrorResponse>()
+                    .map_or_else(|_| format!("status {code}"), |response| response.error);
+                LeaderboardError::Rejected(reason)
+            }
+            ureq::Error::Transport(e) => LeaderboardError::Transport(e.to_string()),
+        }
+    }
+}
+
+/// Sends `submission` to the server at `url` and returns its rank.
+pub fn submit(url: &str, submission: &Submission) -> Result<usize, LeaderboardError> {
+    let submitted: Submitted = ureq::post(&format!("{}/scores", url.trim_end_matches('/')))
+        .send_json(submission)?
+        .into_json()
+        .map_err(LeaderboardError::Io)?;
+    Ok(submitted.rank)
+}
+
+/// The best scores of `category` on the server at `url`.
+pub fn rankings(
+    url: &str,
+    category: &ScoreCategory,
+    limit: usize,
+) -> Result<Vec<Ranking>, LeaderboardError> {
+    ureq::get(&format!("{}/scores", url.trim_end_matches('/')))
+        .query("boundary", boundary_name(category.boundary))
+        .query("width", &category.width.to_string())
+        .query("height", &category.height.to_string())
+        .query("difficulty", difficulty_name(category.pace.difficulty))
+        .query("accelerate", &category.pace.accelerate.to_string())
+        .query("power_ups", &category.power_ups.to_string())
+        .query("ruleset", ruleset_name(category.ruleset))
+        .query("time_limit", &category.time_limit.to_string())
+        .query("refresh_rate", &category.refresh_rate.to_string())
+        .query("limit", &limit.to_string())
+        .call()?
+        .into_json()
+        .map_err(LeaderboardError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::BotKind;
+    use crate::config::MAX_GRID_SIZE;
+    use crate::level::Level;
+    use crate::rules::SnakeDirection;
+
+    /// A finished game of the BFS bot on `level`, with its actual score.
+    fn submission(level: Level) -> Submission {
+        let mut bots = [BotKind::Bfs.create()];
+        let replay = Replay::from_bots(11, level, Boundary::Walls, &mut bots, 10_000);
+        Submission {
+            name: "BOT".to_string(),
+            score: replay.simulate().game.score(),
+            replay,
+        }
+    }
+
+    fn open() -> Level {
+        Level::open(8, 8, SnakeDirection::Up)
+    }
+
+    #[test]
+    fn accepts_genuine_scores() {
+        let submission = submission(open());
+        assert!(submission.score > 0);
+        let category = verify(&submission).unwrap();
+        assert_eq!((category.width, category.height), (8, 8));
+        assert_eq!(category.boundary, Boundary::Walls);
+    }
+
+    #[test]
+    fn rejects_forged_scores() {
+        let mut forged = submission(open());
+        forged.score += 1;
+        assert!(matches!(
+            verify(&forged),
+            Err(Rejection::ScoreMismatch { claimed, actual }) if claimed == actual + 1
+        ));
+
+        let mut unfinished = submission(open());
+        unfinished.replay.inputs.pop();
+        assert!(matches!(verify(&unfinished), Err(Rejection::Unfinished)));
+
+        let mut renamed = submission(open());
+        renamed.name = "bot".to_string();
+        assert!(matches!(verify(&renamed), Err(Rejection::InvalidName)));
+    }
+
+    #[test]
+    fn only_ranks_open_grids() {
+        let level: Level = "........\n.....#..\n..^.....\n........\n".parse().unwrap();
+        assert!(matches!(
+            verify(&submission(level)),
+            Err(Rejection::NotOpen)
+        ));
+    }
+
+    #[test]
+    fn rejects_forged_time_limits() {
+        let mut clocked = submission(open());
+        clocked.replay.time_limit = Some(TimeLimit::new(60, clocked.replay.refresh_rate));
+        assert!(matches!(verify(&clocked), Err(Rejection::InvalidTimeLimit)));
+
+        let mut unclocked = submission(open());
+        unclocked.replay.ruleset = Ruleset::TimeAttack;
+        assert!(matches!(
+            verify(&unclocked),
+            Err(Rejection::InvalidTimeLimit)
+        ));
+
+        let mut longer = unclocked.clone();
+        let rate = longer.replay.pace.start_rate(longer.replay.refresh_rate);
+        let limit = TimeLimit::new(60, rate);
+        longer.replay.time_limit = Some(TimeLimit {
+            ticks: limit.ticks * 2,
+            ..limit
+        });
+        assert!(matches!(verify(&longer), Err(Rejection::InvalidTimeLimit)));
+    }
+
+    #[test]
+    fn rejects_replays_longer_than_their_grid_allows() {
+        let mut long = submission(open());
+        long.replay.inputs.resize(max_replay_ticks(8, 8) + 1, None);
+        assert!(matches!(
+            verify(&long),
+            Err(Rejection::TooLong { max: 4096 })
+        ));
+    }
+
+    #[test]
+    fn rejects_costly_replays_before_playing_them() {
+        // A snake going round in circles on the largest grid for as long as any game
+        let level = Level::open(MAX_GRID_SIZE, MAX_GRID_SIZE, SnakeDirection::Up);
+        let mut replay = Replay::from_bots(11, level, Boundary::Walls, &mut [], 0);
+        let circle = [
+            SnakeDirection::Right,
+            SnakeDirection::Up,
+            SnakeDirection::Left,
+            SnakeDirection::Down,
+        ];
+        replay.inputs = circle.map(Some).repeat(MAX_REPLAY_TICKS / circle.len());
+        let costly = Submission {
+            name: "BOT".to_string(),
+            score: 0,
+            replay,
+        };
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            verify(&costly),
+            Err(Rejection::TooLong { max: 20_000 })
+        ));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,627 @@
+//! Levels: the size of the grid, its obstacles and where the snake starts.
+//!
+//! Levels are written as ASCII maps, one character per cell and the top row first:
+//!
+//! ```text
+//! ; Lines starting with ';' are comments
+//! name: Cross
+//! .........
+//! ....#....
+//! ....#....
+//! .###^###.
+//! ....#....
+//! .*..#..*.
+//! .........
+//! ```
+//!
+//! - `.` is an empty cell and `#` an obstacle.
+//! - `^`, `v`, `<` and `>` are the start of the snake, heading up, down, left or right,
+//!   never towards an obstacle nor the edge of the grid.
+//! - `*` is an apple spawn. When a level has some, apples only appear on them.
+//! - A digit is one end of a portal, the other end being the other cell with the same
+//!   digit. The snake entering one end comes out of the other.
+
+use crate::config::MAX_GRID_SIZE;
+use crate::rules::{Position, SnakeDirection};
+use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadedFolder};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::any::TypeId;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Level {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub obstacles: BTreeSet<Position>,
+    pub start: Position,
+    pub direction: SnakeDirection,
+    pub apple_spawns: BTreeSet<Position>,
+    /// The two ends of each portal, numbered by their index.
+    pub portals: Vec<[Position; 2]>,
+}
+
+/// A level has at most one portal per digit.
+pub const MAX_PORTALS: usize = 10;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LevelError {
+    Empty,
+    UnevenRows { line: usize },
+    UnknownCharacter { line: usize, character: char },
+    UnknownKey { line: usize, key: String },
+    Size { width: usize, height: usize },
+    NoStart,
+    MultipleStarts { line: usize },
+    BlockedStart,
+    NoFreeCell,
+    UnpairedPortal { digit: char },
+    TooManyPortals,
+    NoRivalStart,
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Empty => write!(f, "the level has no map"),
+            LevelError::UnevenRows { line } => {
+                write!(
+                    f,
+                    "line {line}: all the rows of the map must have the same length"
+                )
+            }
+            LevelError::UnknownCharacter { line, character } => {
+                write!(f, "line {line}: unknown character {character:?}")
+            }
+            LevelError::UnknownKey { line, key } => write!(f, "line {line}: unknown key {key:?}"),
+            LevelError::Size { width, height } => write!(
+                f,
+                "the map is {width}x{height}, its sides must be between 2 and {MAX_GRID_SIZE}"
+            ),
+            LevelError::NoStart => write!(f, "the map has no start (one of ^, v, < or >)"),
+            LevelError::MultipleStarts { line } => {
+                write!(f, "line {line}: the map can only have one start")
+            }
+            LevelError::BlockedStart => {
+                write!(f, "the snake starts in front of an obstacle or of the edge")
+            }
+            LevelError::NoFreeCell => write!(f, "there is no free cell for the apples"),
+            LevelError::UnpairedPortal { digit } => {
+                write!(f, "portal {digit} must have exactly two ends")
+            }
+            LevelError::TooManyPortals => {
+                write!(f, "the level cannot have more than {MAX_PORTALS} portals")
+            }
+            LevelError::NoRivalStart => {
+                write!(f, "there is no room for a second snake opposite the start")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl Level {
+    /// An empty grid with the snake in the middle.
+    pub fn open(width: usize, height: usize, direction: SnakeDirection) -> Self {
+        Level {
+            name: "Open".to_string(),
+            width,
+            height,
+            obstacles: BTreeSet::new(),
+            start: Position::new(width as i32 / 2, height as i32 / 2),
+            direction,
+            apple_spawns: BTreeSet::new(),
+            portals: vec![],
+        }
+    }
+
+    /// An empty grid with room for two snakes, the first one starting on the left.
+    pub fn open_versus(width: usize, height: usize, direction: SnakeDirection) -> Self {
+        Level {
+            start: Position::new(width as i32 / 4, height as i32 / 2),
+            ..Level::open(width, height, direction)
+        }
+    }
+
+    /// Whether the level is the empty grid [`Level::open`] makes.
+    pub fn is_open(&self) -> bool {
+        *self == Level::open(self.width, self.height, self.direction)
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        (0..self.width as i32).contains(&position.x)
+            && (0..self.height as i32).contains(&position.y)
+    }
+
+    /// All the cells of the grid, row by row from the bottom-left one.
+    pub fn cells(&self) -> impl Iterator<Item = Position> {
+        let width = self.width as i32;
+        (0..self.height as i32).flat_map(move |y| (0..width).map(move |x| Position::new(x, y)))
+    }
+
+    /// The index of the portal with an end at `position`.
+    pub fn portal_at(&self, position: Position) -> Option<usize> {
+        self.portals
+            .iter()
+            .position(|ends| ends.contains(&position))
+    }
+
+    /// Where the snake comes out when it enters a portal at `position`.
+    pub fn portal_exit(&self, position: Position) -> Option<Position> {
+        self.portals.iter().find_map(|[a, b]| match position {
+            p if p == *a => Some(*b),
+            p if p == *b => Some(*a),
+            _ => None,
+        })
+    }
+
+    /// Checks that the level can be played. A level is played with either boundary, so
+    /// it must be valid with walls: a start facing the edge is rejected even though the
+    /// snake would wrap around it.
+    pub fn validate(&self) -> Result<(), LevelError> {
+        let sizes = 2..=MAX_GRID_SIZE;
+        if !sizes.contains(&self.width) || !sizes.contains(&self.height) {
+            return Err(LevelError::Size {
+                width: self.width,
+                height: self.height,
+            });
+        }
+        let front = self.start.neighbour(self.direction);
+        if !self.contains(front) || self.obstacles.contains(&front) {
+            return Err(LevelError::BlockedStart);
+        }
+        if self.portals.len() > MAX_PORTALS {
+            return Err(LevelError::TooManyPortals);
+        }
+        if self.obstacles.len() + self.portals.len() * 2 + 1 >= self.width * self.height {
+            return Err(LevelError::NoFreeCell);
+        }
+        Ok(())
+    }
+
+    /// Checks that the level can be played by two snakes.
+    pub fn validate_versus(&self) -> Result<(), LevelError> {
+        self.validate()?;
+        self.rival_start().ok_or(LevelError::NoRivalStart)?;
+        Ok(())
+    }
+
+    /// Where the second snake of versus games starts: the cell opposite to the start
+    /// through the center of the grid, heading the other way.
+    pub fn rival_start(&self) -> Option<(Position, SnakeDirection)> {
+        let start = Position::new(
+            self.width as i32 - 1 - self.start.x,
+            self.height as i32 - 1 - self.start.y,
+        );
+        let direction = self.direction.opposite();
+        let front = start.neighbour(direction);
+        let is_open = |position: Position| {
+            self.contains(position)
+                && position != self.start
+                && !self.obstacles.contains(&position)
+                && self.portal_at(position).is_none()
+        };
+        // The snakes must not meet on their first move
+        let clear = front != self.start.neighbour(self.direction);
+        (is_open(start) && is_open(front) && clear).then_some((start, direction))
+    }
+
+    fn cell(&self, position: Position) -> char {
+        if position == self.start {
+            match self.direction {
+                SnakeDirection::Up => '^',
+                SnakeDirection::Down => 'v',
+                SnakeDirection::Left => '<',
+                SnakeDirection::Right => '>',
+            }
+        } else if self.obstacles.contains(&position) {
+            '#'
+        } else if self.apple_spawns.contains(&position) {
+            '*'
+        } else if let Some(index) = self.portal_at(position) {
+            char::from_digit(index as u32, 10).unwrap_or('.')
+        } else {
+            '.'
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = LevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut name = String::new();
+        let mut rows: Vec<(usize, &str)> = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            match line.split_once(':') {
+                Some((key, value)) if rows.is_empty() => match key.trim() {
+                    "name" => name = value.trim().to_string(),
+                    key => {
+                        return Err(LevelError::UnknownKey {
+                            line: line_number,
+                            key: key.to_string(),
+                        })
+                    }
+                },
+                _ => rows.push((line_number, line)),
+            }
+        }
+
+        let Some((_, first_row)) = rows.first() else {
+            return Err(LevelError::Empty);
+        };
+        let width = first_row.chars().count();
+        let height = rows.len();
+        let mut obstacles = BTreeSet::new();
+        let mut apple_spawns = BTreeSet::new();
+        let mut portal_ends: BTreeMap<char, Vec<Position>> = BTreeMap::new();
+        let mut start = None;
+        for (row, (line, text)) in rows.iter().enumerate() {
+            if text.chars().count() != width {
+                return Err(LevelError::UnevenRows { line: *line });
+            }
+            let y = (height - 1 - row) as i32;
+            for (x, character) in text.chars().enumerate() {
+                let position = Position::new(x as i32, y);
+                let direction = match character {
+                    '.' => None,
+                    '#' => {
+                        obstacles.insert(position);
+                        None
+                    }
+                    '*' => {
+                        apple_spawns.insert(position);
+                        None
+                    }
+                    '0'..='9' => {
+                        portal_ends.entry(character).or_default().push(position);
+                        None
+                    }
+                    '^' => Some(SnakeDirection::Up),
+                    'v' => Some(SnakeDirection::Down),
+                    '<' => Some(SnakeDirection::Left),
+                    '>' => Some(SnakeDirection::Right),
+                    character => {
+                        return Err(LevelError::UnknownCharacter {
+                            line: *line,
+                            character,
+                        })
+                    }
+                };
+                if let Some(direction) = direction {
+                    if start.replace((position, direction)).is_some() {
+                        return Err(LevelError::MultipleStarts { line: *line });
+                    }
+                }
+            }
+        }
+        let Some((start, direction)) = start else {
+            return Err(LevelError::NoStart);
+        };
+        let portals = portal_ends
+            .into_iter()
+            .map(|(digit, ends)| match ends[..] {
+                [a, b] => Ok([a, b]),
+                _ => Err(LevelError::UnpairedPortal { digit }),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let level = Level {
+            name,
+            width,
+            height,
+            obstacles,
+            start,
+            direction,
+            apple_spawns,
+            portals,
+        };
+        level.validate()?;
+        Ok(level)
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.name.is_empty() {
+            writeln!(f, "name: {}", self.name)?;
+        }
+        for y in (0..self.height as i32).rev() {
+            let row: String = (0..self.width as i32)
+                .map(|x| self.cell(Position::new(x, y)))
+                .collect();
+            writeln!(f, "{row}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Levels are stored in their ASCII form, in replays for instance.
+impl Serialize for Level {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Level {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[derive(Asset, TypePath, Deref)]
+pub struct LevelAsset(pub Level);
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+#[derive(Debug)]
+pub enum LevelLoaderError {
+    Io(std::io::Error),
+    Level(LevelError),
+}
+
+impl fmt::Display for LevelLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelLoaderError::Io(e) => write!(f, "cannot read the level: {e}"),
+            LevelLoaderError::Level(e) => write!(f, "invalid level: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LevelLoaderError {}
+
+impl AssetLoader for LevelLoader {
+    type Asset = LevelAsset;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut text = String::new();
+            reader
+                .read_to_string(&mut text)
+                .await
+                .map_err(LevelLoaderError::Io)?;
+            text.parse()
+                .map(LevelAsset)
+                .map_err(LevelLoaderError::Level)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
+
+/// The folder of the levels made with the editor.
+pub fn custom_levels_dir() -> PathBuf {
+    crate::data_dir().join("levels")
+}
+
+impl Level {
+    /// The file of the level in [`custom_levels_dir`], named after the level.
+    pub fn custom_path(&self) -> PathBuf {
+        let stem: String = self
+            .name
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        custom_levels_dir().join(format!("{stem}.level"))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_string())
+    }
+}
+
+/// The levels of the `levels` folder of the assets, followed by the custom ones.
+#[derive(Resource)]
+pub struct Levels {
+    pub folder: Handle<LoadedFolder>,
+    pub custom: Vec<Handle<LevelAsset>>,
+}
+
+impl Levels {
+    /// Returns the levels that loaded successfully, sorted by file name.
+    pub fn loaded(
+        &self,
+        folders: &Assets<LoadedFolder>,
+        levels: &Assets<LevelAsset>,
+    ) -> Vec<(AssetId<LevelAsset>, String)> {
+        let mut handles: Vec<Handle<LevelAsset>> = folders
+            .get(&self.folder)
+            .map(|folder| {
+                folder
+                    .handles
+                    .iter()
+                    .filter(|handle| handle.type_id() == TypeId::of::<LevelAsset>())
+                    .map(|handle| handle.clone().typed())
+                    .collect()
+            })
+            .unwrap_or_default();
+        handles.sort_by_key(|handle| handle.path().map(|path| path.to_string()));
+        handles
+            .iter()
+            .chain(&self.custom)
+            .filter_map(|handle| {
+                let level = levels.get(handle)?;
+                Some((handle.id(), level.name.clone()))
+            })
+            .collect()
+    }
+
+    pub fn is_custom(&self, id: AssetId<LevelAsset>) -> bool {
+        self.custom.iter().any(|handle| handle.id() == id)
+    }
+
+    /// Adds a custom level, replacing the one with the same name.
+    pub fn save_custom(&mut self, level: Level, levels: &mut Assets<LevelAsset>) {
+        let existing = self
+            .custom
+            .iter()
+            .find(|handle| levels.get(*handle).is_some_and(|l| l.name == level.name));
+        match existing {
+            Some(handle) => levels.insert(handle.id(), LevelAsset(level)),
+            None => self.custom.push(levels.add(LevelAsset(level))),
+        }
+    }
+}
+
+/// Loads the levels of the assets in the background and the custom ones right away.
+pub fn load_levels(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut level_assets: ResMut<Assets<LevelAsset>>,
+) {
+    let mut paths: Vec<PathBuf> = fs::read_dir(custom_levels_dir())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "level"))
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    let custom = paths
+        .iter()
+        .filter_map(|path| {
+            let text = fs::read_to_string(path)
+                .map_err(|e| error!("Cannot read {}: {e}", path.display()))
+                .ok()?;
+            let level: Level = text
+                .parse()
+                .map_err(|e| error!("Invalid level {}: {e}", path.display()))
+                .ok()?;
+            Some(level_assets.add(LevelAsset(level)))
+        })
+        .collect();
+    commands.insert_resource(Levels {
+        folder: asset_server.load_folder("levels"),
+        custom,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CROSS: &str = "\
+; A comment
+name: Cross
+1....
+...#.
+.#^#.
+..*.1
+";
+
+    #[test]
+    fn parses_maps() {
+        let level: Level = CROSS.parse().unwrap();
+        assert_eq!(level.name, "Cross");
+        assert_eq!((level.width, level.height), (5, 4));
+        assert_eq!(level.start, Position::new(2, 1));
+        assert_eq!(level.direction, SnakeDirection::Up);
+        assert_eq!(
+            level.obstacles,
+            BTreeSet::from([
+                Position::new(3, 2),
+                Position::new(1, 1),
+                Position::new(3, 1)
+            ])
+        );
+        assert_eq!(level.apple_spawns, BTreeSet::from([Position::new(2, 0)]));
+        assert_eq!(level.portals, [[Position::new(0, 3), Position::new(4, 0)]]);
+        assert_eq!(
+            level.portal_exit(Position::new(4, 0)),
+            Some(Position::new(0, 3))
+        );
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let level: Level = CROSS.parse().unwrap();
+        assert_eq!(level.to_string().parse::<Level>(), Ok(level.clone()));
+        let open = Level::open(6, 3, SnakeDirection::Left);
+        assert_eq!(open.to_string().parse::<Level>().unwrap().start, open.start);
+    }
+
+    #[test]
+    fn rejects_invalid_maps() {
+        let error = |map: &str| map.parse::<Level>().unwrap_err();
+        assert_eq!(error("; nothing\n"), LevelError::Empty);
+        assert_eq!(error("...\n.^\n"), LevelError::UnevenRows { line: 2 });
+        assert_eq!(
+            error("..x\n.^.\n"),
+            LevelError::UnknownCharacter {
+                line: 1,
+                character: 'x'
+            }
+        );
+        assert_eq!(
+            error("size: 3\n.^.\n"),
+            LevelError::UnknownKey {
+                line: 1,
+                key: "size".to_string()
+            }
+        );
+        assert_eq!(
+            error(".>..\n"),
+            LevelError::Size {
+                width: 4,
+                height: 1
+            }
+        );
+        assert_eq!(error("...\n...\n"), LevelError::NoStart);
+        assert_eq!(error(".^.\n.v.\n"), LevelError::MultipleStarts { line: 2 });
+        assert_eq!(error("..#\n..>\n"), LevelError::BlockedStart);
+        assert_eq!(error(".#.\n.^.\n"), LevelError::BlockedStart);
+        assert_eq!(error("##\n#>\n"), LevelError::BlockedStart);
+        assert_eq!(
+            error("1.1\n.^1\n"),
+            LevelError::UnpairedPortal { digit: '1' }
+        );
+    }
+
+    #[test]
+    fn valid_levels_can_be_played_with_either_boundary() {
+        use crate::rules::{Boundary, SnakeGame};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        // Wrapping around would do, but the walls would not
+        let facing_the_edge = "...\n..>\n".parse::<Level>();
+        assert_eq!(facing_the_edge.unwrap_err(), LevelError::BlockedStart);
+        let level: Level = "...\n.>.\n".parse().unwrap();
+        for boundary in [Boundary::Walls, Boundary::Wrap] {
+            let mut rng = StdRng::seed_from_u64(0);
+            let mut game = SnakeGame::from_level(level.clone(), &mut rng).with_boundary(boundary);
+            assert!(!game.step(None, &mut rng).is_over());
+        }
+    }
+}
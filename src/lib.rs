@@ -1,13 +1,37 @@
+pub mod audio;
+pub mod bot;
+pub mod cli;
+pub mod config;
 pub mod constants;
+pub mod daily;
+pub mod difficulty;
+pub mod env;
+pub mod gamepad;
+pub mod input;
+pub mod leaderboard;
+pub mod level;
 pub mod logic;
+pub mod music;
+pub mod persist;
+pub mod pickups;
+pub mod replay;
+pub mod rules;
+pub mod save;
+pub mod scores;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod settings;
 pub mod ui;
 
 use bevy::{core::FrameCount, prelude::*, winit::WinitWindows};
+use config::GameConfig;
 use constants::*;
-use logic::{PlayerInput, Scoreboard, SnakeDirection};
+use input::{Action, InputMap};
+use logic::{Player, PlayerInput, ScoreText, Scoreboard, SnakeDirection};
+use std::path::PathBuf;
 use winit::window::Icon;
 
-use crate::ui::game::GameMode;
+use crate::ui::{game::GameMode, menu::Focused, GameState};
 
 #[derive(Deref, DerefMut, Resource)]
 pub struct AlreadyPlayed(pub bool);
@@ -24,68 +48,69 @@ pub struct ButtonHoveredSound(Handle<AudioSource>);
 #[derive(Resource)]
 pub struct ButtonPressedSound(Handle<AudioSource>);
 
-#[derive(Component)]
-pub struct MainMusic;
-
 pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn(Camera2dBundle::default());
-    commands.spawn((
-        AudioBundle {
-            source: asset_server.load("music.ogg"),
-            settings: PlaybackSettings::LOOP,
-        },
-        MainMusic,
-    ));
     commands.insert_resource(AppleSound(asset_server.load("apple.ogg")));
     commands.insert_resource(WallSound(asset_server.load("wall.ogg")));
     commands.insert_resource(ButtonHoveredSound(asset_server.load("hovered.ogg")));
     commands.insert_resource(ButtonPressedSound(asset_server.load("pressed.ogg")));
 }
 
-pub fn update_scoreboard(scoreboard: Res<Scoreboard>, mut query: Query<&mut Text>) {
-    let mut text = query.single_mut();
-    text.sections[1].value = scoreboard.value.to_string();
+pub fn update_scoreboard(
+    scoreboards: Query<(&Player, &Scoreboard)>,
+    mut query: Query<(&ScoreText, &mut Text)>,
+) {
+    for (player, scoreboard) in &scoreboards {
+        for (score_text, mut text) in &mut query {
+            if score_text.0 == player.0 {
+                text.sections[1].value = scoreboard.value.to_string();
+            }
+        }
+    }
 }
 
 pub fn handle_input(
     keyboard_input: Res<Input<KeyCode>>,
-    mut player_input: ResMut<PlayerInput>,
+    input_map: Res<InputMap>,
+    mut players: Query<(&Player, &mut PlayerInput)>,
     current_state: Res<State<GameMode>>,
     mut next_state: ResMut<NextState<GameMode>>,
-    music_controller: Query<&AudioSink, With<MainMusic>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    focused: Query<(), With<Focused>>,
 ) {
-    // Toggle game state
-    if keyboard_input.just_pressed(KeyCode::Space) {
-        let sink = music_controller.get_single().unwrap();
-        let audio_scale_factor = 3.0;
+    // Toggle game state, unless a button of the pause screen was picked with the
+    // keyboard, as the key may be the one pressing it
+    if input_map.just_pressed(Action::Pause, &keyboard_input) {
         if let GameMode::Running = current_state.get() {
             next_state.set(GameMode::Pause);
-            sink.set_volume(sink.volume() / audio_scale_factor);
-        } else {
+        } else if focused.is_empty() {
             next_state.set(GameMode::Running);
-            sink.set_volume(sink.volume() * audio_scale_factor);
         }
     }
 
-    if let GameMode::Running = current_state.get() {
-        let mut direction: Option<SnakeDirection> = None;
-        use SnakeDirection::*;
-
-        if keyboard_input.pressed(KeyCode::Up) || keyboard_input.pressed(KeyCode::P) {
-            direction = Some(Up);
-        }
-        if keyboard_input.pressed(KeyCode::Down) || keyboard_input.pressed(KeyCode::I) {
-            direction = Some(Down);
-        }
-        if keyboard_input.pressed(KeyCode::Left) || keyboard_input.pressed(KeyCode::U) {
-            direction = Some(Left);
-        }
-        if keyboard_input.pressed(KeyCode::Right) || keyboard_input.pressed(KeyCode::E) {
-            direction = Some(Right);
-        }
+    if input_map.just_pressed(Action::Restart, &keyboard_input) {
+        next_state.set(GameMode::Running);
+        game_state.set(GameState::Restart);
+        return;
+    }
 
-        if let Some(direction) = direction {
-            player_input.push(direction);
+    if let GameMode::Running = current_state.get() {
+        // In versus games each player has their own moves, a single player has both
+        let versus = players.iter().len() > 1;
+        for (player, mut player_input) in &mut players {
+            let mut direction: Option<SnakeDirection> = None;
+            let moves = match versus {
+                true => &Action::MOVES[player.0.min(1)..=player.0.min(1)],
+                false => &Action::MOVES[..],
+            };
+            for action in moves.iter().flatten() {
+                if input_map.pressed(*action, &keyboard_input) {
+                    direction = action.direction();
+                }
+            }
+            if let Some(direction) = direction {
+                player_input.push(direction);
+            }
         }
     }
 }
@@ -103,10 +128,15 @@ pub fn set_window_icon(windows: NonSend<WinitWindows>) {
     }
 }
 
-pub fn get_window_resolution() -> (f32, f32) {
+/// The size of a window fitting a `grid_width` x `grid_height` grid and its walls.
+pub fn get_window_resolution(
+    config: &GameConfig,
+    grid_width: usize,
+    grid_height: usize,
+) -> (f32, f32) {
     let (width, height) = (
-        (GRID_WIDTH as f32 + 2.0) * TILE_SIZE.x + WINDOW_PADDING * 2.0,
-        (GRID_HEIGHT as f32 + 2.0) * TILE_SIZE.y + WINDOW_PADDING * 2.0,
+        (grid_width as f32 + 2.0) * config.tile_size.x + WINDOW_PADDING * 2.0,
+        (grid_height as f32 + 2.0) * config.tile_size.y + WINDOW_PADDING * 2.0,
     );
     (width.max(MENU_WIDTH), height.max(MENU_HEIGHT))
 }
@@ -116,3 +146,10 @@ pub fn make_visible(mut window: Query<&mut Window>, frames: Res<FrameCount>) {
         window.single_mut().visible = true;
     }
 }
+
+/// Where the game keeps the files it writes, such as replays.
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("snake")
+}
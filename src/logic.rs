@@ -1,12 +1,27 @@
 #![allow(clippy::type_complexity)]
 #![allow(clippy::too_many_arguments)]
 
+use crate::audio::{AudioMixer, Bus};
+use crate::bot::{BotKind, SnakeBot};
+use crate::config::GameConfig;
+use crate::daily::DailyGame;
+use crate::difficulty::{GamePace, Pace};
+use crate::input::{Action, InputMap};
+use crate::level::{Level, LevelAsset, MAX_PORTALS};
+use crate::pickups::{PickupKind, SLOW_TIME_SCALE};
+use crate::replay::{ReplayPlayer, ReplayRecorder};
+use crate::rules::{Boundary, DeathCause, Position, Ruleset, SnakeGame, StepOutcome, TimeLimit};
+use crate::scores::{HighScores, NewHighScore, ScoreCategory};
+use crate::ui::editor::Playtest;
 use crate::ui::game::OnGameScreen;
 use crate::{constants::*, ui::GameState};
 use crate::{AppleSound, WallSound};
-use bevy::{prelude::*, sprite::collide_aabb::collide};
-use rand::{thread_rng, Rng};
-use std::ops::{Deref, DerefMut};
+use bevy::prelude::*;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::time::Duration;
+
+pub use crate::rules::SnakeDirection;
 
 #[derive(Resource, Deref, DerefMut)]
 pub struct GameTimer(pub Timer);
@@ -15,238 +30,650 @@ pub struct GameTimer(pub Timer);
 pub enum GameEvent {
     GameOver(String),
     GameWon,
+    /// The clock of a time-attack game ran out.
+    TimeUp,
+    /// The player ended a zen game.
+    Finished,
+    /// The snake of a survival game died, for the given reason.
+    Survived(String),
+    /// The end of a versus game, `winner` being `None` for a draw.
+    VersusOver {
+        winner: Option<usize>,
+        why: String,
+    },
 }
 
-#[derive(Debug, Resource)]
+/// The score of a snake.
+#[derive(Debug, Component)]
 pub struct Scoreboard {
     pub value: usize,
 }
 
+/// The score of the snake of a player on the HUD.
 #[derive(Component)]
-pub struct Head;
+pub struct ScoreText(pub usize);
 
-#[derive(Debug, Resource)]
-pub struct SnakeBody {
-    pub body: Vec<Entity>,
-}
+/// The number of moves per second on the HUD.
+#[derive(Component)]
+pub struct SpeedText;
+
+/// The seconds left in time-attack games on the HUD.
+#[derive(Component)]
+pub struct ClockText;
 
-impl Deref for SnakeBody {
-    type Target = Vec<Entity>;
+/// The effects the snakes are under, and the time they have left, on the HUD.
+#[derive(Component)]
+pub struct EffectsText;
+
+/// The index of a snake in [`SnakeGame::snakes`], `0` being the first player.
+#[derive(Clone, Copy, Component, Debug, PartialEq, Eq)]
+pub struct Player(pub usize);
+
+/// The options picked in the menu for the next games.
+#[derive(Clone, Debug, Resource)]
+pub struct GameOptions {
+    pub boundary: Boundary,
+    /// The level to play, an open grid of the configured size if `None`.
+    pub level: Option<AssetId<LevelAsset>>,
+    /// Two players share the keyboard.
+    pub versus: bool,
+    /// The bot playing the snake, or the second snake in versus games.
+    pub bot: Option<BotKind>,
+    pub pace: Pace,
+    /// Pickups are dropped as the apples are eaten.
+    pub power_ups: bool,
+    pub ruleset: Ruleset,
+    /// The seconds time-attack games last.
+    pub time_limit: u32,
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.body
+impl Default for GameOptions {
+    fn default() -> Self {
+        GameOptions {
+            boundary: Boundary::default(),
+            level: None,
+            versus: false,
+            bot: None,
+            pace: Pace::default(),
+            power_ups: false,
+            ruleset: Ruleset::default(),
+            time_limit: TIME_LIMITS[0],
+        }
     }
 }
 
-impl DerefMut for SnakeBody {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.body
+/// The state of the running game. Sprites are only a mirror of it.
+#[derive(Resource, Deref, DerefMut)]
+pub struct Game(pub SnakeGame);
+
+/// The seed the next games are started with. A random seed is drawn for each game if
+/// none is set.
+#[derive(Debug, Default, Resource)]
+pub struct GameSeed(pub Option<u64>);
+
+/// The random number generator of the running game. ChaCha is used rather than `StdRng`
+/// because its output is guaranteed not to change between versions of `rand`, so a
+/// seed always gives the same game.
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng {
+    seed: u64,
+    #[deref]
+    rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
     }
+
+    pub fn from_seed(seed: &GameSeed) -> Self {
+        GameRng::new(seed.0.unwrap_or_else(|| thread_rng().gen()))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+/// What happened to each snake during the last tick, waiting to be handled by
+/// `check_for_collisions`.
+#[derive(Default, Resource, Deref, DerefMut)]
+pub struct LastStep(pub Option<Vec<StepOutcome>>);
+
+#[derive(Component)]
+pub struct Head;
+
+/// The tail segments, in the same order as the body of the snake.
+#[derive(Debug, Default, Component, Deref, DerefMut)]
+pub struct SnakeBody {
+    pub body: Vec<Entity>,
 }
 
-#[derive(Deref, DerefMut, Resource)]
+/// The directions pressed by the player since the last tick.
+#[derive(Default, Deref, DerefMut, Component)]
 pub struct PlayerInput(pub Vec<SnakeDirection>);
 
+/// A bot steering the snake instead of the keyboard.
+#[derive(Component, Deref, DerefMut)]
+pub struct Autopilot(pub Box<dyn SnakeBot>);
+
+/// Marks the snakes a bot played for, whose scores are not best scores.
+#[derive(Component)]
+pub struct Assisted;
+
 #[derive(Component)]
 pub struct Tail;
 
+/// The sprite of an obstacle that appeared during a survival game.
+#[derive(Component)]
+pub struct SpawnedObstacle;
+
 #[derive(Component, Deref, DerefMut)]
 pub struct Movement(pub SnakeDirection);
 
-#[derive(Clone, Copy)]
-pub enum SnakeDirection {
-    Left,
-    Right,
-    Up,
-    Down,
-}
-
 impl From<SnakeDirection> for Vec2 {
     fn from(value: SnakeDirection) -> Self {
-        match value {
-            SnakeDirection::Left => Vec2::new(-1.0, 0.0),
-            SnakeDirection::Right => Vec2::new(1.0, 0.0),
-            SnakeDirection::Up => Vec2::new(0.0, 1.0),
-            SnakeDirection::Down => Vec2::new(0.0, -1.0),
-        }
+        let (x, y) = value.offset();
+        Vec2::new(x as f32, y as f32)
     }
 }
 
-#[derive(Component)]
-pub struct Collider;
+/// The food sprites, placed from the state of the game by `sync_sprites`.
+#[derive(Clone, Copy, Component, Debug, PartialEq, Eq)]
+pub enum Pickup {
+    Apple,
+    /// The pickup of games with power-ups, colored after its kind.
+    PowerUp,
+}
 
-#[derive(Component)]
-pub struct Apple;
+/// Converts a grid cell to its world coordinates, the grid being centered on the origin.
+pub fn cell_to_world(position: Position, level: &Level, config: &GameConfig) -> Vec2 {
+    Vec2::new(
+        (position.x as f32 - (level.width - 1) as f32 / 2.0) * config.tile_size.x,
+        (position.y as f32 - (level.height - 1) as f32 / 2.0) * config.tile_size.y,
+    )
+}
 
-pub fn gen_apple_location() -> Vec2 {
-    let mut rng = thread_rng();
+/// Converts world coordinates to the grid cell they fall in, if any.
+pub fn world_to_cell(world: Vec2, level: &Level, config: &GameConfig) -> Option<Position> {
+    let position = Position::new(
+        (world.x / config.tile_size.x + level.width as f32 / 2.0).floor() as i32,
+        (world.y / config.tile_size.y + level.height as f32 / 2.0).floor() as i32,
+    );
+    level.contains(position).then_some(position)
+}
 
-    let x = (GRID_WIDTH - 1) as f32 / 2.0 * TILE_SIZE.x;
-    let y = (GRID_HEIGHT - 1) as f32 / 2.0 * TILE_SIZE.y;
+pub fn pickup_color(kind: PickupKind) -> Color {
+    match kind {
+        PickupKind::Golden => GOLDEN_APPLE_COLOR,
+        PickupKind::Shrink => SHRINK_PILL_COLOR,
+        PickupKind::Slow => SLOW_TIME_COLOR,
+        PickupKind::Ghost => GHOST_COLOR,
+        PickupKind::Multiplier => MULTIPLIER_COLOR,
+    }
+}
 
-    let mut x = rng.gen_range(-x..=x);
-    let mut y = rng.gen_range(-y..=y);
-    x = (x / TILE_SIZE.x).round() * TILE_SIZE.x;
-    y = (y / TILE_SIZE.y).round() * TILE_SIZE.y;
+/// Both ends of a portal share a color, each portal having its own.
+pub fn portal_color(index: usize) -> Color {
+    Color::hsl(index as f32 * 360.0 / MAX_PORTALS as f32, 0.8, 0.6)
+}
 
-    Vec2::new(x, y)
+/// Starts a game on `level`, which should have been validated for versus games if
+/// `versus` is set.
+pub fn new_game(
+    level: Level,
+    boundary: Boundary,
+    versus: bool,
+    power_ups: bool,
+    ruleset: Ruleset,
+    time_limit: Option<TimeLimit>,
+    rng: &mut GameRng,
+) -> Game {
+    let game = if versus {
+        SnakeGame::versus(level, &mut rng.rng)
+    } else {
+        SnakeGame::from_level(level, &mut rng.rng)
+    };
+    Game(
+        game.with_boundary(boundary)
+            .with_power_ups(power_ups)
+            .with_ruleset(ruleset)
+            .with_time_limit(time_limit),
+    )
+}
+
+pub fn move_snake(
+    time: Res<Time>,
+    mut timer: ResMut<GameTimer>,
+    mut players: Query<(&Player, &mut PlayerInput, Option<&mut Autopilot>)>,
+    mut game: ResMut<Game>,
+    mut rng: ResMut<GameRng>,
+    mut last_step: ResMut<LastStep>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut replay: Option<ResMut<ReplayPlayer>>,
+) {
+    if game.is_over() {
+        return;
+    }
+
+    // Zen games end where the player finished them, after the last recorded input
+    if let Some(player) = replay.as_deref() {
+        if player.is_finished() && player.replay.finished && game.ruleset() == Ruleset::Zen {
+            last_step.0 = Some(game.finish());
+            return;
+        }
+    }
+
+    let tick = match replay.as_deref_mut() {
+        Some(player) if player.paused => std::mem::take(&mut player.step),
+        Some(player) => timer
+            .tick(time.delta().mul_f32(player.speed()))
+            .just_finished(),
+        None => timer.tick(time.delta()).just_finished(),
+    };
+    if !tick {
+        return;
+    }
+
+    // While watching a replay, the recorded inputs replace the players'
+    let mut directions = vec![None; game.snakes().len()];
+    for (player, mut input, autopilot) in &mut players {
+        directions[player.0] = match autopilot {
+            Some(mut autopilot) => autopilot.next_direction(&game, player.0),
+            None => game.snakes()[player.0].pick_direction(&input),
+        };
+        input.clear();
+    }
+    if let Some(player) = replay.as_deref_mut() {
+        directions = player.next_inputs();
+    }
+    if let Some(replay) = recorder.0.as_mut() {
+        replay.push(&directions);
+    }
+    last_step.0 = Some(game.step_all(&directions, &mut rng.rng));
+}
+
+/// Hands the snake of the first player over to a bot when the key of
+/// [`Action::Autopilot`] is pressed, or takes it back.
+pub fn toggle_autopilot(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    input_map: Res<InputMap>,
+    options: Res<GameOptions>,
+    players: Query<(Entity, &Player, Has<Autopilot>)>,
+) {
+    if !input_map.just_pressed(Action::Autopilot, &keyboard_input) {
+        return;
+    }
+    for (entity, player, autopilot) in &players {
+        if player.0 != 0 {
+            continue;
+        }
+        if autopilot {
+            commands.entity(entity).remove::<Autopilot>();
+        } else {
+            let bot = options.bot.unwrap_or_default().create();
+            commands.entity(entity).insert((Autopilot(bot), Assisted));
+        }
+    }
 }
 
 pub fn check_for_collisions(
     mut commands: Commands,
-    mut scoreboard: ResMut<Scoreboard>,
-    mut body: ResMut<SnakeBody>,
+    mut scoreboards: Query<(&Player, &mut Scoreboard)>,
+    mut last_step: ResMut<LastStep>,
+    game: Res<Game>,
     mut game_state: ResMut<NextState<GameState>>,
     mut events: EventWriter<GameEvent>,
-    snake_query: Query<&mut Transform, With<Head>>,
-    collider_query: Query<
-        (Entity, &Transform, Option<&Apple>, Option<&Tail>),
-        (With<Collider>, Without<Head>),
-    >,
-    tail: Query<&Transform, (With<Tail>, Without<Head>)>,
     apple_sound: Res<AppleSound>,
     wall_sound: Res<WallSound>,
+    mixer: Res<AudioMixer>,
+    high_scores: Res<HighScores>,
+    (replay, playtest, daily): (
+        Option<Res<ReplayPlayer>>,
+        Option<Res<Playtest>>,
+        Option<Res<DailyGame>>,
+    ),
+    assisted: Query<(), With<Assisted>>,
+    recorder: Res<ReplayRecorder>,
+    config: Res<GameConfig>,
+    pace: Res<GamePace>,
 ) {
-    let snake_transform = snake_query.single();
-
-    for (collider_entity, transform, maybe_apple, maybe_tail) in &collider_query {
-        let collision = collide(
-            snake_transform.translation,
-            TILE_SIZE,
-            transform.translation,
-            transform.scale.truncate(),
-        );
-        if collision.is_some() {
-            if maybe_apple.is_some() {
-                // Handle collision with an apple
-                commands.entity(collider_entity).despawn();
-                play_collision_sound(&mut commands, apple_sound.0.clone());
-
-                // Spawn a new apple
-                let apple_loc = loop {
-                    let location = gen_apple_location();
-                    if collider_query
-                        .iter()
-                        .all(|query| query.1.translation.truncate() != location)
-                    {
-                        break location;
-                    }
-                };
-                spawn_apple(&mut commands, apple_loc);
+    let Some(outcomes) = last_step.take() else {
+        return;
+    };
+    for (player, mut scoreboard) in &mut scoreboards {
+        scoreboard.value = game.snakes()[player.0].score();
+    }
+    // Watched games, tests of the editor, daily challenges, versus games and bots do not
+    // count, daily challenges having their own records
+    if game.is_over()
+        && replay.is_none()
+        && playtest.is_none()
+        && daily.is_none()
+        && !game.is_versus()
+        && assisted.is_empty()
+    {
+        let category = ScoreCategory::of(&game, pace.0, config.refresh_rate);
+        let local = high_scores.qualifies(&category, game.score());
+        // Only games on open grids are ranked by the server
+        let replay = recorder
+            .0
+            .clone()
+            .filter(|_| config.leaderboard_url.is_some())
+            .filter(|_| game.level().is_open() && game.score() > 0);
+        if local || replay.is_some() {
+            commands.insert_resource(NewHighScore {
+                category,
+                score: game.score(),
+                initials: String::new(),
+                local,
+                replay,
+            });
+        }
+    }
 
-                scoreboard.value += 1;
+    if outcomes
+        .iter()
+        .any(|outcome| matches!(outcome, StepOutcome::Died(_) | StepOutcome::Bumped(_)))
+    {
+        play_collision_sound(&mut commands, wall_sound.0.clone(), &mixer);
+    } else if outcomes.iter().any(|outcome| {
+        matches!(
+            outcome,
+            StepOutcome::AteApple | StepOutcome::Picked(_) | StepOutcome::Won
+        )
+    }) {
+        play_collision_sound(&mut commands, apple_sound.0.clone(), &mixer);
+    }
+    if !game.is_over() {
+        return;
+    }
 
-                let transform = if body.is_empty() {
-                    *snake_transform
-                } else {
-                    let tail_id = body.last().unwrap();
-                    *tail.component::<Transform>(*tail_id)
+    let event = if game.is_versus() {
+        versus_results(&game, &outcomes)
+    } else {
+        match outcomes[0] {
+            StepOutcome::Died(cause) => {
+                let why = match cause {
+                    DeathCause::Wall => "You hit a wall!",
+                    DeathCause::Obstacle => "You hit an obstacle!",
+                    DeathCause::Tail => "You hit your tail!",
+                    DeathCause::Rival | DeathCause::HeadOn => "You hit the other snake!",
                 };
-
-                let new_tail = commands
-                    .spawn((
-                        SpriteBundle {
-                            transform,
-                            sprite: Sprite {
-                                color: TAIL_COLOR,
-                                ..default()
-                            },
-                            ..default()
-                        },
-                        Tail,
-                        Collider,
-                        Movement(INITIAL_SNAKE_DIRECTION),
-                        OnGameScreen,
-                    ))
-                    .id();
-
-                body.push(new_tail);
-
-                if body.len() == GRID_HEIGHT * GRID_WIDTH - 1 {
-                    events.send(GameEvent::GameWon);
-                    game_state.set(GameState::Results);
-                }
-            } else if maybe_tail.is_some() {
-                if body.len() > 1 {
-                    // Collision with tail
-                    play_collision_sound(&mut commands, wall_sound.0.clone());
-                    events.send(GameEvent::GameOver("You hit your tail!".into()));
-                    game_state.set(GameState::Results);
+                match game.ruleset() {
+                    Ruleset::Survival => GameEvent::Survived(why.into()),
+                    _ => GameEvent::GameOver(why.into()),
                 }
-            } else {
-                // Collision with a wall
-                play_collision_sound(&mut commands, wall_sound.0.clone());
-                game_state.set(GameState::Results);
-                events.send(GameEvent::GameOver("You hit a wall!".into()));
             }
+            StepOutcome::TimeUp => GameEvent::TimeUp,
+            StepOutcome::Finished => GameEvent::Finished,
+            _ => GameEvent::GameWon,
         }
+    };
+    events.send(event);
+    game_state.set(GameState::Results);
+}
+
+/// The time between two moves, from the pace of the game and the apples eaten by the
+/// best snake so far.
+pub fn tick_interval(game: &SnakeGame, pace: Pace, config: &GameConfig) -> f32 {
+    let apples = game.snakes().iter().map(|snake| snake.apples()).max();
+    let interval = pace.tick_interval(config, apples.unwrap_or(0));
+    if game.is_slowed() {
+        interval * SLOW_TIME_SCALE
+    } else {
+        interval
     }
 }
 
-pub fn move_snake(
-    time: Res<Time>,
+/// Sets the time between two moves from the pace of the game and the apples eaten by
+/// the best snake so far, and shows the resulting speed.
+pub fn update_speed(
+    game: Res<Game>,
+    pace: Res<GamePace>,
+    config: Res<GameConfig>,
     mut timer: ResMut<GameTimer>,
-    mut player_input: ResMut<PlayerInput>,
-    body: Res<SnakeBody>,
-    mut head: Query<(&mut Transform, &mut Movement), With<Head>>,
-    mut tail: Query<(&mut Transform, &Movement), (With<Tail>, Without<Head>)>,
+    mut speed_text: Query<&mut Text, With<SpeedText>>,
 ) {
-    let (mut snake_transform, mut snake_velocity) = head.single_mut();
-
-    if timer.tick(time.delta()).just_finished() {
-        use SnakeDirection::*;
-        for next_direction in player_input.iter().rev() {
-            match (snake_velocity.0, next_direction) {
-                (Left, Right) | (Right, Left) | (Up, Down) | (Down, Up) => continue,
-                _ => {
-                    snake_velocity.0 = *next_direction;
-                    break;
-                }
+    let interval = tick_interval(&game, pace.0, &config);
+    if timer.duration().as_secs_f32() != interval {
+        timer.set_duration(Duration::from_secs_f32(interval));
+    }
+    if let Ok(mut text) = speed_text.get_single_mut() {
+        text.sections[1].value = format!("{:.1}", 1.0 / interval);
+    }
+}
+
+/// Shows the seconds left before the clock of a time-attack game runs out, at the
+/// current speed.
+pub fn update_clock(
+    game: Res<Game>,
+    timer: Res<GameTimer>,
+    mut clock_text: Query<&mut Text, With<ClockText>>,
+) {
+    let (Ok(mut text), Some(ticks_left)) = (clock_text.get_single_mut(), game.ticks_left()) else {
+        return;
+    };
+    let seconds = ticks_left as f32 * timer.duration().as_secs_f32();
+    text.sections[1].value = format!("{seconds:.1}");
+}
+
+/// Shows the effects of each snake with the seconds they have left at the current
+/// speed, the player being named in versus games.
+pub fn update_effects(
+    game: Res<Game>,
+    timer: Res<GameTimer>,
+    mut effects_text: Query<&mut Text, With<EffectsText>>,
+) {
+    let Ok(mut text) = effects_text.get_single_mut() else {
+        return;
+    };
+    let interval = timer.duration().as_secs_f32();
+    let mut effects = vec![];
+    for (i, snake) in game.snakes().iter().enumerate() {
+        for active in snake.effects() {
+            let seconds = active.ticks_left as f32 * interval;
+            let name = active.effect.name();
+            effects.push(match game.is_versus() {
+                true => format!("P{} {name} {seconds:.1}", i + 1),
+                false => format!("{name} {seconds:.1}"),
+            });
+        }
+    }
+    text.sections[0].value = effects.join("   ").to_uppercase();
+}
+
+/// The winner of a versus game is the last snake alive, or the one with the best score
+/// when they both filled the grid or the clock ran out.
+fn versus_results(game: &SnakeGame, outcomes: &[StepOutcome]) -> GameEvent {
+    let dead: Vec<usize> = (0..outcomes.len())
+        .filter(|i| matches!(outcomes[*i], StepOutcome::Died(_)))
+        .collect();
+    let (winner, why) = match dead[..] {
+        [] => {
+            let (first, second) = (game.snakes()[0].score(), game.snakes()[1].score());
+            let winner = match first.cmp(&second) {
+                std::cmp::Ordering::Greater => Some(0),
+                std::cmp::Ordering::Less => Some(1),
+                std::cmp::Ordering::Equal => None,
+            };
+            let why = match outcomes[0] {
+                StepOutcome::TimeUp => "Time's up!",
+                StepOutcome::Finished => "Game finished!",
+                _ => "The grid is full!",
+            };
+            (winner, why.to_string())
+        }
+        [loser] => {
+            let what = match outcomes[loser] {
+                StepOutcome::Died(DeathCause::Wall) => "hit a wall",
+                StepOutcome::Died(DeathCause::Obstacle) => "hit an obstacle",
+                StepOutcome::Died(DeathCause::Tail) => "hit their tail",
+                _ => "hit the other snake",
+            };
+            (Some(1 - loser), format!("Player {} {what}!", loser + 1))
+        }
+        _ => (None, "Both snakes crashed!".to_string()),
+    };
+    GameEvent::VersusOver { winner, why }
+}
+
+/// Mirrors the state of the game into the sprites of the snakes and of the food.
+pub fn sync_sprites(
+    mut commands: Commands,
+    game: Res<Game>,
+    config: Res<GameConfig>,
+    mut heads: Query<(&Player, &mut Transform, &mut Movement, &mut SnakeBody), With<Head>>,
+    mut tail: Query<&mut Transform, (With<Tail>, Without<Head>, Without<Pickup>)>,
+    mut pickups: Query<
+        (&Pickup, &mut Transform, &mut Sprite, &mut Visibility),
+        (Without<Head>, Without<Tail>),
+    >,
+) {
+    for (player, mut head_transform, mut movement, mut body) in &mut heads {
+        let snake = &game.snakes()[player.0];
+        head_transform.translation = cell_to_world(snake.head(), game.level(), &config).extend(1.0);
+        movement.0 = snake.direction();
+
+        for (i, position) in snake.body().iter().skip(1).enumerate() {
+            let translation = cell_to_world(*position, game.level(), &config).extend(0.5);
+            if let Some(tail_id) = body.get(i) {
+                *tail.component_mut::<Transform>(*tail_id) =
+                    Transform::from_translation(translation)
+                        .with_scale(config.snake_size.extend(0.0));
+                continue;
             }
+            let tail_id = commands
+                .spawn((
+                    SpriteBundle {
+                        transform: Transform::from_translation(translation)
+                            .with_scale(config.snake_size.extend(0.0)),
+                        sprite: Sprite {
+                            color: config.colors.snake(player.0).1,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    Tail,
+                    OnGameScreen,
+                ))
+                .id();
+            body.push(tail_id);
         }
-        player_input.clear();
-        // For each body segment, set the transform of one segment to match the transform
-        // of the segment above
-        if !body.is_empty() {
-            for i in (0..body.len()).rev() {
-                let next_trans = if i == 0 {
-                    *snake_transform
-                } else {
-                    *tail.component::<Transform>(body[i - 1])
-                };
-                let mut trans = tail.component_mut::<Transform>(body[i]);
-                *trans = next_trans;
+        // Shrink pills and bumps of zen games leave segments behind
+        let len = snake.body().len() - 1;
+        if body.len() > len {
+            for tail_id in body.drain(len..) {
+                commands.entity(tail_id).despawn();
             }
         }
+    }
 
-        let direction: Vec2 = Into::<Vec2>::into(snake_velocity.0);
-        snake_transform.translation.x += direction.x * TILE_SIZE.x;
-        snake_transform.translation.y += direction.y * TILE_SIZE.y;
-        snake_transform.translation.x =
-            (snake_transform.translation.x / TILE_SIZE.x).round() * TILE_SIZE.x;
-        snake_transform.translation.y =
-            (snake_transform.translation.y / TILE_SIZE.y).round() * TILE_SIZE.y;
+    for (pickup, mut transform, mut sprite, mut visibility) in &mut pickups {
+        let shown = match pickup {
+            Pickup::Apple => game.apple().map(|position| (position, config.colors.apple)),
+            // Pickups about to disappear blink
+            Pickup::PowerUp => game
+                .pickup()
+                .filter(|pickup| {
+                    pickup.ticks_left > PICKUP_BLINK_TICKS || pickup.ticks_left % 2 == 0
+                })
+                .map(|pickup| (pickup.position, pickup_color(pickup.kind))),
+        };
+        match shown {
+            Some((position, color)) => {
+                transform.translation = cell_to_world(position, game.level(), &config).extend(0.0);
+                sprite.color = color;
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
     }
 }
 
-pub fn spawn_apple(commands: &mut Commands, loc: Vec2) {
-    commands.spawn((
-        SpriteBundle {
-            transform: Transform {
-                translation: loc.extend(0.0),
-                scale: APPLE_SIZE.extend(0.0),
-                ..default()
-            },
-            sprite: Sprite {
-                color: APPLE_COLOR,
-                ..default()
-            },
+/// Repaints the snakes, the walls and the apple when the colors of the configuration
+/// change, as the settings may while the game is paused.
+pub fn recolor_sprites(
+    config: Res<GameConfig>,
+    mut heads: Query<(&Player, &SnakeBody, &mut Sprite), With<Head>>,
+    mut tail: Query<&mut Sprite, (With<Tail>, Without<Head>)>,
+    mut walls: Query<&mut Sprite, (With<Wall>, Without<Tail>)>,
+    mut pickups: Query<(&Pickup, &mut Sprite), (Without<Tail>, Without<Wall>)>,
+) {
+    for (player, body, mut sprite) in &mut heads {
+        let (head_color, tail_color) = config.colors.snake(player.0);
+        sprite.color = head_color;
+        for tail_id in body.iter() {
+            if let Ok(mut sprite) = tail.get_mut(*tail_id) {
+                sprite.color = tail_color;
+            }
+        }
+    }
+    for mut sprite in &mut walls {
+        // Walls the snake wraps around stay dimmed
+        sprite.color = config.colors.wall.with_a(sprite.color.a());
+    }
+    for (pickup, mut sprite) in &mut pickups {
+        if *pickup == Pickup::Apple {
+            sprite.color = config.colors.apple;
+        }
+    }
+}
+
+/// Adds the sprites of the obstacles that appeared since the last tick of a survival
+/// game.
+pub fn sync_obstacles(
+    mut commands: Commands,
+    game: Res<Game>,
+    config: Res<GameConfig>,
+    spawned: Query<(), With<SpawnedObstacle>>,
+) {
+    for position in game.spawned_obstacles().iter().skip(spawned.iter().len()) {
+        commands.spawn((
+            obstacle_sprite(*position, game.level(), &config),
+            SpawnedObstacle,
+            OnGameScreen,
+        ));
+    }
+}
+
+pub fn obstacle_sprite(
+    position: Position,
+    level: &Level,
+    config: &GameConfig,
+) -> (SpriteBundle, Wall) {
+    let sprite = SpriteBundle {
+        transform: Transform {
+            translation: cell_to_world(position, level, config).extend(0.0),
+            scale: config.tile_size.extend(0.0),
             ..default()
         },
-        Apple,
-        Collider,
-        OnGameScreen,
-    ));
+        sprite: Sprite {
+            color: config.colors.wall,
+            ..default()
+        },
+        ..default()
+    };
+    (sprite, Wall)
+}
+
+/// Spawns the sprites of the apple and of the pickup, hidden until they are placed.
+pub fn spawn_pickups(commands: &mut Commands, config: &GameConfig) {
+    for pickup in [Pickup::Apple, Pickup::PowerUp] {
+        commands.spawn((
+            SpriteBundle {
+                transform: Transform::from_scale(config.apple_size.extend(0.0)),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            pickup,
+            OnGameScreen,
+        ));
+    }
 }
 
 pub enum WallLocation {
@@ -257,23 +684,19 @@ pub enum WallLocation {
 }
 
 impl WallLocation {
-    pub fn size(&self) -> Vec2 {
+    pub fn size(&self, level: &Level, config: &GameConfig) -> Vec2 {
         use WallLocation::*;
+        let tile = config.tile_size;
+        let thickness = config.wall_thickness;
         match self {
-            Top | Bottom => Vec2::new(
-                GRID_WIDTH as f32 * TILE_SIZE.x + TILE_SIZE.x + WALL_THICKNESS,
-                WALL_THICKNESS,
-            ),
-            Left | Right => Vec2::new(
-                WALL_THICKNESS,
-                GRID_HEIGHT as f32 * TILE_SIZE.y + TILE_SIZE.y + WALL_THICKNESS,
-            ),
+            Top | Bottom => Vec2::new(level.width as f32 * tile.x + tile.x + thickness, thickness),
+            Left | Right => Vec2::new(thickness, level.height as f32 * tile.y + tile.y + thickness),
         }
     }
 
-    pub fn position(&self) -> Vec2 {
-        let x = ((GRID_WIDTH + 1) / 2) as f32 * TILE_SIZE.x;
-        let y = ((GRID_HEIGHT + 1) / 2) as f32 * TILE_SIZE.y;
+    pub fn position(&self, level: &Level, config: &GameConfig) -> Vec2 {
+        let x = (level.width + 1) as f32 / 2.0 * config.tile_size.x;
+        let y = (level.height + 1) as f32 / 2.0 * config.tile_size.y;
         match self {
             WallLocation::Top => Vec2::new(0.0, y),
             WallLocation::Bottom => Vec2::new(0.0, -y),
@@ -283,35 +706,68 @@ impl WallLocation {
     }
 }
 
+/// The walls around the grid and the obstacles, drawn with the color of the walls.
+#[derive(Component)]
+pub struct Wall;
+
 #[derive(Bundle)]
 pub struct WallBundle {
     sprite_bundle: SpriteBundle,
-    collider: Collider,
+    wall: Wall,
 }
 
 impl WallBundle {
-    pub fn new(location: WallLocation) -> Self {
+    /// Walls the snake wraps around are drawn dimmed.
+    pub fn new(
+        location: WallLocation,
+        level: &Level,
+        boundary: Boundary,
+        config: &GameConfig,
+    ) -> Self {
+        let color = match boundary {
+            Boundary::Walls => config.colors.wall,
+            Boundary::Wrap => config.colors.wall.with_a(WRAP_WALL_ALPHA),
+        };
         WallBundle {
             sprite_bundle: SpriteBundle {
                 transform: Transform {
-                    translation: location.position().extend(0.0),
-                    scale: location.size().extend(0.0),
-                    ..default()
-                },
-                sprite: Sprite {
-                    color: WALL_COLOR,
+                    translation: location.position(level, config).extend(0.0),
+                    scale: location.size(level, config).extend(0.0),
                     ..default()
                 },
+                sprite: Sprite { color, ..default() },
                 ..default()
             },
-            collider: Collider,
+            wall: Wall,
         }
     }
 }
 
-fn play_collision_sound(commands: &mut Commands, source: Handle<AudioSource>) {
-    commands.spawn(AudioBundle {
-        source,
-        settings: PlaybackSettings::DESPAWN,
-    });
+fn play_collision_sound(commands: &mut Commands, source: Handle<AudioSource>, mixer: &AudioMixer) {
+    mixer.play(commands, source, Bus::Sfx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::Difficulty;
+
+    #[test]
+    fn accelerates_with_the_apples_rather_than_the_points() {
+        let config = GameConfig {
+            speed_up_every: 1,
+            ..default()
+        };
+        let pace = Pace {
+            difficulty: Difficulty::Normal,
+            accelerate: true,
+        };
+        let level = ".*...\n.^...\n.....\n".parse().unwrap();
+        let mut rng = GameRng::new(0);
+        let mut game = SnakeGame::from_level(level, &mut *rng).with_ruleset(Ruleset::Survival);
+        let start = tick_interval(&game, pace, &config);
+        game.step(None, &mut *rng);
+        assert_eq!(game.score(), 0);
+        assert!(tick_interval(&game, pace, &config) < start);
+    }
 }
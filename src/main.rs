@@ -1,49 +1,201 @@
-use bevy::{prelude::*, window::WindowTheme};
-use snake::constants::*;
+use bevy::{
+    prelude::*,
+    window::{WindowMode, WindowTheme},
+};
+use clap::Parser;
+use snake::audio::{duck_music, toggle_mute, AudioMixer};
+use snake::cli::Cli;
+use snake::config::GameConfig;
+use snake::constants::INITIAL_SNAKE_DIRECTION;
+use snake::daily::load_daily_bests;
+use snake::gamepad::{assign_gamepads, GamepadPlayers};
+use snake::input::{load_input_map, quit};
+use snake::level::{load_levels, Level, LevelAsset, LevelLoader};
 use snake::logic::*;
+use snake::music::{play_music, play_stingers, Playlist};
+use snake::replay::{LastReplay, Replay, ReplayPlayer, ReplayRecorder};
+use snake::rules::Boundary;
+use snake::save::load_saved_game;
+use snake::scores::{load_high_scores, NewHighScore};
+use snake::settings::{apply_settings, settings_path, BaseConfig, Settings};
 use snake::ui::results::ResultsTimer;
 use snake::ui::*;
 use snake::*;
+use std::fmt::Display;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: "Snake".to_string(),
-                resizable: false,
-                resolution: get_window_resolution().into(),
-                window_theme: Some(WindowTheme::Dark),
-                enabled_buttons: bevy::window::EnabledButtons {
-                    maximize: false,
-                    ..default()
-                },
-                visible: false,
+    let cli = Cli::parse();
+
+    let mut config = match &cli.config {
+        Some(path) => GameConfig::load(path),
+        None => GameConfig::load_or_default(GameConfig::default_path()),
+    }
+    .unwrap_or_else(|e| exit_with_error(e));
+    let settings = Settings::load_or_recover(settings_path());
+    let mut base_config = config.clone();
+    cli.apply(&mut base_config);
+    settings.apply(&mut config);
+    cli.apply(&mut config);
+
+    if let Err(e) = config.validate() {
+        exit_with_error(e);
+    }
+    let playlist =
+        Playlist::load_or_default(Playlist::path()).unwrap_or_else(|e| exit_with_error(e));
+    let replay = cli.replay.as_ref().map(|path| {
+        let replay = Replay::load(path)
+            .unwrap_or_else(|e| exit_with_error(format!("{}: {e}", path.display())));
+        if let Err(e) = replay.validate() {
+            exit_with_error(format!("{}: {e}", path.display()));
+        }
+        replay
+    });
+
+    // Without a window, the bot plays a whole game on an open grid first
+    let replay = match (cli.headless, cli.bot) {
+        (true, Some(kind)) => {
+            let level = Level::open(
+                config.grid_width,
+                config.grid_height,
+                INITIAL_SNAKE_DIRECTION,
+            );
+            if let Err(e) = level.validate() {
+                exit_with_error(e);
+            }
+            let seed = GameRng::from_seed(&GameSeed(cli.seed)).seed();
+            // Enough for the bot to eat every apple the long way round
+            let cells = level.width * level.height;
+            let replay = Replay::from_bots(
+                seed,
+                level,
+                Boundary::Walls,
+                &mut [kind.create()],
+                cells * cells,
+            );
+            Some(replay)
+        }
+        _ => replay,
+    };
+
+    if let (true, Some(replay)) = (cli.headless, &replay) {
+        let simulation = replay.simulate();
+        println!("seed: {}", replay.seed);
+        println!("ticks: {}/{}", simulation.ticks, replay.inputs.len());
+        let scores: Vec<String> = simulation
+            .game
+            .snakes()
+            .iter()
+            .map(|snake| snake.score().to_string())
+            .collect();
+        println!("score: {}", scores.join(" "));
+        if simulation.game.is_over() {
+            println!("outcome: {:?}", simulation.outcomes);
+        } else {
+            println!("outcome: unfinished");
+        }
+        return;
+    }
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            title: "Snake".to_string(),
+            resizable: false,
+            resolution:
+                get_window_resolution(&config, config.grid_width, config.grid_height).into(),
+            mode: if cli.fullscreen {
+                WindowMode::BorderlessFullscreen
+            } else {
+                settings.window_mode()
+            },
+            present_mode: settings.present_mode(),
+            window_theme: Some(WindowTheme::Dark),
+            enabled_buttons: bevy::window::EnabledButtons {
+                maximize: false,
                 ..default()
-            }),
+            },
+            visible: false,
             ..default()
-        }))
-        .add_plugins((
-            splash::SplashPlugin,
-            menu::MenuPlugin,
-            game::GamePlugin,
-            results::ResultsPlugin,
-        ))
-        .add_state::<GameState>()
-        .add_event::<GameEvent>()
-        .insert_resource(AlreadyPlayed(false))
-        .insert_resource(Scoreboard { value: 0 })
-        .insert_resource(PlayerInput(vec![]))
-        .insert_resource(SnakeBody { body: vec![] })
-        .insert_resource(ClearColor(BACKGROUND_COLOR))
-        .insert_resource(GameTimer(Timer::from_seconds(
-            1.0 / REFRESH_RATE,
-            TimerMode::Repeating,
-        )))
-        .insert_resource(ResultsTimer(Timer::from_seconds(
-            RESULTS_SCREEN_DURATION,
-            TimerMode::Repeating,
-        )))
-        .add_systems(Startup, (set_window_icon, setup).chain())
-        .add_systems(Update, (make_visible, bevy::window::close_on_esc))
-        .run();
+        }),
+        ..default()
+    }))
+    .add_plugins((
+        splash::SplashPlugin,
+        menu::MenuPlugin,
+        game::GamePlugin,
+        results::ResultsPlugin,
+        editor::EditorPlugin,
+    ))
+    .add_state::<GameState>()
+    .add_event::<GameEvent>()
+    .insert_resource(AlreadyPlayed(false))
+    .insert_resource(AudioMixer::new(&settings, cli.mute))
+    .insert_resource(LastStep::default())
+    .insert_resource(GamepadPlayers::default())
+    .insert_resource(GameSeed(cli.seed))
+    .insert_resource(GameOptions {
+        bot: cli.bot,
+        pace: cli.pace(),
+        ..default()
+    })
+    .insert_resource(ReplayRecorder::default())
+    .insert_resource(LastReplay::default())
+    .insert_resource(ClearColor(config.colors.background))
+    .insert_resource(GameTimer(Timer::from_seconds(
+        1.0 / config.refresh_rate,
+        TimerMode::Repeating,
+    )))
+    .insert_resource(ResultsTimer(Timer::from_seconds(
+        config.results_screen_duration,
+        TimerMode::Repeating,
+    )))
+    .insert_resource(config)
+    .insert_resource(BaseConfig(base_config))
+    .insert_resource(settings)
+    .insert_resource(playlist)
+    .init_asset::<LevelAsset>()
+    .init_asset_loader::<LevelLoader>()
+    .add_systems(
+        Startup,
+        (
+            set_window_icon,
+            setup,
+            load_levels,
+            load_high_scores,
+            load_daily_bests,
+            load_input_map,
+            load_saved_game,
+        )
+            .chain(),
+    )
+    .add_systems(
+        Update,
+        (
+            make_visible,
+            assign_gamepads,
+            apply_settings.run_if(resource_changed::<Settings>()),
+            (duck_music, play_music).chain(),
+            play_stingers,
+            // The key of the action being rebound is not a quit, and the quit key skips
+            // the initials of a new high score
+            quit.run_if(
+                not(resource_exists::<menu::Rebinding>())
+                    .and_then(not(resource_exists::<NewHighScore>())),
+            ),
+            // Nor a mute, and neither are the letters of initials
+            toggle_mute.run_if(
+                not(resource_exists::<menu::Rebinding>())
+                    .and_then(not(resource_exists::<NewHighScore>())),
+            ),
+        ),
+    );
+    if let Some(replay) = replay {
+        app.insert_resource(ReplayPlayer::new(replay));
+    }
+    app.run();
+}
+
+fn exit_with_error(e: impl Display) -> ! {
+    eprintln!("error: {e}");
+    std::process::exit(1);
 }
@@ -0,0 +1,280 @@
+//! The music, which follows what is happening.
+//!
+//! The tracks of the menus, of the game and of the results screen are read from the
+//! playlist `music.ron` in the assets, and crossfaded when the screen changes. The music
+//! of the game can be made of layers, heard one after the other as the snakes grow, and
+//! it speeds up with the snakes. Stingers are played when a game is won or lost.
+
+#![allow(clippy::too_many_arguments)]
+
+use crate::audio::{approach, AudioMixer, Bus};
+use crate::config::{ConfigError, GameConfig};
+use crate::constants::*;
+use crate::logic::{Game, GameEvent, GameTimer};
+use crate::ui::GameState;
+use bevy::audio::Volume;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The tracks to play, as paths in the assets.
+#[derive(Debug, Deserialize, Resource)]
+#[serde(default)]
+pub struct Playlist {
+    /// Played in the menus and the level editor.
+    pub menu: Option<String>,
+    /// Played together during games and replays.
+    pub game: Vec<Layer>,
+    /// Played on the results screen.
+    pub results: Option<String>,
+    /// Played once when a game is won.
+    pub game_won: Option<String>,
+    /// Played once when a game is lost.
+    pub game_over: Option<String>,
+    /// How closely the music of the game follows the speed of the snakes, from 0 for a
+    /// steady tempo to 1 for a tempo as many times faster as the snakes.
+    pub tempo: f32,
+}
+
+/// A track of the music of the game.
+#[derive(Debug, Deserialize)]
+pub struct Layer {
+    pub track: String,
+    /// The length the longest snake must reach for the layer to be heard.
+    #[serde(default)]
+    pub length: usize,
+}
+
+impl Default for Playlist {
+    fn default() -> Self {
+        Playlist {
+            menu: Some("music.ogg".to_string()),
+            game: vec![Layer {
+                track: "music.ogg".to_string(),
+                length: 0,
+            }],
+            results: Some("music.ogg".to_string()),
+            game_won: None,
+            game_over: None,
+            tempo: 0.5,
+        }
+    }
+}
+
+/// The part of the game the music is played for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scene {
+    Menu,
+    Game,
+    Results,
+}
+
+impl Scene {
+    fn of(state: GameState) -> Self {
+        match state {
+            GameState::Game | GameState::Replay | GameState::Restart => Scene::Game,
+            GameState::Results => Scene::Results,
+            GameState::Splash | GameState::Menu | GameState::Editor => Scene::Menu,
+        }
+    }
+}
+
+impl Playlist {
+    pub fn path() -> PathBuf {
+        PathBuf::from("assets/music.ron")
+    }
+
+    /// Reads and validates the playlist at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let playlist: Playlist =
+            ron::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        playlist.validate()?;
+        Ok(playlist)
+    }
+
+    /// Reads the playlist at `path`, or returns the default one if there is no such file.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        match Self::load(&path) {
+            Err(ConfigError::Io(_, e)) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            result => result,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(self.tempo.is_finite() && (0.0..=1.0).contains(&self.tempo)) {
+            return Err(ConfigError::Invalid {
+                field: "tempo",
+                reason: "must be between 0 and 1".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The tracks of `scene`, and whether each one is heard while the longest snake is
+    /// `length` long.
+    fn tracks(&self, scene: Scene, length: usize) -> Vec<(&str, bool)> {
+        match scene {
+            Scene::Menu => self
+                .menu
+                .iter()
+                .map(|track| (track.as_str(), true))
+                .collect(),
+            Scene::Game => self
+                .game
+                .iter()
+                .map(|layer| (layer.track.as_str(), length >= layer.length))
+                .collect(),
+            Scene::Results => self
+                .results
+                .iter()
+                .map(|track| (track.as_str(), true))
+                .collect(),
+        }
+    }
+}
+
+/// A looping track of the music. A track shared by two scenes keeps playing from one
+/// to the other.
+#[derive(Component)]
+pub struct MusicTrack {
+    path: String,
+    /// How much of the volume of the music the track plays at, rising while it is heard
+    /// and falling otherwise.
+    fade: f32,
+}
+
+/// Crossfades the tracks of the playlist to the ones of the current scene, and sets
+/// their volume and speed.
+pub fn play_music(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    playlist: Res<Playlist>,
+    mixer: Res<AudioMixer>,
+    state: Res<State<GameState>>,
+    game: Option<Res<Game>>,
+    timer: Res<GameTimer>,
+    config: Res<GameConfig>,
+    mut tracks: Query<(Entity, &mut MusicTrack, Option<&AudioSink>)>,
+) {
+    let scene = Scene::of(*state.get());
+    let (length, speed) = match (scene, game) {
+        (Scene::Game, Some(game)) => {
+            let length = game.snakes().iter().map(|snake| snake.body().len()).max();
+            let rate = 1.0 / timer.duration().as_secs_f32();
+            let speed = 1.0 + playlist.tempo * (rate / config.refresh_rate - 1.0);
+            (
+                length.unwrap_or(0),
+                speed.clamp(1.0 / MUSIC_MAX_SPEED, MUSIC_MAX_SPEED),
+            )
+        }
+        _ => (0, 1.0),
+    };
+    let wanted = playlist.tracks(scene, length);
+    let step = time.delta_seconds() / MUSIC_CROSSFADE_DURATION;
+
+    for (entity, mut track, sink) in &mut tracks {
+        let heard = wanted
+            .iter()
+            .find(|(path, _)| *path == track.path)
+            .map(|&(_, heard)| heard);
+        track.fade = approach(
+            track.fade,
+            if heard == Some(true) { 1.0 } else { 0.0 },
+            step,
+        );
+        if heard.is_none() && track.fade == 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        if let Some(sink) = sink {
+            let volume = mixer.music_volume() * track.fade;
+            if sink.volume() != volume {
+                sink.set_volume(volume);
+            }
+            if sink.speed() != speed {
+                sink.set_speed(approach(sink.speed(), speed, step));
+            }
+        }
+    }
+
+    // Layers start together, even those not heard yet, to stay in time
+    for (path, _) in wanted {
+        if tracks.iter().all(|(_, track, _)| track.path != path) {
+            commands.spawn((
+                AudioBundle {
+                    source: asset_server.load(path.to_string()),
+                    settings: PlaybackSettings::LOOP.with_volume(Volume::new_relative(0.0)),
+                },
+                MusicTrack {
+                    path: path.to_string(),
+                    fade: 0.0,
+                },
+            ));
+        }
+    }
+}
+
+/// Plays the stinger of the playlist for a game won or lost.
+pub fn play_stingers(
+    mut commands: Commands,
+    mut events: EventReader<GameEvent>,
+    asset_server: Res<AssetServer>,
+    playlist: Res<Playlist>,
+    mixer: Res<AudioMixer>,
+) {
+    for event in events.read() {
+        let stinger = match event {
+            GameEvent::GameWon => playlist.game_won.as_deref(),
+            GameEvent::GameOver(_) => playlist.game_over.as_deref(),
+            _ => None,
+        };
+        if let Some(path) = stinger {
+            mixer.play(
+                &mut commands,
+                asset_server.load(path.to_string()),
+                Bus::Music,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_bundled_playlist() {
+        let playlist = Playlist::load(Playlist::path()).unwrap();
+        assert_eq!(playlist.menu.as_deref(), Some("music.ogg"));
+        assert_eq!(playlist.game.len(), 1);
+    }
+
+    #[test]
+    fn layers_are_heard_as_the_snakes_grow() {
+        let playlist: Playlist =
+            ron::from_str(r#"(game: [(track: "base.ogg"), (track: "drums.ogg", length: 10)])"#)
+                .unwrap();
+        assert_eq!(
+            playlist.tracks(Scene::Game, 3),
+            [("base.ogg", true), ("drums.ogg", false)]
+        );
+        assert_eq!(
+            playlist.tracks(Scene::Game, 10),
+            [("base.ogg", true), ("drums.ogg", true)]
+        );
+        assert_eq!(playlist.tracks(Scene::Results, 10), [("music.ogg", true)]);
+    }
+
+    #[test]
+    fn rejects_invalid_tempos() {
+        let playlist: Playlist = ron::from_str("(tempo: 1.5)").unwrap();
+        assert!(playlist.validate().is_err());
+    }
+}
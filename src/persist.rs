@@ -0,0 +1,74 @@
+//! The files the game keeps in the data directory: the high scores, the key bindings,
+//! the settings and the results of the daily challenges.
+//!
+//! They are saved as RON to a temporary file first, which is then renamed over the
+//! previous one, so that a crash never leaves half a file behind. A file that cannot be
+//! read anyway is renamed with a `.bak` extension and the game starts over with the
+//! defaults, so that the next save does not overwrite what the player may want to fix by
+//! hand.
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Reads the value saved at `path`, or the default one if there is no such file or it
+/// cannot be read.
+pub fn load_or_recover<T: DeserializeOwned + Default>(path: impl AsRef<Path>) -> T {
+    let path = path.as_ref();
+    let error = match fs::read_to_string(path) {
+        Ok(text) => match ron::from_str(&text) {
+            Ok(value) => return value,
+            Err(e) => e.to_string(),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return default(),
+        Err(e) => e.to_string(),
+    };
+    let backup = path.with_extension("ron.bak");
+    warn!(
+        "{}: {error}, moving it to {}",
+        path.display(),
+        backup.display()
+    );
+    if let Err(e) = fs::rename(path, &backup) {
+        error!("Cannot move {}: {e}", path.display());
+    }
+    default()
+}
+
+/// Saves `value` at `path`, creating the directory if needed.
+pub fn save_atomic<T: Serialize>(value: &T, path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let text = ron::ser::to_string_pretty(value, default()).map_err(io::Error::other)?;
+    let temporary = path.with_extension("ron.tmp");
+    fs::write(&temporary, text)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn moves_unreadable_files_aside() {
+        let dir = std::env::temp_dir().join(format!("snake-persist-{}", std::process::id()));
+        let path = dir.join("data.ron");
+        let mut data = BTreeMap::new();
+        data.insert("apples".to_string(), 3);
+        save_atomic(&data, &path).unwrap();
+        assert_eq!(load_or_recover::<BTreeMap<String, u32>>(&path), data);
+
+        fs::write(&path, "{\"apples\": ").unwrap();
+        assert!(load_or_recover::<BTreeMap<String, u32>>(&path).is_empty());
+        assert!(!path.exists());
+        assert!(path.with_extension("ron.bak").exists());
+        // Nothing to read any more
+        assert!(load_or_recover::<BTreeMap<String, u32>>(&path).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+//! Special food and power-ups.
+//!
+//! Games with power-ups sometimes drop a pickup on the grid when an apple is eaten. What
+//! each kind of pickup does is read from [`PICKUPS`], so a new kind only takes a line in
+//! the table and a color. Like the rest of the rules, durations are counted in ticks, so
+//! replays of games with power-ups play the same every time.
+
+use crate::rules::Position;
+use rand::Rng;
+
+/// Chance that a pickup is dropped when an apple is eaten and none is on the grid.
+pub const PICKUP_CHANCE: f64 = 0.3;
+/// How many times more points a snake under [`Effect::Multiplier`] scores.
+pub const SCORE_MULTIPLIER: usize = 2;
+/// How many times slower the snakes move under [`Effect::SlowTime`].
+pub const SLOW_TIME_SCALE: f32 = 1.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PickupKind {
+    Golden,
+    Shrink,
+    Slow,
+    Ghost,
+    Multiplier,
+}
+
+impl PickupKind {
+    pub fn spec(self) -> &'static PickupSpec {
+        PICKUPS
+            .iter()
+            .find(|spec| spec.kind == self)
+            .expect("every kind of pickup is in the table")
+    }
+}
+
+/// A lasting effect of a pickup on the snake that took it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Effect {
+    /// Every snake moves [`SLOW_TIME_SCALE`] times slower.
+    SlowTime,
+    /// The snake goes through its own tail.
+    Ghost,
+    /// The snake scores [`SCORE_MULTIPLIER`] times more points.
+    Multiplier,
+}
+
+impl Effect {
+    pub fn name(self) -> &'static str {
+        match self {
+            Effect::SlowTime => "Slow",
+            Effect::Ghost => "Ghost",
+            Effect::Multiplier => "x2",
+        }
+    }
+}
+
+/// What a kind of pickup does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PickupSpec {
+    pub kind: PickupKind,
+    /// How often this kind is dropped, relative to the others.
+    pub weight: u32,
+    /// Ticks the pickup stays on the grid before it disappears.
+    pub lifetime: u32,
+    /// Points given when it is taken, multiplied like the ones of the apples.
+    pub points: usize,
+    /// The snake grows by one segment, as with an apple.
+    pub grows: bool,
+    /// Segments taken off the tail, the head always staying.
+    pub shrink: usize,
+    /// The effect given, and the number of ticks it lasts.
+    pub effect: Option<(Effect, u32)>,
+}
+
+pub const PICKUPS: [PickupSpec; 5] = [
+    PickupSpec {
+        kind: PickupKind::Golden,
+        weight: 3,
+        lifetime: 30,
+        points: 5,
+        grows: true,
+        shrink: 0,
+        effect: None,
+    },
+    PickupSpec {
+        kind: PickupKind::Shrink,
+        weight: 2,
+        lifetime: 60,
+        points: 0,
+        grows: false,
+        shrink: 3,
+        effect: None,
+    },
+    PickupSpec {
+        kind: PickupKind::Slow,
+        weight: 2,
+        lifetime: 60,
+        points: 0,
+        grows: false,
+        shrink: 0,
+        effect: Some((Effect::SlowTime, 50)),
+    },
+    PickupSpec {
+        kind: PickupKind::Ghost,
+        weight: 2,
+        lifetime: 60,
+        points: 0,
+        grows: false,
+        shrink: 0,
+        effect: Some((Effect::Ghost, 40)),
+    },
+    PickupSpec {
+        kind: PickupKind::Multiplier,
+        weight: 1,
+        lifetime: 45,
+        points: 0,
+        grows: false,
+        shrink: 0,
+        effect: Some((Effect::Multiplier, 80)),
+    },
+];
+
+/// Draws one of the `allowed` kinds of pickup, each kind being as likely as its weight.
+pub fn random_kind(rng: &mut impl Rng, allowed: impl Fn(PickupKind) -> bool) -> PickupKind {
+    let specs = || PICKUPS.iter().filter(|spec| allowed(spec.kind));
+    let total: u32 = specs().map(|spec| spec.weight).sum();
+    let mut draw = rng.gen_range(0..total);
+    for spec in specs() {
+        if draw < spec.weight {
+            return spec.kind;
+        }
+        draw -= spec.weight;
+    }
+    unreachable!("the draw is below the sum of the weights")
+}
+
+/// A pickup lying on the grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridPickup {
+    pub kind: PickupKind,
+    pub position: Position,
+    /// Ticks before it disappears.
+    pub ticks_left: u32,
+}
+
+/// An effect a snake is under.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ActiveEffect {
+    pub effect: Effect,
+    /// Moves left with the effect.
+    pub ticks_left: u32,
+}
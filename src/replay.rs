@@ -0,0 +1,423 @@
+//! Recording and playback of whole games.
+//!
+//! A game is fully determined by its level, its seed and by the directions fed to
+//! [`SnakeGame::step_all`](crate::rules::SnakeGame::step_all) at each tick, so that is
+//! all a replay file holds.
+
+use crate::bot::SnakeBot;
+use crate::constants::REFRESH_RATE;
+use crate::difficulty::Pace;
+use crate::gamepad;
+use crate::input::{Action, InputMap};
+use crate::level::{Level, LevelError};
+use crate::logic::{new_game, Game, GameEvent, GameRng};
+use crate::rules::{Boundary, Ruleset, SnakeDirection, SnakeGame, StepOutcome, TimeLimit};
+use crate::scores::default_refresh_rate;
+use crate::ui::GameState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const FAST_FORWARD_SPEED: f32 = 4.0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    pub direction: SnakeDirection,
+    #[serde(default)]
+    pub boundary: Boundary,
+    /// The level the game was played on, an open grid if `None`.
+    #[serde(default)]
+    pub level: Option<Level>,
+    #[serde(default)]
+    pub versus: bool,
+    /// How fast the game was played, which the rules do not depend on.
+    #[serde(default)]
+    pub pace: Pace,
+    /// The moves per second the pace scaled.
+    #[serde(default = "default_refresh_rate")]
+    pub refresh_rate: f32,
+    #[serde(default)]
+    pub power_ups: bool,
+    #[serde(default)]
+    pub ruleset: Ruleset,
+    #[serde(default)]
+    pub time_limit: Option<TimeLimit>,
+    /// The input of each tick, `None` when the snake went straight on.
+    pub inputs: Vec<Option<SnakeDirection>>,
+    /// The inputs of the second snake of versus games.
+    #[serde(default)]
+    pub rival_inputs: Vec<Option<SnakeDirection>>,
+    /// The player finished the zen game after the last input.
+    #[serde(default)]
+    pub finished: bool,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "cannot access the replay file: {e}"),
+            ReplayError::Parse(e) => write!(f, "invalid replay file: {e}"),
+            ReplayError::Serialize(e) => write!(f, "cannot serialize the replay: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// The state of a game at the end of its replay.
+#[derive(Debug)]
+pub struct Simulation {
+    pub game: SnakeGame,
+    /// Number of inputs played before the game ended.
+    pub ticks: usize,
+    /// The outcome of the last tick for each snake.
+    pub outcomes: Vec<StepOutcome>,
+}
+
+impl Replay {
+    /// Starts the replay of `game`, played at `pace` scaling `refresh_rate`, which must
+    /// not have been played yet.
+    pub fn new(seed: u64, game: &SnakeGame, pace: Pace, refresh_rate: f32) -> Self {
+        Replay {
+            seed,
+            width: game.width(),
+            height: game.height(),
+            direction: game.direction(),
+            boundary: game.boundary(),
+            level: Some(game.level().clone()),
+            versus: game.is_versus(),
+            pace,
+            refresh_rate,
+            power_ups: game.has_power_ups(),
+            ruleset: game.ruleset(),
+            time_limit: game.time_limit(),
+            inputs: vec![],
+            rival_inputs: vec![],
+            finished: false,
+        }
+    }
+
+    /// Records the inputs of the snakes for one tick.
+    pub fn push(&mut self, inputs: &[Option<SnakeDirection>]) {
+        self.inputs.push(inputs.first().copied().flatten());
+        if self.versus {
+            self.rival_inputs.push(inputs.get(1).copied().flatten());
+        }
+    }
+
+    /// The inputs of the snakes at `tick`.
+    pub fn inputs_at(&self, tick: usize) -> Vec<Option<SnakeDirection>> {
+        let mut inputs = vec![self.inputs.get(tick).copied().flatten()];
+        if self.versus {
+            inputs.push(self.rival_inputs.get(tick).copied().flatten());
+        }
+        inputs
+    }
+
+    /// Records a game on `level` played by `bots`, one for each snake, giving up after
+    /// `max_ticks` if they never end it.
+    pub fn from_bots(
+        seed: u64,
+        level: Level,
+        boundary: Boundary,
+        bots: &mut [Box<dyn SnakeBot>],
+        max_ticks: usize,
+    ) -> Self {
+        let mut rng = GameRng::new(seed);
+        let Game(mut game) = new_game(
+            level,
+            boundary,
+            bots.len() > 1,
+            false,
+            Ruleset::Standard,
+            None,
+            &mut rng,
+        );
+        let mut replay = Replay::new(seed, &game, Pace::default(), REFRESH_RATE);
+        while !game.is_over() && replay.inputs.len() < max_ticks {
+            let inputs: Vec<_> = bots
+                .iter_mut()
+                .enumerate()
+                .map(|(player, bot)| bot.next_direction(&game, player))
+                .collect();
+            replay.push(&inputs);
+            game.step_all(&inputs, &mut *rng);
+        }
+        replay
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let text = fs::read_to_string(path).map_err(ReplayError::Io)?;
+        ron::from_str(&text).map_err(ReplayError::Parse)
+    }
+
+    /// The level the game was played on.
+    pub fn level(&self) -> Level {
+        self.level
+            .clone()
+            .unwrap_or_else(|| Level::open(self.width, self.height, self.direction))
+    }
+
+    /// Checks that the game of the replay can be started.
+    pub fn validate(&self) -> Result<(), LevelError> {
+        match self.versus {
+            true => self.level().validate_versus(),
+            false => self.level().validate(),
+        }
+    }
+
+    /// Starts the game of the replay, which should have been validated.
+    pub fn new_game(&self) -> (SnakeGame, GameRng) {
+        let mut rng = GameRng::new(self.seed);
+        let game = new_game(
+            self.level(),
+            self.boundary,
+            self.versus,
+            self.power_ups,
+            self.ruleset,
+            self.time_limit,
+            &mut rng,
+        );
+        (game.0, rng)
+    }
+
+    /// Plays the replay through the rules alone, without any frontend.
+    pub fn simulate(&self) -> Simulation {
+        let (mut game, mut rng) = self.new_game();
+        let mut outcomes = vec![];
+        let mut ticks = 0;
+        for tick in 0..self.inputs.len() {
+            if game.is_over() {
+                break;
+            }
+            outcomes = game.step_all(&self.inputs_at(tick), &mut *rng);
+            ticks += 1;
+        }
+        if self.finished && self.ruleset == Ruleset::Zen && !game.is_over() {
+            outcomes = game.finish();
+        }
+        Simulation {
+            game,
+            ticks,
+            outcomes,
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(ReplayError::Io)?;
+        }
+        let text = ron::to_string(self).map_err(ReplayError::Serialize)?;
+        fs::write(path, text).map_err(ReplayError::Io)
+    }
+
+    /// Saves the replay in `dir` as `{name}.ron`, or as `{name}-1.ron` and so on if the
+    /// name is taken, and returns its path. Another replay is never overwritten, even
+    /// one saved at the same time by another instance of the game.
+    pub fn save_new(&self, dir: impl AsRef<Path>, name: &str) -> Result<PathBuf, ReplayError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(ReplayError::Io)?;
+        let text = ron::to_string(self).map_err(ReplayError::Serialize)?;
+        let mut attempt = 0;
+        loop {
+            let path = match attempt {
+                0 => dir.join(format!("{name}.ron")),
+                n => dir.join(format!("{name}-{n}.ron")),
+            };
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    file.write_all(text.as_bytes()).map_err(ReplayError::Io)?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(e) => return Err(ReplayError::Io(e)),
+            }
+        }
+    }
+}
+
+pub fn replays_dir() -> PathBuf {
+    crate::data_dir().join("replays")
+}
+
+/// The replay of the game being played, if it is recorded.
+#[derive(Default, Resource, Deref, DerefMut)]
+pub struct ReplayRecorder(pub Option<Replay>);
+
+/// The file of the last recorded game.
+#[derive(Default, Resource, Deref, DerefMut)]
+pub struct LastReplay(pub Option<PathBuf>);
+
+/// The replay being watched, only present in [`GameState::Replay`].
+#[derive(Resource)]
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    pub tick: usize,
+    pub paused: bool,
+    pub fast_forward: bool,
+    /// Set to play a single tick while paused.
+    pub step: bool,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer {
+            replay,
+            tick: 0,
+            paused: false,
+            fast_forward: false,
+            step: false,
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        if self.fast_forward {
+            FAST_FORWARD_SPEED
+        } else {
+            1.0
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.replay.inputs.len()
+    }
+
+    /// Returns the inputs of the snakes for the next tick.
+    pub fn next_inputs(&mut self) -> Vec<Option<SnakeDirection>> {
+        let inputs = self.replay.inputs_at(self.tick);
+        self.tick += 1;
+        inputs
+    }
+}
+
+pub fn save_replay(
+    mut events: EventReader<GameEvent>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut last_replay: ResMut<LastReplay>,
+) {
+    if events.read().last().is_none() {
+        return;
+    }
+    let Some(replay) = recorder.take() else {
+        return;
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let dir = replays_dir();
+    match replay.save_new(&dir, &timestamp.to_string()) {
+        Ok(path) => {
+            info!("Replay saved to {}", path.display());
+            last_replay.0 = Some(path);
+        }
+        Err(e) => error!("Cannot save the replay in {}: {e}", dir.display()),
+    }
+}
+
+pub fn replay_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    input_map: Res<InputMap>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut player: ResMut<ReplayPlayer>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if input_map.just_pressed(Action::Pause, &keyboard_input)
+        || gamepad::just_pressed(&gamepads, &gamepad_buttons, GamepadButtonType::Start)
+    {
+        player.paused = !player.paused;
+    }
+    if input_map.just_pressed(Action::Step, &keyboard_input) && player.paused {
+        player.step = true;
+    }
+    player.fast_forward = input_map.pressed(Action::FastForward, &keyboard_input);
+    if keyboard_input.just_pressed(KeyCode::Back)
+        || gamepad::just_pressed(&gamepads, &gamepad_buttons, GamepadButtonType::East)
+    {
+        game_state.set(GameState::Menu);
+    }
+}
+
+/// Leaves a replay whose inputs ran out before the game ended, unless the player
+/// finished it there.
+pub fn end_replay(
+    player: Res<ReplayPlayer>,
+    game: Res<Game>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if player.is_finished() && !player.replay.finished && !game.is_over() {
+        game_state.set(GameState::Menu);
+    }
+}
+
+pub fn stop_replay(mut commands: Commands) {
+    commands.remove_resource::<ReplayPlayer>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::DeathCause;
+
+    /// The replay of a fresh game on a `width` x `height` grid.
+    fn replay(seed: u64, width: usize, height: usize) -> Replay {
+        let game = SnakeGame::new(width, height, SnakeDirection::Up, &mut *GameRng::new(seed));
+        Replay::new(seed, &game, Pace::default(), REFRESH_RATE)
+    }
+
+    #[test]
+    fn round_trips_through_ron() {
+        let mut replay = replay(42, 17, 13);
+        replay.inputs = vec![None, Some(SnakeDirection::Left), None];
+        let text = ron::to_string(&replay).unwrap();
+        let parsed: Replay = ron::from_str(&text).unwrap();
+        assert_eq!(parsed.seed, 42);
+        assert_eq!((parsed.width, parsed.height), (17, 13));
+        assert_eq!(parsed.direction, SnakeDirection::Up);
+        assert_eq!(parsed.inputs, replay.inputs);
+    }
+
+    #[test]
+    fn simulates_until_the_game_ends() {
+        let mut replay = replay(7, 5, 5);
+        replay.inputs = vec![None; 5];
+        let simulation = replay.simulate();
+        assert_eq!(simulation.ticks, 3);
+        assert_eq!(simulation.outcomes, [StepOutcome::Died(DeathCause::Wall)]);
+        assert_eq!(replay.simulate().game.apple(), simulation.game.apple());
+    }
+
+    #[test]
+    fn never_overwrites_saved_replays() {
+        let dir = std::env::temp_dir().join(format!("snake-replays-{}", std::process::id()));
+        let replay = replay(1, 5, 5);
+        let first = replay.save_new(&dir, "1700000000").unwrap();
+        let second = replay.save_new(&dir, "1700000000").unwrap();
+        let third = replay.save_new(&dir, "1700000000").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(first, dir.join("1700000000.ron"));
+        assert_eq!(second, dir.join("1700000000-1.ron"));
+        assert_eq!(third, dir.join("1700000000-2.ron"));
+    }
+}
@@ -0,0 +1,1148 @@
+//! Engine-independent game rules.
+//!
+//! Everything in this module works on plain grid coordinates and knows nothing about
+//! Bevy, so the rules can be unit-tested, simulated or driven by another frontend.
+//! The Bevy systems in [`crate::logic`] only feed inputs in and mirror the state into
+//! sprites.
+
+use crate::level::Level;
+use crate::pickups::{self, ActiveEffect, Effect, GridPickup, PickupKind, PICKUP_CHANCE};
+use rand::rngs::mock::StepRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SnakeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl SnakeDirection {
+    pub fn opposite(self) -> Self {
+        use SnakeDirection::*;
+        match self {
+            Left => Right,
+            Right => Left,
+            Up => Down,
+            Down => Up,
+        }
+    }
+
+    pub fn clockwise(self) -> Self {
+        use SnakeDirection::*;
+        match self {
+            Up => Right,
+            Right => Down,
+            Down => Left,
+            Left => Up,
+        }
+    }
+
+    pub fn offset(self) -> (i32, i32) {
+        use SnakeDirection::*;
+        match self {
+            Left => (-1, 0),
+            Right => (1, 0),
+            Up => (0, 1),
+            Down => (0, -1),
+        }
+    }
+}
+
+/// A cell of the grid. `(0, 0)` is the bottom-left cell.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32) -> Self {
+        Position { x, y }
+    }
+
+    pub fn neighbour(self, direction: SnakeDirection) -> Self {
+        let (dx, dy) = direction.offset();
+        Position::new(self.x + dx, self.y + dy)
+    }
+}
+
+/// What happens when the snake reaches an edge of the grid.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Boundary {
+    /// The grid is surrounded by walls.
+    #[default]
+    Walls,
+    /// The snake comes back on the opposite edge.
+    Wrap,
+}
+
+/// The rules a game is won or lost by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Ruleset {
+    /// The game goes on until a snake dies or the grid is full.
+    #[default]
+    Standard,
+    /// The snakes eat as many apples as they can before the [`TimeLimit`] runs out.
+    TimeAttack,
+    /// Obstacles appear every [`SURVIVAL_OBSTACLE_EVERY`] moves, and the snakes score
+    /// a point every [`SURVIVAL_POINT_EVERY`] moves they last instead of eating.
+    Survival,
+    /// Nothing kills the snakes, they lose [`ZEN_SHRINK`] segments instead, and the
+    /// game goes on until the player [finishes](SnakeGame::finish) it.
+    Zen,
+}
+
+impl Ruleset {
+    pub const ALL: [Ruleset; 4] = [
+        Ruleset::Standard,
+        Ruleset::TimeAttack,
+        Ruleset::Survival,
+        Ruleset::Zen,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Ruleset::Standard => "Standard",
+            Ruleset::TimeAttack => "Time attack",
+            Ruleset::Survival => "Survival",
+            Ruleset::Zen => "Zen",
+        }
+    }
+}
+
+/// Moves between two obstacles appearing in survival games.
+pub const SURVIVAL_OBSTACLE_EVERY: usize = 25;
+/// Moves a snake has to last for a point in survival games.
+pub const SURVIVAL_POINT_EVERY: usize = 10;
+/// Obstacles never appear this close to a head, counting the moves to get there.
+pub const SURVIVAL_SAFE_DISTANCE: i32 = 3;
+/// Segments a snake loses when it runs into something in zen games.
+pub const ZEN_SHRINK: usize = 2;
+
+/// The clock of a time-attack game. The rules count the moves: time-attack games keep
+/// the speed they start at, so that `ticks` moves last the `seconds` the player picked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TimeLimit {
+    pub seconds: u32,
+    pub ticks: usize,
+}
+
+impl TimeLimit {
+    /// The clock of `seconds` seconds for snakes making `rate` moves per second.
+    pub fn new(seconds: u32, rate: f32) -> Self {
+        TimeLimit {
+            seconds,
+            ticks: (seconds as f32 * rate).round() as usize,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeathCause {
+    Wall,
+    Obstacle,
+    Tail,
+    /// The head ran into the body of another snake.
+    Rival,
+    /// Two heads met on the same cell.
+    HeadOn,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    Moved,
+    AteApple,
+    /// The snake took a pickup of games with power-ups.
+    Picked(PickupKind),
+    /// The snake of a zen game ran into something, and lost segments instead of dying.
+    Bumped(DeathCause),
+    Won,
+    /// The clock of a time-attack game ran out.
+    TimeUp,
+    /// The player ended a zen game.
+    Finished,
+    Died(DeathCause),
+}
+
+impl StepOutcome {
+    pub fn is_over(self) -> bool {
+        matches!(
+            self,
+            StepOutcome::Won | StepOutcome::TimeUp | StepOutcome::Finished | StepOutcome::Died(_)
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Snake {
+    /// The cells occupied by the snake, head first.
+    body: VecDeque<Position>,
+    direction: SnakeDirection,
+    score: usize,
+    /// Apples eaten, which unlike the score do not depend on the ruleset and the pickups.
+    apples: usize,
+    alive: bool,
+    effects: Vec<ActiveEffect>,
+}
+
+impl Snake {
+    fn new(start: Position, direction: SnakeDirection) -> Self {
+        Snake {
+            body: VecDeque::from([start]),
+            direction,
+            score: 0,
+            apples: 0,
+            alive: true,
+            effects: vec![],
+        }
+    }
+
+    pub fn head(&self) -> Position {
+        self.body[0]
+    }
+
+    pub fn body(&self) -> &VecDeque<Position> {
+        &self.body
+    }
+
+    pub fn direction(&self) -> SnakeDirection {
+        self.direction
+    }
+
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn apples(&self) -> usize {
+        self.apples
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.alive
+    }
+
+    /// The effects of the pickups the snake took, with the moves they have left.
+    pub fn effects(&self) -> &[ActiveEffect] {
+        &self.effects
+    }
+
+    pub fn has_effect(&self, effect: Effect) -> bool {
+        self.effects.iter().any(|active| active.effect == effect)
+    }
+
+    /// The points `points` are worth to the snake, with its multiplier.
+    fn points(&self, points: usize) -> usize {
+        match self.has_effect(Effect::Multiplier) {
+            true => points * pickups::SCORE_MULTIPLIER,
+            false => points,
+        }
+    }
+
+    /// Counts down the effects the snake moved under, dropping the ones that ran out.
+    fn tick_effects(&mut self) {
+        for active in &mut self.effects {
+            active.ticks_left = active.ticks_left.saturating_sub(1);
+        }
+        self.effects.retain(|active| active.ticks_left > 0);
+    }
+
+    /// Scores and shrinks the snake for taking `kind`.
+    fn take(&mut self, kind: PickupKind) {
+        let spec = kind.spec();
+        self.score += self.points(spec.points);
+        self.shrink(spec.shrink);
+    }
+
+    /// Takes `segments` segments off the tail, the head always staying.
+    fn shrink(&mut self, segments: usize) {
+        let len = self.body.len().saturating_sub(segments).max(1);
+        self.body.truncate(len);
+    }
+
+    /// Puts the snake under `effect` for `ticks` moves, starting over if it already was.
+    fn start_effect(&mut self, effect: Effect, ticks: u32) {
+        self.effects.retain(|active| active.effect != effect);
+        self.effects.push(ActiveEffect {
+            effect,
+            ticks_left: ticks,
+        });
+    }
+
+    /// Returns the most recent direction of `inputs` the snake is allowed to turn to,
+    /// i.e. the last one that would not make it go back on itself.
+    pub fn pick_direction(&self, inputs: &[SnakeDirection]) -> Option<SnakeDirection> {
+        inputs
+            .iter()
+            .rev()
+            .find(|direction| **direction != self.direction.opposite())
+            .copied()
+    }
+
+    fn turn(&mut self, input: Option<SnakeDirection>) {
+        if let Some(direction) = input {
+            if direction != self.direction.opposite() {
+                self.direction = direction;
+            }
+        }
+    }
+}
+
+/// A game on one level, played by one snake or by two in versus games. The game is over
+/// as soon as a snake dies, or when the clock of a time-attack game runs out.
+#[derive(Clone, Debug)]
+pub struct SnakeGame {
+    level: Level,
+    snakes: Vec<Snake>,
+    boundary: Boundary,
+    apple: Option<Position>,
+    /// Pickups are only dropped in games with power-ups.
+    power_ups: bool,
+    pickup: Option<GridPickup>,
+    ruleset: Ruleset,
+    time_limit: Option<TimeLimit>,
+    /// The obstacles that appeared during a survival game.
+    spawned_obstacles: Vec<Position>,
+    /// Moves played so far.
+    ticks: usize,
+    over: bool,
+}
+
+impl SnakeGame {
+    /// Starts a game with the snake in the middle of an empty `width` x `height` grid.
+    pub fn new(width: usize, height: usize, direction: SnakeDirection, rng: &mut impl Rng) -> Self {
+        Self::from_level(Level::open(width, height, direction), rng)
+    }
+
+    /// Starts a game on `level`, which should have been validated.
+    pub fn from_level(level: Level, rng: &mut impl Rng) -> Self {
+        let snake = Snake::new(level.start, level.direction);
+        Self::with_snakes(level, vec![snake], rng)
+    }
+
+    /// Starts a versus game on `level`, the second snake starting at
+    /// [`Level::rival_start`]. The level should have been validated with
+    /// [`Level::validate_versus`].
+    pub fn versus(level: Level, rng: &mut impl Rng) -> Self {
+        let (start, direction) = level
+            .rival_start()
+            .expect("the level has no room for a second snake");
+        let snakes = vec![
+            Snake::new(level.start, level.direction),
+            Snake::new(start, direction),
+        ];
+        Self::with_snakes(level, snakes, rng)
+    }
+
+    fn with_snakes(level: Level, snakes: Vec<Snake>, rng: &mut impl Rng) -> Self {
+        assert!(
+            level.width > 0 && level.height > 0,
+            "the grid cannot be empty"
+        );
+        let mut game = SnakeGame {
+            level,
+            snakes,
+            boundary: Boundary::default(),
+            apple: None,
+            power_ups: false,
+            pickup: None,
+            ruleset: Ruleset::default(),
+            time_limit: None,
+            spawned_obstacles: vec![],
+            ticks: 0,
+            over: false,
+        };
+        game.apple = game.gen_apple_location(rng);
+        game
+    }
+
+    pub fn with_boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    pub fn with_power_ups(mut self, power_ups: bool) -> Self {
+        self.power_ups = power_ups;
+        self
+    }
+
+    pub fn with_ruleset(mut self, ruleset: Ruleset) -> Self {
+        self.ruleset = ruleset;
+        self
+    }
+
+    /// Ends the game after `time_limit`, which time-attack games should be given.
+    pub fn with_time_limit(mut self, time_limit: Option<TimeLimit>) -> Self {
+        self.time_limit = time_limit;
+        self
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    pub fn width(&self) -> usize {
+        self.level.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.level.height
+    }
+
+    pub fn snakes(&self) -> &[Snake] {
+        &self.snakes
+    }
+
+    pub fn is_versus(&self) -> bool {
+        self.snakes.len() > 1
+    }
+
+    /// The head of the first snake, the only one outside of versus games.
+    pub fn head(&self) -> Position {
+        self.snakes[0].head()
+    }
+
+    /// The body of the first snake.
+    pub fn body(&self) -> &VecDeque<Position> {
+        self.snakes[0].body()
+    }
+
+    /// The direction of the first snake.
+    pub fn direction(&self) -> SnakeDirection {
+        self.snakes[0].direction()
+    }
+
+    /// The score of the first snake.
+    pub fn score(&self) -> usize {
+        self.snakes[0].score()
+    }
+
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    pub fn apple(&self) -> Option<Position> {
+        self.apple
+    }
+
+    pub fn has_power_ups(&self) -> bool {
+        self.power_ups
+    }
+
+    /// The pickup lying on the grid, if any.
+    pub fn pickup(&self) -> Option<GridPickup> {
+        self.pickup
+    }
+
+    /// Whether a snake took a pickup slowing the game down.
+    pub fn is_slowed(&self) -> bool {
+        self.snakes
+            .iter()
+            .any(|snake| snake.has_effect(Effect::SlowTime))
+    }
+
+    pub fn ruleset(&self) -> Ruleset {
+        self.ruleset
+    }
+
+    pub fn time_limit(&self) -> Option<TimeLimit> {
+        self.time_limit
+    }
+
+    /// The moves left before the clock runs out, in time-attack games.
+    pub fn ticks_left(&self) -> Option<usize> {
+        self.time_limit
+            .map(|limit| limit.ticks.saturating_sub(self.ticks))
+    }
+
+    /// Moves played so far.
+    pub fn ticks(&self) -> usize {
+        self.ticks
+    }
+
+    pub fn spawned_obstacles(&self) -> &[Position] {
+        &self.spawned_obstacles
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.level.contains(position)
+    }
+
+    pub fn is_obstacle(&self, position: Position) -> bool {
+        self.level.obstacles.contains(&position) || self.spawned_obstacles.contains(&position)
+    }
+
+    /// Whether an apple can be placed at `position`.
+    pub fn is_free(&self, position: Position) -> bool {
+        self.contains(position)
+            && !self.is_obstacle(position)
+            && self.level.portal_at(position).is_none()
+            && self.pickup.is_none_or(|pickup| pickup.position != position)
+            && !self
+                .snakes
+                .iter()
+                .any(|snake| snake.body.contains(&position))
+    }
+
+    /// The cells that are not free, see [`SnakeGame::is_free`], indexed by
+    /// [`SnakeGame::cell_index`]. Items are placed from this grid, which takes as long as
+    /// the grid has cells however long the snakes are.
+    fn occupancy(&self) -> Vec<bool> {
+        let mut taken = vec![false; self.level.width * self.level.height];
+        let portals = self.level.portals.iter().flatten();
+        let pickup = self.pickup.map(|pickup| pickup.position);
+        let bodies = self.snakes.iter().flat_map(|snake| &snake.body);
+        let cells = (self.level.obstacles.iter().chain(&self.spawned_obstacles))
+            .chain(portals)
+            .chain(&pickup)
+            .chain(bodies);
+        for &position in cells {
+            if self.contains(position) {
+                taken[self.cell_index(position)] = true;
+            }
+        }
+        taken
+    }
+
+    /// The index of `position`, which must be on the grid, in the rows of the grid.
+    fn cell_index(&self, position: Position) -> usize {
+        position.y as usize * self.level.width + position.x as usize
+    }
+
+    /// Picks a random free cell, or `None` if the snakes fill the whole grid. The apple
+    /// spawns of the level are preferred while one of them is free.
+    pub fn gen_apple_location(&self, rng: &mut impl Rng) -> Option<Position> {
+        let taken = self.occupancy();
+        let is_free = |position: &Position| !taken[self.cell_index(*position)];
+        let spawns: Vec<Position> = self
+            .level
+            .apple_spawns
+            .iter()
+            .copied()
+            .filter(is_free)
+            .collect();
+        let free = if spawns.is_empty() {
+            self.level.cells().filter(is_free).collect()
+        } else {
+            spawns
+        };
+        if free.is_empty() {
+            None
+        } else {
+            Some(free[rng.gen_range(0..free.len())])
+        }
+    }
+
+    /// Drops a pickup on a random free cell other than the one of the apple, if there
+    /// is any.
+    fn drop_pickup(&mut self, rng: &mut impl Rng) {
+        let taken = self.occupancy();
+        let free: Vec<Position> = self
+            .level
+            .cells()
+            .filter(|position| !taken[self.cell_index(*position)] && Some(*position) != self.apple)
+            .collect();
+        if free.is_empty() {
+            return;
+        }
+        let position = free[rng.gen_range(0..free.len())];
+        // Nothing slows down time-attack games, whose clock counts the moves
+        let kind = pickups::random_kind(rng, |kind| {
+            self.time_limit.is_none()
+                || kind
+                    .spec()
+                    .effect
+                    .is_none_or(|(effect, _)| effect != Effect::SlowTime)
+        });
+        self.pickup = Some(GridPickup {
+            kind,
+            position,
+            ticks_left: kind.spec().lifetime,
+        });
+    }
+
+    /// Places an obstacle on a random free cell other than the one of the apple, away
+    /// from the heads, if there is any.
+    fn spawn_obstacle(&mut self, rng: &mut impl Rng) {
+        let taken = self.occupancy();
+        let free: Vec<Position> = self
+            .level
+            .cells()
+            .filter(|position| !taken[self.cell_index(*position)] && Some(*position) != self.apple)
+            .filter(|position| {
+                self.snakes.iter().all(|snake| {
+                    let head = snake.head();
+                    (head.x - position.x).abs() + (head.y - position.y).abs()
+                        > SURVIVAL_SAFE_DISTANCE
+                })
+            })
+            .collect();
+        if !free.is_empty() {
+            let position = free[rng.gen_range(0..free.len())];
+            self.spawned_obstacles.push(position);
+        }
+    }
+
+    /// The cell a snake at `from` lands on when moving in `direction`, after wrapping
+    /// around and going through portals, or `None` if it hits a wall.
+    pub fn next_cell(&self, from: Position, direction: SnakeDirection) -> Option<Position> {
+        let mut next = from.neighbour(direction);
+        if self.boundary == Boundary::Wrap {
+            next.x = next.x.rem_euclid(self.level.width as i32);
+            next.y = next.y.rem_euclid(self.level.height as i32);
+        }
+        if !self.level.contains(next) {
+            return None;
+        }
+        Some(self.level.portal_exit(next).unwrap_or(next))
+    }
+
+    /// Ends a zen game, which nothing else ends, and returns the outcome of each snake.
+    pub fn finish(&mut self) -> Vec<StepOutcome> {
+        assert!(!self.over, "the game is over");
+        assert_eq!(self.ruleset, Ruleset::Zen, "only zen games are finished");
+        self.over = true;
+        vec![StepOutcome::Finished; self.snakes.len()]
+    }
+
+    /// Returns the direction the first snake may turn to, see [`Snake::pick_direction`].
+    pub fn pick_direction(&self, inputs: &[SnakeDirection]) -> Option<SnakeDirection> {
+        self.snakes[0].pick_direction(inputs)
+    }
+
+    /// Advances the game by one tick, turning first if `input` is a valid direction.
+    /// Other snakes, if any, go straight on.
+    pub fn step(&mut self, input: Option<SnakeDirection>, rng: &mut impl Rng) -> StepOutcome {
+        self.step_all(&[input], rng)[0]
+    }
+
+    /// Whether moving the first snake in `direction` would end the game, as found by
+    /// [`Self::step`] on a copy of the game. Other snakes, if any, go straight on.
+    pub fn would_die(&self, direction: SnakeDirection) -> bool {
+        // Where the next apple lands has no bearing on the outcome of the move
+        let mut rng = StepRng::new(0, 1);
+        let outcome = self.clone().step(Some(direction), &mut rng);
+        matches!(outcome, StepOutcome::Died(_))
+    }
+
+    /// Advances the game by one tick, each snake turning first if its input is a valid
+    /// direction. All the snakes move at once, and the outcome of each is returned.
+    pub fn step_all(
+        &mut self,
+        inputs: &[Option<SnakeDirection>],
+        rng: &mut impl Rng,
+    ) -> Vec<StepOutcome> {
+        assert!(!self.over, "the game is over");
+
+        let mut outcomes = vec![StepOutcome::Moved; self.snakes.len()];
+        let mut nexts = vec![None; self.snakes.len()];
+        for i in 0..self.snakes.len() {
+            self.snakes[i].turn(inputs.get(i).copied().flatten());
+            let snake = &self.snakes[i];
+            let Some(next) = self.next_cell(snake.head(), snake.direction) else {
+                outcomes[i] = StepOutcome::Died(DeathCause::Wall);
+                continue;
+            };
+            if self.is_obstacle(next) {
+                outcomes[i] = StepOutcome::Died(DeathCause::Obstacle);
+                continue;
+            }
+            nexts[i] = Some(next);
+        }
+
+        // Heads moving to the same cell, or through each other
+        for i in 0..self.snakes.len() {
+            for j in i + 1..self.snakes.len() {
+                let (Some(a), Some(b)) = (nexts[i], nexts[j]) else {
+                    continue;
+                };
+                if a == b || (a == self.snakes[j].head() && b == self.snakes[i].head()) {
+                    outcomes[i] = StepOutcome::Died(DeathCause::HeadOn);
+                    outcomes[j] = StepOutcome::Died(DeathCause::HeadOn);
+                }
+            }
+        }
+
+        let eats: Vec<bool> = (0..self.snakes.len())
+            .map(|i| outcomes[i] == StepOutcome::Moved && nexts[i] == self.apple)
+            .collect();
+        // Heads meeting on a pickup have crashed already, so only one snake takes it
+        let picks: Vec<Option<PickupKind>> = (0..self.snakes.len())
+            .map(|i| {
+                self.pickup
+                    .filter(|pickup| {
+                        outcomes[i] == StepOutcome::Moved && nexts[i] == Some(pickup.position)
+                    })
+                    .map(|pickup| pickup.kind)
+            })
+            .collect();
+        // The tails leave their cell before the heads move in
+        let mut leaves: Vec<bool> = (0..self.snakes.len())
+            .map(|i| {
+                let grows = eats[i] || picks[i].is_some_and(|kind| kind.spec().grows);
+                outcomes[i] == StepOutcome::Moved && !grows
+            })
+            .collect();
+        loop {
+            for i in 0..self.snakes.len() {
+                let Some(next) = nexts[i].filter(|_| outcomes[i] == StepOutcome::Moved) else {
+                    continue;
+                };
+                let occupied = |j: usize| {
+                    let body = &self.snakes[j].body;
+                    let cells = body.len() - usize::from(leaves[j]);
+                    body.iter().take(cells).any(|position| *position == next)
+                };
+                let mut rivals = (0..self.snakes.len()).filter(|j| *j != i);
+                if occupied(i) && !self.snakes[i].has_effect(Effect::Ghost) {
+                    outcomes[i] = StepOutcome::Died(DeathCause::Tail);
+                } else if rivals.any(occupied) {
+                    outcomes[i] = StepOutcome::Died(DeathCause::Rival);
+                }
+            }
+            if self.ruleset != Ruleset::Zen {
+                break;
+            }
+            // Snakes stay where they are rather than die in zen games, so their tails do
+            // not leave either, and the heads moving in are bumped in turn
+            let mut stayed = false;
+            for i in 0..self.snakes.len() {
+                if let StepOutcome::Died(cause) = outcomes[i] {
+                    outcomes[i] = StepOutcome::Bumped(cause);
+                    stayed |= std::mem::take(&mut leaves[i]);
+                }
+            }
+            if !stayed {
+                break;
+            }
+        }
+
+        self.ticks += 1;
+        let mut ate = false;
+        for (i, snake) in self.snakes.iter_mut().enumerate() {
+            match (outcomes[i], nexts[i]) {
+                (StepOutcome::Moved, Some(next)) => {
+                    if leaves[i] {
+                        snake.body.pop_back();
+                    }
+                    snake.body.push_front(next);
+                    if self.ruleset == Ruleset::Survival
+                        && self.ticks.is_multiple_of(SURVIVAL_POINT_EVERY)
+                    {
+                        snake.score += snake.points(1);
+                    }
+                    if eats[i] {
+                        snake.apples += 1;
+                        // Survival games are scored on time alone
+                        if self.ruleset != Ruleset::Survival {
+                            snake.score += snake.points(1);
+                        }
+                        outcomes[i] = StepOutcome::AteApple;
+                        ate = true;
+                    } else if let Some(kind) = picks[i] {
+                        snake.take(kind);
+                        outcomes[i] = StepOutcome::Picked(kind);
+                        self.pickup = None;
+                    }
+                    // The effects last until the end of the move they run out on
+                    snake.tick_effects();
+                    if let Some((effect, ticks)) = picks[i].and_then(|kind| kind.spec().effect) {
+                        snake.start_effect(effect, ticks);
+                    }
+                }
+                (StepOutcome::Bumped(_), _) => {
+                    snake.shrink(ZEN_SHRINK);
+                    snake.tick_effects();
+                }
+                _ => {
+                    snake.alive = false;
+                    self.over = true;
+                }
+            }
+        }
+
+        if let Some(pickup) = &mut self.pickup {
+            pickup.ticks_left = pickup.ticks_left.saturating_sub(1);
+            if pickup.ticks_left == 0 {
+                self.pickup = None;
+            }
+        }
+        if ate {
+            self.apple = self.gen_apple_location(rng);
+            if self.apple.is_none() && !self.over {
+                self.over = true;
+                outcomes.fill(StepOutcome::Won);
+            }
+        }
+        if self
+            .ticks_left()
+            .is_some_and(|ticks_left| ticks_left == 0 && !self.over)
+        {
+            self.over = true;
+            outcomes.fill(StepOutcome::TimeUp);
+        }
+        if self.ruleset == Ruleset::Survival
+            && self.ticks.is_multiple_of(SURVIVAL_OBSTACLE_EVERY)
+            && !self.over
+        {
+            self.spawn_obstacle(rng);
+        }
+        // Games without power-ups draw nothing more, so their replays stay the same
+        if ate
+            && self.power_ups
+            && self.pickup.is_none()
+            && !self.over
+            && rng.gen_bool(PICKUP_CHANCE)
+        {
+            self.drop_pickup(rng);
+        }
+        outcomes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(0)
+    }
+
+    /// A game on `map`, see [`crate::level`], with the apple at `apple`.
+    fn game(map: &str, apple: Position) -> SnakeGame {
+        let mut game = SnakeGame::from_level(map.parse().unwrap(), &mut rng());
+        game.apple = Some(apple);
+        game
+    }
+
+    fn open(width: usize, height: usize, apple: Position) -> SnakeGame {
+        let mut game = SnakeGame::new(width, height, SnakeDirection::Up, &mut rng());
+        game.apple = Some(apple);
+        game
+    }
+
+    /// Puts snake `i` on `body`, head first, heading in `direction`.
+    fn place(game: &mut SnakeGame, i: usize, body: &[(i32, i32)], direction: SnakeDirection) {
+        let snake = &mut game.snakes[i];
+        snake.body = body.iter().map(|&(x, y)| Position::new(x, y)).collect();
+        snake.direction = direction;
+    }
+
+    fn versus(width: usize, height: usize) -> SnakeGame {
+        let level = Level::open_versus(width, height, SnakeDirection::Right);
+        let mut game = SnakeGame::versus(level, &mut rng());
+        game.apple = Some(Position::new(0, 0));
+        game
+    }
+
+    #[test]
+    fn tells_the_moves_that_would_end_the_game() {
+        use SnakeDirection::*;
+        let mut game = open(5, 5, Position::new(4, 4));
+        place(&mut game, 0, &[(1, 1), (2, 1), (2, 2), (1, 2)], Up);
+        // The tail moves out of the way, and going back on itself goes straight on
+        assert!(!game.would_die(Up));
+        assert!(!game.would_die(Down));
+        assert!(!game.would_die(Left));
+        assert!(game.would_die(Right));
+        assert_eq!(game.head(), Position::new(1, 1));
+
+        place(&mut game, 0, &[(0, 1)], Left);
+        assert!(game.would_die(Left));
+        game.boundary = Boundary::Wrap;
+        assert!(!game.would_die(Left));
+    }
+
+    #[test]
+    fn moves_and_turns() {
+        let mut game = open(5, 5, Position::new(0, 0));
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::Moved);
+        assert_eq!(game.head(), Position::new(2, 3));
+        assert_eq!(
+            game.step(Some(SnakeDirection::Right), &mut rng()),
+            StepOutcome::Moved
+        );
+        assert_eq!(game.head(), Position::new(3, 3));
+        assert_eq!(game.body().len(), 1);
+    }
+
+    #[test]
+    fn never_turns_back() {
+        let mut game = open(5, 5, Position::new(0, 0));
+        place(&mut game, 0, &[(2, 2), (2, 1)], SnakeDirection::Up);
+        assert_eq!(
+            game.pick_direction(&[SnakeDirection::Left, SnakeDirection::Down]),
+            Some(SnakeDirection::Left)
+        );
+        game.step(Some(SnakeDirection::Down), &mut rng());
+        assert_eq!(game.direction(), SnakeDirection::Up);
+        assert_eq!(game.head(), Position::new(2, 3));
+    }
+
+    #[test]
+    fn eats_and_grows() {
+        let mut game = open(5, 5, Position::new(2, 3));
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::AteApple);
+        assert_eq!(game.score(), 1);
+        assert_eq!(game.body().len(), 2);
+        let apple = game.apple().unwrap();
+        assert!(!game.body().contains(&apple));
+
+        game.apple = Some(Position::new(0, 0));
+        game.step(None, &mut rng());
+        assert_eq!(game.body().len(), 2);
+    }
+
+    #[test]
+    fn dies_on_walls() {
+        let mut game = open(5, 5, Position::new(0, 0));
+        place(&mut game, 0, &[(2, 4)], SnakeDirection::Up);
+        assert_eq!(
+            game.step(None, &mut rng()),
+            StepOutcome::Died(DeathCause::Wall)
+        );
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn dies_on_obstacles() {
+        let mut game = game("#....\n.....\n..^..\n", Position::new(4, 2));
+        place(&mut game, 0, &[(0, 1)], SnakeDirection::Up);
+        assert_eq!(
+            game.step(None, &mut rng()),
+            StepOutcome::Died(DeathCause::Obstacle)
+        );
+    }
+
+    #[test]
+    fn apples_appear_on_the_spawns() {
+        let game = game("*...\n..^.\n....\n", Position::new(0, 0));
+        for seed in 0..10 {
+            let apple = game.gen_apple_location(&mut StdRng::seed_from_u64(seed));
+            assert_eq!(apple, Some(Position::new(0, 2)));
+        }
+    }
+
+    #[test]
+    fn dies_on_its_tail() {
+        let mut game = open(5, 5, Position::new(4, 4));
+        place(
+            &mut game,
+            0,
+            &[(2, 2), (2, 1), (1, 1), (1, 2), (1, 3)],
+            SnakeDirection::Left,
+        );
+        assert_eq!(
+            game.step(None, &mut rng()),
+            StepOutcome::Died(DeathCause::Tail)
+        );
+    }
+
+    #[test]
+    fn follows_its_tail_as_it_leaves() {
+        let mut game = open(5, 5, Position::new(4, 4));
+        place(
+            &mut game,
+            0,
+            &[(2, 2), (2, 1), (1, 1), (1, 2)],
+            SnakeDirection::Left,
+        );
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::Moved);
+        assert_eq!(game.head(), Position::new(1, 2));
+        assert_eq!(game.body().len(), 4);
+    }
+
+    #[test]
+    fn heads_meeting_both_die() {
+        let mut game = versus(7, 5);
+        place(&mut game, 0, &[(2, 2)], SnakeDirection::Right);
+        place(&mut game, 1, &[(4, 2)], SnakeDirection::Left);
+        let outcomes = game.step_all(&[None, None], &mut rng());
+        assert_eq!(outcomes, [StepOutcome::Died(DeathCause::HeadOn); 2]);
+
+        // Or crossing each other
+        let mut game = versus(7, 5);
+        place(&mut game, 0, &[(2, 2)], SnakeDirection::Right);
+        place(&mut game, 1, &[(3, 2)], SnakeDirection::Left);
+        let outcomes = game.step_all(&[None, None], &mut rng());
+        assert_eq!(outcomes, [StepOutcome::Died(DeathCause::HeadOn); 2]);
+    }
+
+    #[test]
+    fn dies_on_rivals() {
+        let mut game = versus(7, 5);
+        place(&mut game, 0, &[(2, 2)], SnakeDirection::Right);
+        place(&mut game, 1, &[(3, 3), (3, 2), (3, 1)], SnakeDirection::Up);
+        let outcomes = game.step_all(&[None, None], &mut rng());
+        assert_eq!(
+            outcomes,
+            [StepOutcome::Died(DeathCause::Rival), StepOutcome::Moved]
+        );
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut game = open(5, 5, Position::new(0, 0)).with_boundary(Boundary::Wrap);
+        place(&mut game, 0, &[(2, 4)], SnakeDirection::Up);
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::Moved);
+        assert_eq!(game.head(), Position::new(2, 0));
+    }
+
+    #[test]
+    fn goes_through_portals() {
+        let mut game = game(".....\n..1..\n..^..\n.....\n1....\n", Position::new(4, 4));
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::Moved);
+        assert_eq!(game.head(), Position::new(0, 0));
+    }
+
+    #[test]
+    fn wins_on_a_full_grid() {
+        let mut game = open(2, 2, Position::new(0, 1));
+        place(
+            &mut game,
+            0,
+            &[(1, 1), (1, 0), (0, 0)],
+            SnakeDirection::Left,
+        );
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::Won);
+        assert!(game.is_over());
+        assert_eq!(game.apple(), None);
+    }
+
+    #[test]
+    fn takes_pickups() {
+        let mut game = open(5, 5, Position::new(3, 4)).with_power_ups(true);
+        game.pickup = Some(GridPickup {
+            kind: PickupKind::Golden,
+            position: Position::new(2, 3),
+            ticks_left: 1,
+        });
+        assert_eq!(
+            game.step(None, &mut rng()),
+            StepOutcome::Picked(PickupKind::Golden)
+        );
+        assert_eq!((game.score(), game.body().len()), (5, 2));
+        assert_eq!(game.pickup(), None);
+
+        game.pickup = Some(GridPickup {
+            kind: PickupKind::Multiplier,
+            position: Position::new(2, 4),
+            ticks_left: 1,
+        });
+        game.step(None, &mut rng());
+        assert!(game.snakes[0].has_effect(Effect::Multiplier));
+        game.step(Some(SnakeDirection::Right), &mut rng());
+        assert_eq!(game.score(), 7);
+    }
+
+    #[test]
+    fn counts_the_apples_apart_from_the_score() {
+        let mut game = open(5, 5, Position::new(2, 4)).with_power_ups(true);
+        game.pickup = Some(GridPickup {
+            kind: PickupKind::Golden,
+            position: Position::new(2, 3),
+            ticks_left: 1,
+        });
+        game.step(None, &mut rng());
+        game.step(None, &mut rng());
+        assert_eq!((game.score(), game.snakes()[0].apples()), (6, 1));
+
+        let mut game = open(5, 5, Position::new(2, 3)).with_ruleset(Ruleset::Survival);
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::AteApple);
+        assert_eq!((game.score(), game.snakes()[0].apples()), (0, 1));
+    }
+
+    #[test]
+    fn pickups_disappear_in_time() {
+        let mut game = open(5, 5, Position::new(0, 0)).with_power_ups(true);
+        game.pickup = Some(GridPickup {
+            kind: PickupKind::Shrink,
+            position: Position::new(0, 4),
+            ticks_left: 2,
+        });
+        game.step(None, &mut rng());
+        assert!(game.pickup().is_some());
+        game.step(None, &mut rng());
+        assert_eq!(game.pickup(), None);
+    }
+
+    #[test]
+    fn ghosts_go_through_their_tail() {
+        let mut game = open(5, 5, Position::new(0, 0));
+        let body = [(2, 2), (3, 2), (3, 3), (2, 3), (1, 3)];
+        place(&mut game, 0, &body, SnakeDirection::Up);
+        game.snakes[0].start_effect(Effect::Ghost, 2);
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::Moved);
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::Moved);
+        assert!(!game.snakes[0].has_effect(Effect::Ghost));
+    }
+
+    #[test]
+    fn time_runs_out() {
+        let mut game = open(9, 9, Position::new(0, 0))
+            .with_ruleset(Ruleset::TimeAttack)
+            .with_time_limit(Some(TimeLimit::new(1, 3.0)));
+        place(&mut game, 0, &[(4, 4)], SnakeDirection::Right);
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::Moved);
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::Moved);
+        assert_eq!(game.ticks_left(), Some(1));
+        assert_eq!(game.step(None, &mut rng()), StepOutcome::TimeUp);
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn zen_snakes_shrink_instead_of_dying() {
+        let mut game = open(5, 5, Position::new(0, 0)).with_ruleset(Ruleset::Zen);
+        place(
+            &mut game,
+            0,
+            &[(2, 4), (2, 3), (2, 2), (2, 1), (2, 0)],
+            SnakeDirection::Up,
+        );
+        assert_eq!(
+            game.step(None, &mut rng()),
+            StepOutcome::Bumped(DeathCause::Wall)
+        );
+        assert!(!game.is_over());
+        assert_eq!(game.head(), Position::new(2, 4));
+        assert_eq!(game.body().len(), 5 - ZEN_SHRINK);
+    }
+
+    #[test]
+    fn zen_snakes_bumping_their_tail_keep_it() {
+        let mut game = open(5, 5, Position::new(4, 4)).with_ruleset(Ruleset::Zen);
+        place(
+            &mut game,
+            0,
+            &[(2, 2), (2, 1), (1, 1), (1, 2), (1, 3)],
+            SnakeDirection::Left,
+        );
+        assert_eq!(
+            game.step(None, &mut rng()),
+            StepOutcome::Bumped(DeathCause::Tail)
+        );
+        assert_eq!(game.body().len(), 5 - ZEN_SHRINK);
+        assert_eq!(game.head(), Position::new(2, 2));
+    }
+
+    #[test]
+    fn zen_games_end_when_finished() {
+        let mut game = open(5, 5, Position::new(2, 3)).with_ruleset(Ruleset::Zen);
+        game.step(None, &mut rng());
+        assert_eq!(game.finish(), [StepOutcome::Finished]);
+        assert!(game.is_over());
+        assert_eq!(game.score(), 1);
+    }
+}
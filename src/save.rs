@@ -0,0 +1,273 @@
+//! Saving a game in progress to resume it later.
+//!
+//! Like a replay, a save holds the seed and the inputs of the game so far, so resuming
+//! plays them again through the rules and the rest of the game is recorded as usual.
+//! It also holds a [`Snapshot`] of the state the game was left in, random number
+//! generator included, which the game played again must match: a file that was
+//! tampered with, or written by a version whose rules differ, is rejected rather than
+//! resumed into another game.
+
+use crate::bot::BotKind;
+use crate::daily::DailyChallenge;
+use crate::level::LevelError;
+use crate::logic::GameRng;
+use crate::replay::Replay;
+use crate::rules::{Position, SnakeDirection, SnakeGame};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The version of the save files written by this version of the game. Files of other
+/// versions are not resumed.
+pub const SAVE_VERSION: u32 = 1;
+
+/// The state of a snake when the game was saved.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnakeSnapshot {
+    pub direction: SnakeDirection,
+    /// The cells of the snake, head first.
+    pub body: Vec<Position>,
+    pub score: usize,
+}
+
+/// The state of a game when it was saved.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub snakes: Vec<SnakeSnapshot>,
+    pub apple: Option<Position>,
+    /// The words drawn from the random number generator so far.
+    pub rng_words: u64,
+}
+
+impl Snapshot {
+    pub fn of(game: &SnakeGame, rng: &GameRng) -> Self {
+        Snapshot {
+            snakes: game
+                .snakes()
+                .iter()
+                .map(|snake| SnakeSnapshot {
+                    direction: snake.direction(),
+                    body: snake.body().iter().copied().collect(),
+                    score: snake.score(),
+                })
+                .collect(),
+            apple: game.apple(),
+            rng_words: rng.get_word_pos() as u64,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub version: u32,
+    /// The game so far.
+    pub replay: Replay,
+    pub snapshot: Snapshot,
+    /// Seconds since the last move.
+    pub timer_elapsed: f32,
+    /// The bot of the game, racing the player in versus games.
+    pub bot: Option<BotKind>,
+    /// The first player had handed their snake over to the bot.
+    pub autopilot: bool,
+    /// A bot played for the first player at some point, so the score is not a best
+    /// score.
+    pub assisted: bool,
+    /// The daily challenge being played, if any.
+    pub daily: Option<DailyChallenge>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+    Version(u32),
+    Level(LevelError),
+    Over,
+    Mismatch,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "cannot access the saved game: {e}"),
+            SaveError::Parse(e) => write!(f, "invalid saved game: {e}"),
+            SaveError::Serialize(e) => write!(f, "cannot serialize the game: {e}"),
+            SaveError::Version(version) => write!(
+                f,
+                "the game was saved by another version (save version {version}, expected {SAVE_VERSION})"
+            ),
+            SaveError::Level(e) => write!(f, "invalid level: {e}"),
+            SaveError::Over => write!(f, "the saved game is over"),
+            SaveError::Mismatch => write!(f, "the saved game does not match its inputs"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl SavedGame {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        let text = fs::read_to_string(path).map_err(SaveError::Io)?;
+        // The version is checked first, the rest of an older file may not parse
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let options = ron::Options::default();
+        if let Ok(Version { version }) = options.from_str(&text) {
+            if version != SAVE_VERSION {
+                return Err(SaveError::Version(version));
+            }
+        }
+        options.from_str(&text).map_err(SaveError::Parse)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(SaveError::Io)?;
+        }
+        let text = ron::to_string(self).map_err(SaveError::Serialize)?;
+        let temporary = path.with_extension("ron.tmp");
+        fs::write(&temporary, text).map_err(SaveError::Io)?;
+        fs::rename(&temporary, path).map_err(SaveError::Io)
+    }
+
+    /// Plays the inputs of the save again, and returns the game and its random number
+    /// generator as they were saved.
+    pub fn restore(&self) -> Result<(SnakeGame, GameRng), SaveError> {
+        if self.version != SAVE_VERSION {
+            return Err(SaveError::Version(self.version));
+        }
+        self.replay.validate().map_err(SaveError::Level)?;
+        let (mut game, mut rng) = self.replay.new_game();
+        for tick in 0..self.replay.inputs.len() {
+            if game.is_over() {
+                return Err(SaveError::Mismatch);
+            }
+            game.step_all(&self.replay.inputs_at(tick), &mut *rng);
+        }
+        if game.is_over() {
+            return Err(SaveError::Over);
+        }
+        if Snapshot::of(&game, &rng) != self.snapshot {
+            return Err(SaveError::Mismatch);
+        }
+        Ok((game, rng))
+    }
+}
+
+pub fn save_path() -> PathBuf {
+    crate::data_dir().join("save.ron")
+}
+
+/// Reads and checks the saved game at `path`, if there is one. A save that cannot be
+/// resumed is renamed with a `.bak` extension, so that the menu stops offering it.
+fn check_saved_game(path: &Path) -> Option<SavedGame> {
+    let error = match SavedGame::load(path) {
+        Ok(saved) => match saved.restore() {
+            Ok(_) => return Some(saved),
+            Err(e) => e,
+        },
+        Err(SaveError::Io(e)) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => e,
+    };
+    let backup = path.with_extension("ron.bak");
+    warn!(
+        "{}: {error}, moving it to {}",
+        path.display(),
+        backup.display()
+    );
+    if let Err(e) = fs::rename(path, &backup) {
+        error!("Cannot move {}: {e}", path.display());
+    }
+    None
+}
+
+/// The saved game the `Continue` button of the menu resumes. The file is checked once,
+/// when the game starts, and this is kept up to date as games are saved and resumed.
+#[derive(Default, Resource, Deref, DerefMut)]
+pub struct SaveSlot(pub Option<SavedGame>);
+
+pub fn load_saved_game(mut commands: Commands) {
+    commands.insert_resource(SaveSlot(check_saved_game(&save_path())));
+}
+
+/// The saved game picked with the `Continue` button of the menu, resumed by the next
+/// game.
+#[derive(Resource)]
+pub struct Resume(pub SavedGame);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::REFRESH_RATE;
+    use crate::difficulty::Pace;
+
+    /// A game on a 9x9 grid saved after two moves.
+    fn saved() -> SavedGame {
+        let game = SnakeGame::new(9, 9, SnakeDirection::Up, &mut *GameRng::new(3));
+        let mut replay = Replay::new(3, &game, Pace::default(), REFRESH_RATE);
+        let (mut game, mut rng) = replay.new_game();
+        for _ in 0..2 {
+            replay.push(&[None]);
+            game.step_all(&[None], &mut *rng);
+        }
+        SavedGame {
+            version: SAVE_VERSION,
+            snapshot: Snapshot::of(&game, &rng),
+            replay,
+            timer_elapsed: 0.0,
+            bot: None,
+            autopilot: false,
+            assisted: false,
+            daily: None,
+        }
+    }
+
+    #[test]
+    fn restores_the_saved_state() {
+        let saved = saved();
+        let (game, rng) = saved.restore().unwrap();
+        assert_eq!(Snapshot::of(&game, &rng), saved.snapshot);
+    }
+
+    #[test]
+    fn rejects_mismatched_snapshots() {
+        let mut scored = saved();
+        scored.snapshot.snakes[0].score += 1;
+        assert!(matches!(scored.restore(), Err(SaveError::Mismatch)));
+
+        let mut drawn = saved();
+        drawn.snapshot.rng_words += 1;
+        assert!(matches!(drawn.restore(), Err(SaveError::Mismatch)));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut saved = saved();
+        saved.version = SAVE_VERSION + 1;
+        assert!(matches!(saved.restore(), Err(SaveError::Version(_))));
+    }
+
+    #[test]
+    fn moves_saves_that_cannot_be_resumed_aside() {
+        let dir = std::env::temp_dir().join(format!("snake-save-{}", std::process::id()));
+        let path = dir.join("save.ron");
+        saved().save(&path).unwrap();
+        assert!(check_saved_game(&path).is_some());
+
+        let mut tampered = saved();
+        tampered.snapshot.snakes[0].score += 1;
+        tampered.save(&path).unwrap();
+        assert!(check_saved_game(&path).is_none());
+        assert!(!path.exists());
+        assert!(path.with_extension("ron.bak").exists());
+        assert!(check_saved_game(&path).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+//! The high-score tables, kept across launches.
+//!
+//! There is one table for each game mode, grid size, speed, pace and ruleset, with or
+//! without power-ups, saved in the data directory with the other [`persist`] files.
+
+use crate::constants::REFRESH_RATE;
+use crate::difficulty::Pace;
+use crate::persist;
+use crate::replay::Replay;
+use crate::rules::{Boundary, Ruleset, SnakeGame};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Number of scores kept in each table.
+pub const HIGH_SCORE_COUNT: usize = 10;
+pub const INITIALS_LEN: usize = 3;
+
+/// The games whose scores are compared with each other.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScoreCategory {
+    pub boundary: Boundary,
+    pub width: usize,
+    pub height: usize,
+    /// Tables saved before the difficulties were added are the ones of normal games.
+    #[serde(default)]
+    pub pace: Pace,
+    #[serde(default)]
+    pub power_ups: bool,
+    #[serde(default)]
+    pub ruleset: Ruleset,
+    /// The seconds of time-attack games, `0` for the other rulesets.
+    #[serde(default)]
+    pub time_limit: u32,
+    /// The moves per second of the configuration or of the settings, which the pace
+    /// scales. Tables saved before it was kept are the ones of the default speed.
+    #[serde(default = "default_refresh_rate")]
+    pub refresh_rate: f32,
+}
+
+pub(crate) fn default_refresh_rate() -> f32 {
+    REFRESH_RATE
+}
+
+impl ScoreCategory {
+    pub fn of(game: &SnakeGame, pace: Pace, refresh_rate: f32) -> Self {
+        ScoreCategory {
+            boundary: game.boundary(),
+            width: game.width(),
+            height: game.height(),
+            pace,
+            power_ups: game.has_power_ups(),
+            ruleset: game.ruleset(),
+            time_limit: game.time_limit().map_or(0, |limit| limit.seconds),
+            refresh_rate,
+        }
+    }
+
+    /// The pace of the games, with their base speed when it is not the default one.
+    pub fn pace_name(&self) -> String {
+        match self.refresh_rate == REFRESH_RATE {
+            true => self.pace.name(),
+            false => format!("{} {} moves/s", self.pace.name(), self.refresh_rate),
+        }
+    }
+
+    /// The ruleset of the games, with their length for time-attack ones.
+    pub fn ruleset_name(&self) -> String {
+        match self.ruleset {
+            Ruleset::TimeAttack => format!("{} {} s", self.ruleset.name(), self.time_limit),
+            ruleset => ruleset.name().to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub initials: String,
+    pub score: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ScoreTable {
+    category: ScoreCategory,
+    /// Best score first.
+    scores: Vec<HighScore>,
+}
+
+#[derive(Clone, Debug, Default, Resource, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HighScores {
+    tables: Vec<ScoreTable>,
+}
+
+/// Keeps the letters and digits of `initials`, in upper case, up to [`INITIALS_LEN`].
+pub fn clean_initials(initials: &str) -> String {
+    initials
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .take(INITIALS_LEN)
+        .collect()
+}
+
+impl HighScores {
+    /// The scores of `category`, best first.
+    pub fn scores(&self, category: &ScoreCategory) -> &[HighScore] {
+        self.tables
+            .iter()
+            .find(|table| table.category == *category)
+            .map_or(&[], |table| &table.scores)
+    }
+
+    pub fn best(&self, category: &ScoreCategory) -> usize {
+        self.scores(category).first().map_or(0, |entry| entry.score)
+    }
+
+    /// Whether `score` would make it into the table of `category`.
+    pub fn qualifies(&self, category: &ScoreCategory, score: usize) -> bool {
+        let scores = self.scores(category);
+        score > 0
+            && (scores.len() < HIGH_SCORE_COUNT || scores.last().is_some_and(|s| score > s.score))
+    }
+
+    /// Adds `entry` to the table of `category` and returns its rank, starting from 0, or
+    /// `None` if it is not good enough. Older scores stay ahead of equal new ones.
+    pub fn insert(&mut self, category: ScoreCategory, entry: HighScore) -> Option<usize> {
+        if !self.qualifies(&category, entry.score) {
+            return None;
+        }
+        let index = match self.tables.iter().position(|t| t.category == category) {
+            Some(index) => index,
+            None => {
+                self.tables.push(ScoreTable {
+                    category,
+                    scores: vec![],
+                });
+                self.tables.len() - 1
+            }
+        };
+        let scores = &mut self.tables[index].scores;
+        let rank = scores
+            .iter()
+            .position(|s| s.score < entry.score)
+            .unwrap_or(scores.len());
+        scores.insert(rank, entry);
+        scores.truncate(HIGH_SCORE_COUNT);
+        Some(rank)
+    }
+
+    /// Sorts the tables and drops what could not have been saved by the game, in case
+    /// the file was edited by hand.
+    fn sanitize(&mut self) {
+        let mut tables: Vec<ScoreTable> = vec![];
+        for mut table in std::mem::take(&mut self.tables) {
+            for entry in &mut table.scores {
+                entry.initials = clean_initials(&entry.initials);
+            }
+            match tables.iter_mut().find(|t| t.category == table.category) {
+                Some(existing) => existing.scores.append(&mut table.scores),
+                None => tables.push(table),
+            }
+        }
+        for table in &mut tables {
+            table.scores.retain(|entry| entry.score > 0);
+            table
+                .scores
+                .sort_by_key(|entry| std::cmp::Reverse(entry.score));
+            table.scores.truncate(HIGH_SCORE_COUNT);
+        }
+        tables.retain(|table| !table.scores.is_empty());
+        self.tables = tables;
+    }
+
+    /// Reads the high scores at `path`, starting with empty tables if there is no such
+    /// file.
+    pub fn load_or_recover(path: impl AsRef<Path>) -> Self {
+        let mut high_scores: HighScores = persist::load_or_recover(path);
+        high_scores.sanitize();
+        high_scores
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        persist::save_atomic(self, path)
+    }
+}
+
+pub fn high_scores_path() -> PathBuf {
+    crate::data_dir().join("high_scores.ron")
+}
+
+pub fn load_high_scores(mut commands: Commands) {
+    commands.insert_resource(HighScores::load_or_recover(high_scores_path()));
+}
+
+/// A score waiting for the initials of the player on the results screen, to go into its
+/// table or to the leaderboard server.
+#[derive(Debug, Resource)]
+pub struct NewHighScore {
+    pub category: ScoreCategory,
+    pub score: usize,
+    pub initials: String,
+    /// The score makes it into the local table.
+    pub local: bool,
+    /// The game to send to the leaderboard server, if one is configured.
+    pub replay: Option<Replay>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::Difficulty;
+
+    const CATEGORY: ScoreCategory = ScoreCategory {
+        boundary: Boundary::Walls,
+        width: 17,
+        height: 17,
+        pace: Pace {
+            difficulty: Difficulty::Normal,
+            accelerate: false,
+        },
+        power_ups: false,
+        ruleset: Ruleset::Standard,
+        time_limit: 0,
+        refresh_rate: REFRESH_RATE,
+    };
+
+    fn entry(initials: &str, score: usize) -> HighScore {
+        HighScore {
+            initials: initials.to_string(),
+            score,
+        }
+    }
+
+    #[test]
+    fn inserts_scores_in_order() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(CATEGORY, entry("AAA", 0)), None);
+        assert_eq!(high_scores.insert(CATEGORY, entry("AAA", 5)), Some(0));
+        assert_eq!(high_scores.insert(CATEGORY, entry("BBB", 9)), Some(0));
+        // Older scores stay ahead of equal ones
+        assert_eq!(high_scores.insert(CATEGORY, entry("CCC", 5)), Some(2));
+        let initials: Vec<_> = high_scores
+            .scores(&CATEGORY)
+            .iter()
+            .map(|entry| entry.initials.as_str())
+            .collect();
+        assert_eq!(initials, ["BBB", "AAA", "CCC"]);
+        assert_eq!(high_scores.best(&CATEGORY), 9);
+
+        let other = ScoreCategory {
+            boundary: Boundary::Wrap,
+            ..CATEGORY
+        };
+        assert!(high_scores.scores(&other).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_best_scores() {
+        let mut high_scores = HighScores::default();
+        for score in 1..=HIGH_SCORE_COUNT {
+            high_scores.insert(CATEGORY, entry("AAA", score));
+        }
+        assert!(!high_scores.qualifies(&CATEGORY, 1));
+        assert_eq!(high_scores.insert(CATEGORY, entry("BBB", 2)), Some(9));
+        let scores = high_scores.scores(&CATEGORY);
+        assert_eq!(scores.len(), HIGH_SCORE_COUNT);
+        assert_eq!(scores.last(), Some(&entry("BBB", 2)));
+    }
+
+    #[test]
+    fn sanitizes_edited_files() {
+        let mut high_scores = HighScores::default();
+        let table = |scores: Vec<HighScore>| ScoreTable {
+            category: CATEGORY,
+            scores,
+        };
+        high_scores.tables = vec![
+            table(vec![entry("a-b", 3), entry("ZZZ", 0)]),
+            table((0..12).map(|score| entry("long name", score)).collect()),
+        ];
+        high_scores.sanitize();
+        assert_eq!(high_scores.tables.len(), 1);
+        let scores = high_scores.scores(&CATEGORY);
+        assert_eq!(scores.len(), HIGH_SCORE_COUNT);
+        assert_eq!(scores[0], entry("LON", 11));
+        assert!(scores.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        // Entries with equal scores keep their order
+        assert_eq!(scores[8..], [entry("AB", 3), entry("LON", 3)]);
+        assert_eq!(clean_initials("j.d 2x"), "JD2");
+    }
+}
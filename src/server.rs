@@ -0,0 +1,320 @@
+//! The leaderboard server run by the `snake-server` binary.
+//!
+//! A few worker threads handle the requests, so that the replays played again by some
+//! of them never hold the others up, and the scores are kept in an SQLite database along
+//! with their replays.
+
+use crate::constants::REFRESH_RATE;
+use crate::difficulty::Pace;
+use crate::leaderboard::*;
+use crate::rules::Ruleset;
+use crate::scores::ScoreCategory;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Largest request body read: the inputs of the longest games, none of which takes more
+/// than 8 bytes (`"Right",`), and room for the rest of the replay.
+const MAX_BODY_SIZE: u64 = MAX_REPLAY_TICKS as u64 * 8 + 1024 * 1024;
+/// Requests handled at the same time.
+const WORKERS: usize = 4;
+/// Most rankings answered at once.
+const MAX_RANKINGS: usize = 100;
+
+pub struct Store {
+    connection: Connection,
+}
+
+/// The columns added since the first version, with the statements adding them. The
+/// scores of older databases are the ones of normal standard games without power-ups,
+/// at the default speed.
+const MIGRATIONS: [(&str, &str); 4] = [
+    (
+        "difficulty",
+        "ALTER TABLE scores ADD COLUMN difficulty TEXT NOT NULL DEFAULT 'normal';
+        ALTER TABLE scores ADD COLUMN accelerate INTEGER NOT NULL DEFAULT 0;",
+    ),
+    (
+        "power_ups",
+        "ALTER TABLE scores ADD COLUMN power_ups INTEGER NOT NULL DEFAULT 0;",
+    ),
+    (
+        "ruleset",
+        "ALTER TABLE scores ADD COLUMN ruleset TEXT NOT NULL DEFAULT 'standard';
+        ALTER TABLE scores ADD COLUMN time_limit INTEGER NOT NULL DEFAULT 0;",
+    ),
+    (
+        "refresh_rate",
+        "ALTER TABLE scores ADD COLUMN refresh_rate REAL NOT NULL DEFAULT 7.0;",
+    ),
+];
+
+/// Matches the scores of a category, whose values are bound first, see
+/// [`category_values`].
+const CATEGORY_FILTER: &str = "boundary = ?1 AND width = ?2 AND height = ?3
+    AND difficulty = ?4 AND accelerate = ?5 AND power_ups = ?6
+    AND ruleset = ?7 AND time_limit = ?8 AND refresh_rate = ?9";
+
+fn category_values(category: &ScoreCategory) -> Vec<Value> {
+    vec![
+        boundary_name(category.boundary).to_string().into(),
+        (category.width as i64).into(),
+        (category.height as i64).into(),
+        difficulty_name(category.pace.difficulty).to_string().into(),
+        category.pace.accelerate.into(),
+        category.power_ups.into(),
+        ruleset_name(category.ruleset).to_string().into(),
+        i64::from(category.time_limit).into(),
+        f64::from(category.refresh_rate).into(),
+    ]
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS scores (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                score INTEGER NOT NULL,
+                boundary TEXT NOT NULL,
+                width INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                seed INTEGER NOT NULL,
+                replay TEXT NOT NULL,
+                submitted_at INTEGER NOT NULL
+            );",
+        )?;
+        for (column, statements) in MIGRATIONS {
+            let exists: bool = connection.query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('scores') WHERE name = ?1",
+                [column],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                connection.execute_batch(statements)?;
+            }
+        }
+        connection.execute_batch(
+            "DROP INDEX IF EXISTS scores_by_category;
+            DROP INDEX IF EXISTS scores_by_category_and_pace;
+            DROP INDEX IF EXISTS scores_by_full_category;
+            DROP INDEX IF EXISTS scores_by_ranking;
+            CREATE INDEX IF NOT EXISTS scores_by_ranking
+                ON scores (boundary, width, height, difficulty, accelerate, power_ups,
+                    ruleset, time_limit, refresh_rate, score DESC, id);",
+        )?;
+        Ok(Store { connection })
+    }
+
+    /// Adds a verified score to its table and returns its rank, older scores staying
+    /// ahead of equal new ones.
+    pub fn insert(
+        &self,
+        category: &ScoreCategory,
+        submission: &Submission,
+    ) -> rusqlite::Result<usize> {
+        let replay = ron::to_string(&submission.replay)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut values = category_values(category);
+        values.extend([
+            submission.name.clone().into(),
+            (submission.score as i64).into(),
+            // SQLite integers are signed, the bits of the seed are kept as they are
+            (submission.replay.seed as i64).into(),
+            replay.into(),
+            (timestamp as i64).into(),
+        ]);
+        self.connection.execute(
+            "INSERT INTO scores (boundary, width, height, difficulty, accelerate, power_ups,
+                ruleset, time_limit, refresh_rate, name, score, seed, replay, submitted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params_from_iter(&values),
+        )?;
+        // The new score comes after the ones it ties with
+        let mut values = category_values(category);
+        values.push((submission.score as i64).into());
+        let rank: i64 = self.connection.query_row(
+            &format!("SELECT COUNT(*) FROM scores WHERE {CATEGORY_FILTER} AND score >= ?10"),
+            params_from_iter(&values),
+            |row| row.get(0),
+        )?;
+        Ok(rank as usize)
+    }
+
+    /// The best `limit` scores of `category`.
+    pub fn rankings(
+        &self,
+        category: &ScoreCategory,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<Ranking>> {
+        let mut statement = self.connection.prepare_cached(&format!(
+            "SELECT name, score, seed FROM scores
+            WHERE {CATEGORY_FILTER}
+            ORDER BY score DESC, id
+            LIMIT ?10"
+        ))?;
+        let mut values = category_values(category);
+        values.push((limit as i64).into());
+        let rows = statement.query_map(params_from_iter(&values), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+        rows.enumerate()
+            .map(|(i, row)| {
+                let (name, score, seed) = row?;
+                Ok(Ranking {
+                    rank: i + 1,
+                    name,
+                    score: score as usize,
+                    seed: seed as u64,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Answers the requests sent to `address` until the process is killed.
+pub fn serve(address: &str, store: Store) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let server = Server::http(address)?;
+    println!("Listening on http://{}", server.server_addr());
+    let store = Mutex::new(store);
+    thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| {
+                for mut request in server.incoming_requests() {
+                    let response = handle(&store, &mut request);
+                    if let Err(e) = request.respond(response) {
+                        eprintln!("error: cannot answer a request: {e}");
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+type JsonResponse = Response<Cursor<Vec<u8>>>;
+
+fn json(status: u16, body: &impl serde::Serialize) -> JsonResponse {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    Response::from_data(body)
+        .with_status_code(status)
+        .with_header(header)
+}
+
+fn error(status: u16, error: impl ToString) -> JsonResponse {
+    json(
+        status,
+        &ErrorResponse {
+            error: error.to_string(),
+        },
+    )
+}
+
+/// Answers `request`, only holding `store` for its queries.
+fn handle(store: &Mutex<Store>, request: &mut Request) -> JsonResponse {
+    // The queries cannot leave the database half updated
+    let store = || store.lock().unwrap_or_else(PoisonError::into_inner);
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    match (request.method(), path) {
+        (Method::Post, "/scores") => {
+            let mut body = String::new();
+            let reader = request.as_reader().take(MAX_BODY_SIZE);
+            if let Err(e) = { reader }.read_to_string(&mut body) {
+                return error(400, e);
+            }
+            let submission: Submission = match serde_json::from_str(&body) {
+                Ok(submission) => submission,
+                Err(e) => return error(400, e),
+            };
+            let category = match verify(&submission) {
+                Ok(category) => category,
+                Err(rejection) => {
+                    println!("Rejected a score of {}: {rejection}", submission.name);
+                    return error(422, rejection);
+                }
+            };
+            match store().insert(&category, &submission) {
+                Ok(rank) => {
+                    println!(
+                        "{} scored {} on {}x{} ({}, {}, {}), rank {rank}",
+                        submission.name,
+                        submission.score,
+                        category.width,
+                        category.height,
+                        boundary_name(category.boundary),
+                        category.pace_name(),
+                        category.ruleset_name(),
+                    );
+                    json(201, &Submitted { rank })
+                }
+                Err(e) => error(500, e),
+            }
+        }
+        (Method::Get, "/scores") => {
+            let Some((category, limit)) = parse_query(query) else {
+                return error(400, "expected boundary, width and height parameters");
+            };
+            match store().rankings(&category, limit) {
+                Ok(rankings) => json(200, &rankings),
+                Err(e) => error(500, e),
+            }
+        }
+        _ => error(404, "not found"),
+    }
+}
+
+/// Reads the category and the optional limit of a rankings query, the games being
+/// normal standard ones without power-ups, at the default speed, unless the query says
+/// otherwise.
+fn parse_query(query: &str) -> Option<(ScoreCategory, usize)> {
+    let (mut boundary, mut width, mut height) = (None, None, None);
+    let mut pace = Pace::default();
+    let mut power_ups = false;
+    let (mut ruleset, mut time_limit) = (Ruleset::default(), 0);
+    let mut refresh_rate = REFRESH_RATE;
+    let mut limit = RANKINGS_LIMIT;
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=')?;
+        match key {
+            "boundary" => boundary = parse_boundary(value),
+            "width" => width = value.parse().ok(),
+            "height" => height = value.parse().ok(),
+            "difficulty" => pace.difficulty = parse_difficulty(value)?,
+            "accelerate" => pace.accelerate = value.parse().ok()?,
+            "power_ups" => power_ups = value.parse().ok()?,
+            "ruleset" => ruleset = parse_ruleset(value)?,
+            "time_limit" => time_limit = value.parse().ok()?,
+            "refresh_rate" => refresh_rate = value.parse().ok()?,
+            "limit" => limit = value.parse::<usize>().ok()?.min(MAX_RANKINGS),
+            _ => {}
+        }
+    }
+    let category = ScoreCategory {
+        boundary: boundary?,
+        width: width?,
+        height: height?,
+        pace,
+        power_ups,
+        ruleset,
+        time_limit,
+        refresh_rate,
+    };
+    Some((category, limit))
+}
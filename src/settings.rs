@@ -0,0 +1,236 @@
+//! The settings picked in the `Settings` screen.
+//!
+//! They are kept in `settings.ron` in the data directory and applied as soon as they
+//! change. The grid size, the speed and the colors override the configuration file,
+//! until they are set back to `Default`. Options given on the command line win over the
+//! settings when the game starts.
+
+#![allow(clippy::too_many_arguments)]
+
+use crate::audio::AudioMixer;
+use crate::config::{ColorConfig, GameConfig};
+use crate::persist;
+use bevy::prelude::*;
+use bevy::window::{PresentMode, WindowMode};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The volumes offered by the `Settings` screen, with their names.
+pub const VOLUMES: [(f32, &str); 4] = [(0.0, "Off"), (0.3, "Low"), (0.6, "Mid"), (1.0, "Full")];
+
+#[derive(Clone, Debug, PartialEq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The volume of everything, from 0 to 1, scaling the volumes below.
+    pub master_volume: f32,
+    /// The volume of the music.
+    pub music_volume: f32,
+    /// The volume of the sounds of the game.
+    pub sfx_volume: f32,
+    /// The volume of the sounds of the buttons.
+    pub ui_volume: f32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// The size of open grids, the configured one if `None`.
+    pub grid: Option<GridSize>,
+    /// How fast the snakes move, the configured `refresh_rate` if `None`.
+    pub speed: Option<Speed>,
+    /// The colors of the game, the configured ones if `None`.
+    pub theme: Option<Theme>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            master_volume: 1.0,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+            ui_volume: 1.0,
+            fullscreen: false,
+            vsync: true,
+            grid: None,
+            speed: None,
+            theme: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GridSize {
+    Small,
+    Large,
+}
+
+impl GridSize {
+    pub fn size(self) -> (usize, usize) {
+        match self {
+            GridSize::Small => (12, 12),
+            GridSize::Large => (25, 25),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Speed {
+    Slow,
+    Fast,
+}
+
+impl Speed {
+    /// The number of moves of the snake per second.
+    pub fn refresh_rate(self) -> f32 {
+        match self {
+            Speed::Slow => 5.0,
+            Speed::Fast => 10.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Theme {
+    Ocean,
+    Contrast,
+}
+
+impl Theme {
+    pub fn colors(self) -> ColorConfig {
+        match self {
+            Theme::Ocean => ColorConfig {
+                background: Color::rgb(0.04, 0.1, 0.18),
+                wall: Color::rgb(0.18, 0.37, 0.56),
+                head: Color::rgb(0.0, 0.9, 1.0),
+                tail: Color::rgb(0.0, 0.48, 0.54),
+                rival_head: Color::rgb(1.0, 0.69, 0.0),
+                rival_tail: Color::rgb(0.54, 0.38, 0.0),
+                apple: Color::rgb(1.0, 0.25, 0.38),
+            },
+            Theme::Contrast => ColorConfig {
+                background: Color::BLACK,
+                wall: Color::WHITE,
+                head: Color::rgb(1.0, 1.0, 0.0),
+                tail: Color::rgb(0.5, 0.5, 0.0),
+                rival_head: Color::rgb(0.0, 1.0, 1.0),
+                rival_tail: Color::rgb(0.0, 0.5, 0.5),
+                apple: Color::rgb(1.0, 0.0, 1.0),
+            },
+        }
+    }
+}
+
+/// The configuration before the settings, that `Default` brings back.
+#[derive(Resource)]
+pub struct BaseConfig(pub GameConfig);
+
+impl Settings {
+    /// Overrides the grid size, the speed and the colors of `config`.
+    pub fn apply(&self, config: &mut GameConfig) {
+        if let Some(grid) = self.grid {
+            (config.grid_width, config.grid_height) = grid.size();
+        }
+        if let Some(speed) = self.speed {
+            config.refresh_rate = speed.refresh_rate();
+        }
+        if let Some(theme) = self.theme {
+            config.colors = theme.colors();
+        }
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        match self.fullscreen {
+            true => WindowMode::BorderlessFullscreen,
+            false => WindowMode::Windowed,
+        }
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        match self.vsync {
+            true => PresentMode::AutoVsync,
+            false => PresentMode::AutoNoVsync,
+        }
+    }
+
+    /// Reads the settings at `path`, starting with the defaults if there is no such
+    /// file.
+    pub fn load_or_recover(path: impl AsRef<Path>) -> Self {
+        persist::load_or_recover(path)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        persist::save_atomic(self, path)
+    }
+}
+
+pub fn settings_path() -> PathBuf {
+    crate::data_dir().join("settings.ron")
+}
+
+/// Applies the settings that changed and saves them.
+pub fn apply_settings(
+    settings: Res<Settings>,
+    mut applied: Local<Option<Settings>>,
+    base: Res<BaseConfig>,
+    mut config: ResMut<GameConfig>,
+    mut clear_color: ResMut<ClearColor>,
+    mut windows: Query<&mut Window>,
+    mut mixer: ResMut<AudioMixer>,
+) {
+    let Some(previous) = applied.replace(settings.clone()) else {
+        // Applied when the game started
+        return;
+    };
+    let path = settings_path();
+    if let Err(e) = settings.save(&path) {
+        error!("Cannot save the settings to {}: {e}", path.display());
+    }
+
+    mixer.apply(&settings);
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.mode = settings.window_mode();
+        window.present_mode = settings.present_mode();
+    }
+    // Options given on the command line are lost when one of these changes
+    if (previous.grid, previous.speed, previous.theme)
+        != (settings.grid, settings.speed, settings.theme)
+    {
+        let mut new_config = base.0.clone();
+        settings.apply(&mut new_config);
+        *config = new_config;
+        clear_color.0 = config.colors.background;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_fall_back_to_the_defaults() {
+        let settings: Settings = ron::from_str("(music_volume: 0.3, speed: Some(Fast))").unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                music_volume: 0.3,
+                speed: Some(Speed::Fast),
+                ..default()
+            }
+        );
+    }
+
+    #[test]
+    fn overrides_the_configuration_until_set_back() {
+        let base = GameConfig::default();
+        let mut config = base.clone();
+        Settings::default().apply(&mut config);
+        assert_eq!(config.refresh_rate, base.refresh_rate);
+
+        let settings = Settings {
+            grid: Some(GridSize::Small),
+            speed: Some(Speed::Slow),
+            ..default()
+        };
+        settings.apply(&mut config);
+        assert_eq!((config.grid_width, config.grid_height), (12, 12));
+        assert_eq!(config.refresh_rate, Speed::Slow.refresh_rate());
+    }
+}
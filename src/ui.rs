@@ -1,8 +1,9 @@
 #![allow(clippy::type_complexity)]
+#![allow(clippy::too_many_arguments)]
 
 use bevy::prelude::*;
 
-use crate::{get_window_resolution, logic::WallLocation};
+use crate::{config::GameConfig, get_window_resolution, level::Level, logic::WallLocation};
 
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
 pub enum GameState {
@@ -10,12 +11,16 @@ pub enum GameState {
     Splash,
     Menu,
     Game,
+    Replay,
     Results,
+    Editor,
+    /// Passed through to start the running game over, its screen being set up anew.
+    Restart,
 }
 
 pub mod splash {
     use super::{despawn_screen, GameState};
-    use crate::{constants::SPLASH_SCREEN_DURATION, MENU_WIDTH};
+    use crate::{config::GameConfig, replay::ReplayPlayer, MENU_WIDTH};
     use bevy::prelude::*;
 
     pub struct SplashPlugin;
@@ -34,7 +39,11 @@ pub mod splash {
     #[derive(Resource, Deref, DerefMut)]
     struct SplashTimer(Timer);
 
-    fn splash_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    fn splash_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        config: Res<GameConfig>,
+    ) {
         let icon = asset_server.load("icon.png");
         commands
             .spawn((
@@ -61,7 +70,7 @@ pub mod splash {
                 });
             });
         commands.insert_resource(SplashTimer(Timer::from_seconds(
-            SPLASH_SCREEN_DURATION,
+            config.splash_screen_duration,
             TimerMode::Once,
         )));
     }
@@ -70,21 +79,38 @@ pub mod splash {
         mut game_state: ResMut<NextState<GameState>>,
         time: Res<Time>,
         mut timer: ResMut<SplashTimer>,
+        replay: Option<Res<ReplayPlayer>>,
     ) {
         if timer.tick(time.delta()).finished() {
-            game_state.set(GameState::Menu);
+            // A replay given on the command line is played right away
+            game_state.set(match replay {
+                Some(_) => GameState::Replay,
+                None => GameState::Menu,
+            });
         }
     }
 }
 
 pub mod game {
     use super::despawn_screen;
+    use super::editor::Playtest;
     use super::get_scoreboard_position;
+    use super::menu::{button_system, scrolling_menu_style, MenuState, ScrollingMenu};
     use super::GameState;
     use crate::constants::*;
+    use crate::daily::{record_daily, DailyGame, DailyResult};
+    use crate::difficulty::GamePace;
+    use crate::gamepad::gamepad_input;
+    use crate::input::{Action, InputMap};
+    use crate::level::{Level, LevelAsset};
     use crate::logic::*;
+    use crate::replay::*;
+    use crate::rules::{Ruleset, TimeLimit};
+    use crate::save::{save_path, Resume, SaveSlot, SavedGame, Snapshot, SAVE_VERSION};
     use crate::*;
     use bevy::prelude::*;
+    use bevy::window::WindowFocused;
+    use std::time::Duration;
 
     #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
     pub enum GameMode {
@@ -99,19 +125,70 @@ pub mod game {
         fn build(&self, app: &mut App) {
             app.add_state::<GameMode>()
                 .add_systems(OnEnter(GameState::Game), game_setup)
-                .add_systems(Update, handle_input.run_if(in_state(GameState::Game)))
+                .add_systems(OnEnter(GameState::Replay), game_setup)
+                .add_systems(
+                    Update,
+                    (
+                        // Not while a screen is shown over the pause screen
+                        (handle_input, gamepad_input, toggle_autopilot)
+                            .run_if(in_state(MenuState::Disabled)),
+                        record_daily.before(save_replay),
+                        save_replay,
+                        recolor_sprites.run_if(resource_changed::<GameConfig>()),
+                    )
+                        .run_if(in_state(GameState::Game)),
+                )
+                .add_systems(
+                    Update,
+                    pause_on_focus_loss
+                        .run_if(in_state(GameState::Game).and_then(in_state(GameMode::Running))),
+                )
+                .add_systems(
+                    Update,
+                    (replay_controls, end_replay).run_if(in_state(GameState::Replay)),
+                )
                 .add_systems(
                     FixedUpdate,
-                    (move_snake, check_for_collisions, update_scoreboard)
+                    (
+                        move_snake,
+                        check_for_collisions,
+                        update_speed,
+                        update_effects,
+                        update_clock,
+                        sync_sprites,
+                        sync_obstacles,
+                        update_scoreboard,
+                    )
                         .chain()
-                        .run_if(in_state(GameState::Game))
-                        .run_if(in_state(GameMode::Running)),
+                        .run_if(
+                            in_state(GameState::Game)
+                                .and_then(in_state(GameMode::Running))
+                                .or_else(in_state(GameState::Replay)),
+                        ),
                 )
                 .add_systems(OnEnter(GameMode::Pause), pause_setup)
+                .add_systems(
+                    Update,
+                    (button_system, pause_action)
+                        .run_if(in_state(GameState::Game).and_then(in_state(GameMode::Pause))),
+                )
+                .add_systems(
+                    OnExit(MenuState::Controls),
+                    show_pause_menu.run_if(in_state(GameState::Game)),
+                )
+                .add_systems(
+                    OnExit(MenuState::Settings),
+                    show_pause_menu.run_if(in_state(GameState::Game)),
+                )
                 .add_systems(OnExit(GameMode::Pause), despawn_screen::<OnPauseScreen>)
+                .add_systems(OnEnter(GameState::Restart), restart)
                 .add_systems(
                     OnExit(GameState::Game),
                     (despawn_screen::<OnGameScreen>, reset_state),
+                )
+                .add_systems(
+                    OnExit(GameState::Replay),
+                    (despawn_screen::<OnGameScreen>, reset_state, stop_replay),
                 );
         }
     }
@@ -123,52 +200,219 @@ pub mod game {
         mut commands: Commands,
         mut already_played: ResMut<AlreadyPlayed>,
         asset_server: Res<AssetServer>,
+        seed: Res<GameSeed>,
+        mut recorder: ResMut<ReplayRecorder>,
+        replay: Option<Res<ReplayPlayer>>,
+        config: Res<GameConfig>,
+        options: Res<GameOptions>,
+        levels: Res<Assets<LevelAsset>>,
+        playtest: Option<Res<Playtest>>,
+        input_map: Res<InputMap>,
+        mut timer: ResMut<GameTimer>,
+        mut windows: Query<&mut Window>,
+        daily: Option<Res<DailyGame>>,
+        resume: Option<Res<Resume>>,
     ) {
         if !already_played.0 {
             already_played.0 = true;
         }
+        commands.remove_resource::<DailyResult>();
+        commands.remove_resource::<Resume>();
+        // A daily challenge is played with its own options
+        let options = daily.as_ref().map_or(&*options, |daily| &daily.options);
+        // The menu only offers saves that can be restored
+        let (saved, restored) = match resume
+            .as_ref()
+            .map(|resume| (&resume.0, resume.0.restore()))
+        {
+            Some((saved, Ok(restored))) => (Some(saved), Some(restored)),
+            Some((_, Err(e))) => {
+                error!("Cannot resume the saved game: {e}");
+                (None, None)
+            }
+            None => (None, None),
+        };
 
-        // The snake
-        commands.spawn((
-            SpriteBundle {
-                transform: Transform {
-                    translation: Vec3::new(0.0, 0.0, 1.0),
-                    scale: SNAKE_SIZE.extend(0.0),
+        let pace = match (&replay, saved) {
+            (Some(player), _) => player.replay.pace,
+            (None, Some(saved)) => saved.replay.pace,
+            (None, None) => options.pace.for_ruleset(options.ruleset),
+        };
+        let mut interval = pace.tick_interval(&config, 0);
+        let (game, rng) = match (&replay, restored) {
+            (Some(player), _) => {
+                let (game, rng) = player.replay.new_game();
+                (Game(game), rng)
+            }
+            (None, Some((game, rng))) => {
+                interval = tick_interval(&game, pace, &config);
+                (Game(game), rng)
+            }
+            (None, None) => {
+                let mut rng = match &daily {
+                    Some(daily) => GameRng::new(daily.challenge.seed),
+                    None => GameRng::from_seed(&seed),
+                };
+                let versus = options.versus && playtest.is_none();
+                let level = playtest
+                    .as_ref()
+                    .map(|playtest| playtest.0.clone())
+                    .or_else(|| {
+                        options
+                            .level
+                            .and_then(|id| levels.get(id))
+                            .map(|l| l.0.clone())
+                    })
+                    .unwrap_or_else(|| {
+                        let (width, height) = (config.grid_width, config.grid_height);
+                        match versus {
+                            true => Level::open_versus(width, height, INITIAL_SNAKE_DIRECTION),
+                            false => Level::open(width, height, INITIAL_SNAKE_DIRECTION),
+                        }
+                    });
+                let versus = versus
+                    && match level.validate_versus() {
+                        Ok(()) => true,
+                        Err(e) => {
+                            warn!("{} cannot be played by two: {e}", level.name);
+                            false
+                        }
+                    };
+                let time_limit = (options.ruleset == Ruleset::TimeAttack).then(|| {
+                    TimeLimit::new(options.time_limit, pace.start_rate(config.refresh_rate))
+                });
+                let game = new_game(
+                    level,
+                    options.boundary,
+                    versus,
+                    options.power_ups,
+                    options.ruleset,
+                    time_limit,
+                    &mut rng,
+                );
+                (game, rng)
+            }
+        };
+        info!("Starting a game with seed {}", rng.seed());
+        timer.set_duration(Duration::from_secs_f32(interval));
+        timer.reset();
+        if let Some(saved) = saved {
+            timer.set_elapsed(Duration::from_secs_f32(saved.timer_elapsed));
+        }
+        let boundary = game.boundary();
+        // The bot plays the second snake of versus games, the only one otherwise
+        let bot = match (&replay, &playtest, options.bot) {
+            (None, None, Some(kind)) => Some((kind, game.snakes().len() - 1)),
+            _ => None,
+        };
+
+        // The window fits the grid of the level
+        let mut window = windows.single_mut();
+        let (width, height) = get_window_resolution(&config, game.width(), game.height());
+        window.resolution.set(width, height);
+
+        recorder.0 = match (&replay, saved) {
+            (Some(_), _) => None,
+            (None, Some(saved)) => Some(saved.replay.clone()),
+            (None, None) => Some(Replay::new(rng.seed(), &game, pace, config.refresh_rate)),
+        };
+
+        // The snakes
+        for (i, snake) in game.snakes().iter().enumerate() {
+            let mut head = commands.spawn((
+                SpriteBundle {
+                    transform: Transform {
+                        translation: cell_to_world(snake.head(), game.level(), &config).extend(1.0),
+                        scale: config.snake_size.extend(0.0),
+                        ..default()
+                    },
+                    sprite: Sprite {
+                        color: config.colors.snake(i).0,
+                        ..default()
+                    },
                     ..default()
                 },
-                sprite: Sprite {
-                    color: HEAD_COLOR,
-                    ..default()
+                Head,
+                Tail,
+                OnGameScreen,
+                Movement(snake.direction()),
+                Player(i),
+                SnakeBody::default(),
+                PlayerInput::default(),
+                Scoreboard {
+                    value: snake.score(),
                 },
-                ..default()
-            },
-            Head,
-            Tail,
+            ));
+            let autopilot = match saved {
+                // The first snake is handed back as it was saved
+                Some(saved) if i == 0 => saved.autopilot.then(|| options.bot.unwrap_or_default()),
+                _ => bot.filter(|(_, player)| *player == i).map(|(kind, _)| kind),
+            };
+            if let Some(kind) = autopilot {
+                head.insert((Autopilot(kind.create()), Assisted));
+            }
+            if saved.is_some_and(|saved| saved.assisted) && i == 0 {
+                head.insert(Assisted);
+            }
+        }
+
+        // The walls
+        commands.spawn((
+            WallBundle::new(WallLocation::Top, game.level(), boundary, &config),
+            OnGameScreen,
+        ));
+        commands.spawn((
+            WallBundle::new(WallLocation::Bottom, game.level(), boundary, &config),
+            OnGameScreen,
+        ));
+        commands.spawn((
+            WallBundle::new(WallLocation::Left, game.level(), boundary, &config),
+            OnGameScreen,
+        ));
+        commands.spawn((
+            WallBundle::new(WallLocation::Right, game.level(), boundary, &config),
             OnGameScreen,
-            Movement(INITIAL_SNAKE_DIRECTION),
         ));
 
-        // The walls
-        commands.spawn((WallBundle::new(WallLocation::Top), OnGameScreen));
-        commands.spawn((WallBundle::new(WallLocation::Bottom), OnGameScreen));
-        commands.spawn((WallBundle::new(WallLocation::Left), OnGameScreen));
-        commands.spawn((WallBundle::new(WallLocation::Right), OnGameScreen));
+        // The obstacles of the level, the ones of survival games are added as they appear
+        for position in &game.level().obstacles {
+            commands.spawn((
+                obstacle_sprite(*position, game.level(), &config),
+                OnGameScreen,
+            ));
+        }
 
-        // A first apple
-        let location = loop {
-            let loc = gen_apple_location();
-            if loc != Vec2::splat(0.0) {
-                break loc;
+        // The portals
+        for (index, ends) in game.level().portals.iter().enumerate() {
+            for position in ends {
+                commands.spawn((
+                    SpriteBundle {
+                        transform: Transform {
+                            translation: cell_to_world(*position, game.level(), &config)
+                                .extend(0.0),
+                            scale: config.tile_size.extend(0.0),
+                            ..default()
+                        },
+                        sprite: Sprite {
+                            color: portal_color(index),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    OnGameScreen,
+                ));
             }
-        };
-        spawn_apple(&mut commands, location);
+        }
+
+        // The apple and the pickup, placed by `sync_sprites`
+        spawn_pickups(&mut commands, &config);
 
         commands
             .spawn((
                 NodeBundle {
                     style: Style {
                         width: Val::Percent(100.0),
-                        height: Val::Px(get_scoreboard_position()),
+                        height: Val::Px(get_scoreboard_position(game.level(), &config)),
                         justify_content: JustifyContent::Center,
                         align_items: AlignItems::End,
                         ..default()
@@ -181,125 +425,157 @@ pub mod game {
                 parent
                     .spawn(NodeBundle {
                         style: Style {
-                            flex_direction: FlexDirection::Column,
                             align_items: AlignItems::End,
+                            column_gap: Val::Px(SCOREBOARD_GAP),
                             ..default()
                         },
                         background_color: Color::BLACK.into(),
                         ..default()
                     })
                     .with_children(|parent| {
-                        parent.spawn(TextBundle::from_sections([
-                            TextSection::new(
-                                "Score = ".to_uppercase(),
-                                TextStyle {
-                                    font_size: SCOREBOARD_FONT_SIZE,
-                                    color: TEXT_COLOR,
-                                    font: asset_server.load("font.ttf"),
-                                },
-                            ),
-                            TextSection::from_style(TextStyle {
+                        for i in 0..game.snakes().len() {
+                            // Each player has the color of their snake in versus games
+                            let (label, color) = match game.is_versus() {
+                                true if bot.is_some_and(|(_, player)| player == i) => {
+                                    ("Bot = ".to_string(), config.colors.snake(i).0)
+                                }
+                                true => (format!("P{} = ", i + 1), config.colors.snake(i).0),
+                                false => ("Score = ".to_string(), TEXT_COLOR),
+                            };
+                            let style = TextStyle {
                                 font_size: SCOREBOARD_FONT_SIZE,
-                                color: TEXT_COLOR,
+                                color,
                                 font: asset_server.load("font.ttf"),
-                                ..default()
-                            }),
-                        ]));
+                            };
+                            parent.spawn((
+                                TextBundle::from_sections([
+                                    TextSection::new(label.to_uppercase(), style.clone()),
+                                    TextSection::from_style(style),
+                                ]),
+                                ScoreText(i),
+                            ));
+                        }
+                        let style = TextStyle {
+                            font_size: SCOREBOARD_FONT_SIZE,
+                            color: TEXT_COLOR,
+                            font: asset_server.load("font.ttf"),
+                        };
+                        parent.spawn((
+                            TextBundle::from_sections([
+                                TextSection::new("Speed = ".to_uppercase(), style.clone()),
+                                TextSection::new(format!("{:.1}", 1.0 / interval), style.clone()),
+                            ]),
+                            SpeedText,
+                        ));
+                        if let Some(limit) = game.time_limit() {
+                            parent.spawn((
+                                TextBundle::from_sections([
+                                    TextSection::new("Time = ".to_uppercase(), style.clone()),
+                                    TextSection::new(format!("{:.1}", limit.seconds as f32), style),
+                                ]),
+                                ClockText,
+                            ));
+                        }
                     });
             });
-    }
 
-    fn reset_state(
-        mut scoreboard: ResMut<Scoreboard>,
-        mut player_input: ResMut<PlayerInput>,
-        mut snake_body: ResMut<SnakeBody>,
-        mut timer: ResMut<GameTimer>,
-    ) {
-        scoreboard.value = 0;
-        snake_body.clear();
-        player_input.0 = vec![];
-        timer.reset();
-    }
+        // The effects of the pickups, under the grid
+        if game.has_power_ups() {
+            commands.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: EFFECTS_FONT_SIZE,
+                        color: RESULTS_TEXT_COLOR,
+                        font: asset_server.load("font.ttf"),
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(
+                        get_window_resolution(&config, game.width(), game.height()).1
+                            - get_scoreboard_position(game.level(), &config),
+                    ),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                })
+                .with_text_alignment(TextAlignment::Center),
+                EffectsText,
+                OnGameScreen,
+            ));
+        }
 
-    #[derive(Component)]
-    pub struct OnPauseScreen;
+        commands.insert_resource(game);
+        commands.insert_resource(rng);
+        commands.insert_resource(GamePace(pace));
 
-    pub fn pause_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-        commands
-            .spawn((
-                NodeBundle {
-                    style: Style {
-                        width: Val::Percent(100.0),
-                        height: Val::Percent(100.0),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..default()
+        if replay.is_some() {
+            commands.spawn((
+                TextBundle::from_section(
+                    format!(
+                        "Replay: {} pause, {} step, {} fast, backspace quit",
+                        input_map.key_names(Action::Pause),
+                        input_map.key_names(Action::Step),
+                        input_map.key_names(Action::FastForward),
+                    )
+                    .to_uppercase(),
+                    TextStyle {
+                        font_size: REPLAY_HELP_FONT_SIZE,
+                        color: RESULTS_TEXT_COLOR,
+                        font: asset_server.load("font.ttf"),
                     },
-                    background_color: BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.7)),
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(10.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
                     ..default()
-                },
-                OnPauseScreen,
-            ))
-            .with_children(|parent| {
-                parent.spawn(TextBundle::from_sections([
-                    TextSection::new(
-                        "Pause".to_uppercase(),
-                        TextStyle {
-                            font_size: SCOREBOARD_FONT_SIZE,
-                            color: TEXT_COLOR,
-                            font: asset_server.load("font.ttf"),
-                        },
-                    ),
-                    TextSection::from_style(TextStyle {
-                        font_size: SCOREBOARD_FONT_SIZE,
-                        color: TEXT_COLOR,
-                        ..default()
-                    }),
-                ]));
-            });
+                })
+                .with_text_alignment(TextAlignment::Center),
+                OnGameScreen,
+            ));
+        }
     }
-}
-
-pub mod menu {
-    use super::results::ResultsTimer;
-    use super::{despawn_screen, GameState};
-    use crate::{constants::*, AlreadyPlayed, ButtonHoveredSound, ButtonPressedSound};
-    use bevy::app::AppExit;
-    use bevy::prelude::*;
 
-    pub struct MenuPlugin;
+    fn restart(mut game_state: ResMut<NextState<GameState>>) {
+        game_state.set(GameState::Game);
+    }
 
-    impl Plugin for MenuPlugin {
-        fn build(&self, app: &mut App) {
-            app.add_systems(OnEnter(GameState::Menu), menu_setup)
-                .add_systems(
-                    Update,
-                    (menu_action, button_system).run_if(in_state(GameState::Menu)),
-                )
-                .add_systems(OnExit(GameState::Menu), despawn_screen::<OnMenuScreen>);
-        }
+    fn reset_state(mut timer: ResMut<GameTimer>, mut last_step: ResMut<LastStep>) {
+        last_step.0 = None;
+        timer.reset();
     }
 
     #[derive(Component)]
-    pub struct OnMenuScreen;
+    pub struct OnPauseScreen;
 
+    /// The column of buttons of the pause screen, hidden while the controls or the
+    /// settings are shown over it.
     #[derive(Component)]
-    pub struct SelectedOption;
+    struct PauseMenu;
 
     #[derive(Component)]
-    enum MenuButtonAction {
-        Play,
+    enum PauseButtonAction {
+        Resume,
+        /// Ends a zen game, which nothing else ends.
+        Finish,
+        Restart,
+        Settings,
+        Controls,
+        SaveAndQuit,
         Quit,
     }
 
-    pub fn menu_setup(
+    pub fn pause_setup(
         mut commands: Commands,
-        mut timer: ResMut<ResultsTimer>,
         asset_server: Res<AssetServer>,
-        already_played: Res<AlreadyPlayed>,
+        playtest: Option<Res<Playtest>>,
+        game: Res<Game>,
     ) {
         let button_style = Style {
-            width: Val::Px(BUTTON_WIDTH),
+            width: Val::Px(PAUSE_BUTTON_WIDTH),
             height: Val::Px(BUTTON_HEIGHT),
             margin: UiRect::all(Val::Px(BUTTON_MARGIN)),
             justify_content: JustifyContent::Center,
@@ -311,179 +587,2161 @@ pub mod menu {
             color: Color::WHITE,
             font: asset_server.load("font.ttf"),
         };
-
         commands
             .spawn((
                 NodeBundle {
                     style: Style {
                         width: Val::Percent(100.0),
                         height: Val::Percent(100.0),
-                        justify_content: JustifyContent::Center,
+                        flex_direction: FlexDirection::Column,
                         align_items: AlignItems::Center,
                         ..default()
                     },
+                    background_color: BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.7)),
                     ..default()
                 },
-                OnMenuScreen,
+                OnPauseScreen,
             ))
             .with_children(|parent| {
                 parent
-                    .spawn(NodeBundle {
-                        style: Style {
-                            flex_direction: FlexDirection::Column,
-                            align_items: AlignItems::Center,
+                    .spawn((
+                        NodeBundle {
+                            style: scrolling_menu_style(),
                             ..default()
                         },
-                        background_color: Color::BLACK.into(),
-                        ..default()
-                    })
+                        ScrollingMenu::default(),
+                        PauseMenu,
+                    ))
                     .with_children(|parent| {
-                        // Game name
                         parent.spawn(
                             TextBundle::from_section(
-                                "Snake".to_uppercase(),
+                                "Pause".to_uppercase(),
                                 TextStyle {
                                     font_size: MENU_TITLE_SIZE,
-                                    color: MENU_TEXT_COLOR,
+                                    color: TEXT_COLOR,
                                     font: asset_server.load("font.ttf"),
                                 },
                             )
                             .with_style(Style {
-                                margin: UiRect::all(Val::Px(50.0)),
+                                margin: UiRect::all(Val::Px(BUTTON_MARGIN * 2.0)),
                                 ..default()
                             }),
                         );
-
-                        let play_button_text = if already_played.0 { "Replay" } else { "Play" };
-                        // Play button
-                        parent
-                            .spawn((
-                                ButtonBundle {
-                                    style: button_style.clone(),
-                                    background_color: NORMAL_BUTTON.into(),
-                                    ..default()
-                                },
-                                MenuButtonAction::Play,
-                            ))
-                            .with_children(|parent| {
-                                parent.spawn(TextBundle::from_section(
-                                    play_button_text.to_uppercase(),
-                                    button_text_style.clone(),
-                                ));
-                            });
-
-                        // Quit button
-                        parent
-                            .spawn((
-                                ButtonBundle {
-                                    style: button_style,
-                                    background_color: NORMAL_BUTTON.into(),
-                                    ..default()
-                                },
-                                MenuButtonAction::Quit,
-                            ))
-                            .with_children(|parent| {
-                                parent.spawn(TextBundle::from_section(
-                                    "Quit".to_uppercase(),
-                                    button_text_style,
-                                ));
-                            });
+                        let mut buttons = vec![("Resume", PauseButtonAction::Resume)];
+                        if game.ruleset() == Ruleset::Zen {
+                            buttons.push(("Finish", PauseButtonAction::Finish));
+                        }
+                        buttons.extend([
+                            ("Restart", PauseButtonAction::Restart),
+                            ("Settings", PauseButtonAction::Settings),
+                            ("Controls", PauseButtonAction::Controls),
+                        ]);
+                        // Levels being tried out in the editor are not saved, and quitting
+                        // goes back to the editor
+                        match playtest {
+                            Some(_) => buttons.push(("Back to editor", PauseButtonAction::Quit)),
+                            None => buttons.extend([
+                                ("Save & Quit", PauseButtonAction::SaveAndQuit),
+                                ("Quit to menu", PauseButtonAction::Quit),
+                            ]),
+                        }
+                        for (text, action) in buttons {
+                            super::spawn_button(
+                                parent,
+                                &button_style,
+                                &button_text_style,
+                                text,
+                                action,
+                            );
+                        }
                     });
             });
-
-        timer.reset();
     }
 
-    fn button_system(
-        mut commands: Commands,
-        mut interaction_query: Query<
-            (&Interaction, &mut BackgroundColor, Option<&SelectedOption>),
+    /// Acts on the buttons of the pause screen. `Save & Quit` keeps the game paused if
+    /// it cannot be saved.
+    fn pause_action(
+        interaction_query: Query<
+            (&Interaction, &PauseButtonAction),
             (Changed<Interaction>, With<Button>),
         >,
-        hovered_sound: Res<ButtonHoveredSound>,
-        pressed_sound: Res<ButtonPressedSound>,
+        mut game: ResMut<Game>,
+        mut last_step: ResMut<LastStep>,
+        rng: Res<GameRng>,
+        timer: Res<GameTimer>,
+        mut recorder: ResMut<ReplayRecorder>,
+        (options, daily, playtest): (
+            Res<GameOptions>,
+            Option<Res<DailyGame>>,
+            Option<Res<Playtest>>,
+        ),
+        players: Query<(&Player, Has<Autopilot>, Has<Assisted>)>,
+        mut pause_menu: Query<&mut Visibility, With<PauseMenu>>,
+        mut game_mode: ResMut<NextState<GameMode>>,
+        mut game_state: ResMut<NextState<GameState>>,
+        mut menu_state: ResMut<NextState<MenuState>>,
+        mut save_slot: ResMut<SaveSlot>,
     ) {
-        for (interaction, mut color, selected) in &mut interaction_query {
-            if *interaction == Interaction::Hovered {
-                commands.spawn(AudioBundle {
-                    source: hovered_sound.0.clone(),
-                    settings: PlaybackSettings::DESPAWN,
-                });
-            }
-            if *interaction == Interaction::Pressed {
-                commands.spawn(AudioBundle {
-                    source: pressed_sound.0.clone(),
-                    settings: PlaybackSettings::DESPAWN,
-                });
+        for (interaction, action) in &interaction_query {
+            if *interaction != Interaction::Pressed {
+                continue;
             }
-            *color = match (*interaction, selected) {
-                (Interaction::Pressed, _) | (Interaction::None, Some(_)) => PRESSED_BUTTON.into(),
-                (Interaction::Hovered, Some(_)) => HOVERED_PRESSED_BUTTON.into(),
-                (Interaction::Hovered, None) => HOVERED_BUTTON.into(),
-                (Interaction::None, None) => NORMAL_BUTTON.into(),
+            match action {
+                PauseButtonAction::Resume => game_mode.set(GameMode::Running),
+                // The results follow on the next tick, as for any other end
+                PauseButtonAction::Finish => {
+                    if game.is_over() {
+                        continue;
+                    }
+                    last_step.0 = Some(game.finish());
+                    if let Some(replay) = recorder.0.as_mut() {
+                        replay.finished = true;
+                    }
+                    game_mode.set(GameMode::Running);
+                }
+                PauseButtonAction::Restart => {
+                    game_mode.set(GameMode::Running);
+                    game_state.set(GameState::Restart);
+                }
+                PauseButtonAction::Settings => {
+                    *pause_menu.single_mut() = Visibility::Hidden;
+                    menu_state.set(MenuState::Settings);
+                }
+                PauseButtonAction::Controls => {
+                    *pause_menu.single_mut() = Visibility::Hidden;
+                    menu_state.set(MenuState::Controls);
+                }
+                PauseButtonAction::SaveAndQuit => {
+                    let Some(replay) = recorder.0.clone() else {
+                        continue;
+                    };
+                    let (autopilot, assisted) = players
+                        .iter()
+                        .find(|(player, ..)| player.0 == 0)
+                        .map_or((false, false), |(_, autopilot, assisted)| {
+                            (autopilot, assisted)
+                        });
+                    let saved = SavedGame {
+                        version: SAVE_VERSION,
+                        replay,
+                        snapshot: Snapshot::of(&game, &rng),
+                        timer_elapsed: timer.elapsed_secs(),
+                        bot: options.bot,
+                        autopilot,
+                        assisted,
+                        daily: daily.as_ref().map(|daily| daily.challenge),
+                    };
+                    let path = save_path();
+                    match saved.save(&path) {
+                        Ok(()) => {
+                            info!("Game saved to {}", path.display());
+                            save_slot.0 = Some(saved);
+                            game_mode.set(GameMode::Running);
+                            game_state.set(GameState::Menu);
+                        }
+                        Err(e) => error!("Cannot save the game to {}: {e}", path.display()),
+                    }
+                }
+                PauseButtonAction::Quit => {
+                    game_mode.set(GameMode::Running);
+                    game_state.set(match playtest {
+                        Some(_) => GameState::Editor,
+                        None => GameState::Menu,
+                    });
+                }
             }
         }
     }
 
-    fn menu_action(
-        interaction_query: Query<
-            (&Interaction, &MenuButtonAction),
-            (Changed<Interaction>, With<Button>),
-        >,
-        mut app_exit_events: EventWriter<AppExit>,
-        mut game_state: ResMut<NextState<GameState>>,
+    /// Shows the buttons of the pause screen again when leaving the controls.
+    fn show_pause_menu(mut pause_menu: Query<&mut Visibility, With<PauseMenu>>) {
+        if let Ok(mut visibility) = pause_menu.get_single_mut() {
+            *visibility = Visibility::Inherited;
+        }
+    }
+
+    /// Pauses the game when its window loses the focus.
+    fn pause_on_focus_loss(
+        mut focus_events: EventReader<WindowFocused>,
+        mut game_mode: ResMut<NextState<GameMode>>,
     ) {
-        for (interaction, menu_button_action) in &interaction_query {
-            if *interaction == Interaction::Pressed {
-                match menu_button_action {
-                    MenuButtonAction::Play => game_state.set(GameState::Game),
-                    MenuButtonAction::Quit => app_exit_events.send(AppExit),
-                }
-            }
+        if focus_events.read().any(|event| !event.focused) {
+            game_mode.set(GameMode::Pause);
         }
     }
 }
 
-pub mod results {
-    use super::{despawn_screen, GameState};
-    use crate::{constants::RESULTS_TEXT_SIZE, logic::GameEvent};
+pub mod menu {
+    use super::results::ResultsTimer;
+    use super::{despawn_screen, spawn_button, GameState};
+    use crate::audio::{AudioMixer, Bus};
+    use crate::bot::BotKind;
+    use crate::config::GameConfig;
+    use crate::daily::{DailyChallenge, DailyGame, Date};
+    use crate::difficulty::Difficulty;
+    use crate::gamepad::{self, DirectionPresses};
+    use crate::input::{input_map_path, Action, InputMap};
+    use crate::leaderboard::{self, LeaderboardError, Ranking, RANKINGS_LIMIT};
+    use crate::level::{LevelAsset, Levels};
+    use crate::logic::{GameOptions, SnakeDirection};
+    use crate::replay::{LastReplay, Replay, ReplayPlayer};
+    use crate::rules::{Boundary, Ruleset};
+    use crate::save::{save_path, Resume, SaveSlot};
+    use crate::scores::{HighScores, ScoreCategory};
+    use crate::settings::{GridSize, Settings, Speed, Theme, VOLUMES};
+    use crate::{constants::*, AlreadyPlayed, ButtonHoveredSound, ButtonPressedSound};
+    use bevy::app::AppExit;
+    use bevy::asset::LoadedFolder;
+    use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
     use bevy::prelude::*;
+    use bevy::tasks::{block_on, IoTaskPool, Task};
 
-    pub struct ResultsPlugin;
+    pub struct MenuPlugin;
 
-    impl Plugin for ResultsPlugin {
+    impl Plugin for MenuPlugin {
         fn build(&self, app: &mut App) {
-            app.add_systems(OnEnter(GameState::Results), results_setup)
-                .add_systems(Update, countdown.run_if(in_state(GameState::Results)))
+            app.add_state::<MenuState>()
+                .add_systems(OnEnter(GameState::Menu), menu_setup)
+                .add_systems(OnExit(GameState::Menu), menu_cleanup)
+                .add_systems(OnEnter(MenuState::Main), main_menu_setup)
+                .add_systems(OnExit(MenuState::Main), despawn_screen::<OnMainMenuScreen>)
+                .add_systems(OnEnter(MenuState::NewGame), new_game_setup)
+                .add_systems(OnEnter(MenuState::HighScores), high_scores_setup)
                 .add_systems(
-                    OnExit(GameState::Results),
-                    despawn_screen::<OnResultsScreen>,
-                );
-        }
-    }
-
-    #[derive(Component)]
-    struct OnResultsScreen;
-
-    #[derive(Resource, Deref, DerefMut)]
-    pub struct ResultsTimer(pub Timer);
-
-    fn results_setup(
-        mut commands: Commands,
-        mut events: EventReader<GameEvent>,
-        asset_server: Res<AssetServer>,
-    ) {
-        // should not be empty
-        assert!(!events.is_empty());
-
-        let event = events.read().last().unwrap();
-        let (results, text, color) = match event {
-            GameEvent::GameOver(why) => ("Game over!", why.clone(), Color::RED),
-            GameEvent::GameWon => ("Good job!", "Congratulations!".to_string(), Color::GREEN),
-        };
-
-        commands
-            .spawn((
+                    OnExit(MenuState::HighScores),
+                    despawn_screen::<OnHighScoresScreen>,
+                )
+                .add_systems(OnEnter(MenuState::GlobalScores), global_scores_setup)
+                .add_systems(
+                    OnExit(MenuState::GlobalScores),
+                    (despawn_screen::<OnGlobalScoresScreen>, stop_rankings),
+                )
+                .add_systems(
+                    Update,
+                    show_rankings.run_if(resource_exists::<RankingsTask>()),
+                )
+                .add_systems(
+                    OnExit(MenuState::NewGame),
+                    despawn_screen::<OnNewGameScreen>,
+                )
+                .add_systems(OnEnter(MenuState::Settings), settings_setup)
+                .add_systems(
+                    OnExit(MenuState::Settings),
+                    despawn_screen::<OnSettingsScreen>,
+                )
+                .add_systems(
+                    Update,
+                    (
+                        option_button::<MasterOption>,
+                        option_button::<MusicOption>,
+                        option_button::<SoundsOption>,
+                        option_button::<ButtonsOption>,
+                        option_button::<FullscreenOption>,
+                        option_button::<VsyncOption>,
+                        option_button::<GridOption>,
+                        option_button::<SpeedOption>,
+                        option_button::<ThemeOption>,
+                    )
+                        .run_if(in_state(MenuState::Settings)),
+                )
+                .add_systems(OnEnter(MenuState::Controls), controls_setup)
+                .add_systems(
+                    OnExit(MenuState::Controls),
+                    (despawn_screen::<OnControlsScreen>, stop_rebinding),
+                )
+                .add_systems(
+                    Update,
+                    (
+                        controls_button,
+                        rebind_key.run_if(resource_exists::<Rebinding>()),
+                        show_bindings.run_if(resource_changed::<InputMap>()),
+                    )
+                        .run_if(in_state(MenuState::Controls)),
+                )
+                .add_systems(
+                    Update,
+                    (menu_action, button_system).run_if(in_state(GameState::Menu)),
+                )
+                // The controls and the settings can also be shown over the pause screen
+                .add_systems(
+                    Update,
+                    back_to_pause.run_if(
+                        in_state(GameState::Game).and_then(not(in_state(MenuState::Disabled))),
+                    ),
+                )
+                // On every screen with buttons, except while a key is being rebound
+                .add_systems(
+                    Update,
+                    (navigate_focus, press_focused).run_if(not(resource_exists::<Rebinding>())),
+                )
+                .add_systems(Update, scroll_menus)
+                .add_systems(
+                    Update,
+                    (
+                        option_button::<PlayersOption>,
+                        option_button::<BotOption>,
+                        option_button::<BoundaryOption>,
+                        option_button::<LevelOption>,
+                        option_button::<DifficultyOption>,
+                        option_button::<AccelerateOption>,
+                        option_button::<PowerUpsOption>,
+                        option_button::<RulesetOption>,
+                        option_button::<TimeLimitOption>,
+                    )
+                        .run_if(in_state(MenuState::NewGame)),
+                );
+        }
+    }
+
+    #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
+    pub enum MenuState {
+        #[default]
+        Disabled,
+        Main,
+        NewGame,
+        HighScores,
+        GlobalScores,
+        Controls,
+        Settings,
+    }
+
+    #[derive(Component)]
+    pub struct OnMainMenuScreen;
+
+    #[derive(Component)]
+    pub struct OnNewGameScreen;
+
+    #[derive(Component)]
+    pub struct OnHighScoresScreen;
+
+    #[derive(Component)]
+    pub struct OnGlobalScoresScreen;
+
+    #[derive(Component)]
+    pub struct OnControlsScreen;
+
+    #[derive(Component)]
+    pub struct OnSettingsScreen;
+
+    #[derive(Component)]
+    pub struct SelectedOption;
+
+    #[derive(Clone, Copy, Component)]
+    enum MenuButtonAction {
+        Continue,
+        Play,
+        Daily,
+        Start,
+        Watch,
+        HighScores,
+        GlobalScores,
+        Controls,
+        Settings,
+        Editor,
+        BackToMainMenu,
+        Quit,
+    }
+
+    /// A button picking the value of one of the [`GameOptions`], or of another resource
+    /// such as the [`Settings`].
+    pub trait MenuOption: Component + Copy + PartialEq {
+        type Target: Resource;
+
+        fn get(options: &Self::Target) -> Self;
+        fn set(self, options: &mut Self::Target);
+    }
+
+    /// Whether two players share the keyboard.
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct PlayersOption(bool);
+
+    impl MenuOption for PlayersOption {
+        type Target = GameOptions;
+
+        fn get(options: &GameOptions) -> Self {
+            PlayersOption(options.versus)
+        }
+
+        fn set(self, options: &mut GameOptions) {
+            options.versus = self.0;
+        }
+    }
+
+    /// The bot playing the snake, or racing the player in versus games.
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct BotOption(Option<BotKind>);
+
+    impl MenuOption for BotOption {
+        type Target = GameOptions;
+
+        fn get(options: &GameOptions) -> Self {
+            BotOption(options.bot)
+        }
+
+        fn set(self, options: &mut GameOptions) {
+            options.bot = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct BoundaryOption(Boundary);
+
+    impl MenuOption for BoundaryOption {
+        type Target = GameOptions;
+
+        fn get(options: &GameOptions) -> Self {
+            BoundaryOption(options.boundary)
+        }
+
+        fn set(self, options: &mut GameOptions) {
+            options.boundary = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct LevelOption(Option<AssetId<LevelAsset>>);
+
+    impl MenuOption for LevelOption {
+        type Target = GameOptions;
+
+        fn get(options: &GameOptions) -> Self {
+            LevelOption(options.level)
+        }
+
+        fn set(self, options: &mut GameOptions) {
+            options.level = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct DifficultyOption(Difficulty);
+
+    impl MenuOption for DifficultyOption {
+        type Target = GameOptions;
+
+        fn get(options: &GameOptions) -> Self {
+            DifficultyOption(options.pace.difficulty)
+        }
+
+        fn set(self, options: &mut GameOptions) {
+            options.pace.difficulty = self.0;
+        }
+    }
+
+    /// Whether the snakes speed up as they eat.
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct AccelerateOption(bool);
+
+    impl MenuOption for AccelerateOption {
+        type Target = GameOptions;
+
+        fn get(options: &GameOptions) -> Self {
+            AccelerateOption(options.pace.accelerate)
+        }
+
+        fn set(self, options: &mut GameOptions) {
+            options.pace.accelerate = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct PowerUpsOption(bool);
+
+    impl MenuOption for PowerUpsOption {
+        type Target = GameOptions;
+
+        fn get(options: &GameOptions) -> Self {
+            PowerUpsOption(options.power_ups)
+        }
+
+        fn set(self, options: &mut GameOptions) {
+            options.power_ups = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct RulesetOption(Ruleset);
+
+    impl MenuOption for RulesetOption {
+        type Target = GameOptions;
+
+        fn get(options: &GameOptions) -> Self {
+            RulesetOption(options.ruleset)
+        }
+
+        fn set(self, options: &mut GameOptions) {
+            options.ruleset = self.0;
+        }
+    }
+
+    /// The seconds of time-attack games.
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct TimeLimitOption(u32);
+
+    impl MenuOption for TimeLimitOption {
+        type Target = GameOptions;
+
+        fn get(options: &GameOptions) -> Self {
+            TimeLimitOption(options.time_limit)
+        }
+
+        fn set(self, options: &mut GameOptions) {
+            options.time_limit = self.0;
+        }
+    }
+
+    /// The volume of everything.
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct MasterOption(f32);
+
+    impl MenuOption for MasterOption {
+        type Target = Settings;
+
+        fn get(settings: &Settings) -> Self {
+            MasterOption(settings.master_volume)
+        }
+
+        fn set(self, settings: &mut Settings) {
+            settings.master_volume = self.0;
+        }
+    }
+
+    /// The volume of the music.
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct MusicOption(f32);
+
+    impl MenuOption for MusicOption {
+        type Target = Settings;
+
+        fn get(settings: &Settings) -> Self {
+            MusicOption(settings.music_volume)
+        }
+
+        fn set(self, settings: &mut Settings) {
+            settings.music_volume = self.0;
+        }
+    }
+
+    /// The volume of the sounds.
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct SoundsOption(f32);
+
+    impl MenuOption for SoundsOption {
+        type Target = Settings;
+
+        fn get(settings: &Settings) -> Self {
+            SoundsOption(settings.sfx_volume)
+        }
+
+        fn set(self, settings: &mut Settings) {
+            settings.sfx_volume = self.0;
+        }
+    }
+
+    /// The volume of the sounds of the buttons.
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct ButtonsOption(f32);
+
+    impl MenuOption for ButtonsOption {
+        type Target = Settings;
+
+        fn get(settings: &Settings) -> Self {
+            ButtonsOption(settings.ui_volume)
+        }
+
+        fn set(self, settings: &mut Settings) {
+            settings.ui_volume = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct FullscreenOption(bool);
+
+    impl MenuOption for FullscreenOption {
+        type Target = Settings;
+
+        fn get(settings: &Settings) -> Self {
+            FullscreenOption(settings.fullscreen)
+        }
+
+        fn set(self, settings: &mut Settings) {
+            settings.fullscreen = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct VsyncOption(bool);
+
+    impl MenuOption for VsyncOption {
+        type Target = Settings;
+
+        fn get(settings: &Settings) -> Self {
+            VsyncOption(settings.vsync)
+        }
+
+        fn set(self, settings: &mut Settings) {
+            settings.vsync = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct GridOption(Option<GridSize>);
+
+    impl MenuOption for GridOption {
+        type Target = Settings;
+
+        fn get(settings: &Settings) -> Self {
+            GridOption(settings.grid)
+        }
+
+        fn set(self, settings: &mut Settings) {
+            settings.grid = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct SpeedOption(Option<Speed>);
+
+    impl MenuOption for SpeedOption {
+        type Target = Settings;
+
+        fn get(settings: &Settings) -> Self {
+            SpeedOption(settings.speed)
+        }
+
+        fn set(self, settings: &mut Settings) {
+            settings.speed = self.0;
+        }
+    }
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    struct ThemeOption(Option<Theme>);
+
+    impl MenuOption for ThemeOption {
+        type Target = Settings;
+
+        fn get(settings: &Settings) -> Self {
+            ThemeOption(settings.theme)
+        }
+
+        fn set(self, settings: &mut Settings) {
+            settings.theme = self.0;
+        }
+    }
+
+    fn menu_setup(
+        mut commands: Commands,
+        mut menu_state: ResMut<NextState<MenuState>>,
+        mut timer: ResMut<ResultsTimer>,
+    ) {
+        commands.remove_resource::<DailyGame>();
+        menu_state.set(MenuState::Main);
+        timer.reset();
+    }
+
+    fn menu_cleanup(mut menu_state: ResMut<NextState<MenuState>>) {
+        menu_state.set(MenuState::Disabled);
+    }
+
+    fn button_style() -> Style {
+        Style {
+            width: Val::Px(BUTTON_WIDTH),
+            height: Val::Px(BUTTON_HEIGHT),
+            margin: UiRect::all(Val::Px(BUTTON_MARGIN)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        }
+    }
+
+    fn button_text_style(asset_server: &AssetServer) -> TextStyle {
+        TextStyle {
+            font_size: TEXT_BUTTON_SIZE,
+            color: Color::WHITE,
+            font: asset_server.load("font.ttf"),
+        }
+    }
+
+    fn title(text: &str, asset_server: &AssetServer) -> TextBundle {
+        TextBundle::from_section(
+            text.to_uppercase(),
+            TextStyle {
+                font_size: MENU_TITLE_SIZE,
+                color: MENU_TEXT_COLOR,
+                font: asset_server.load("font.ttf"),
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::all(Val::Px(MENU_TITLE_MARGIN)),
+            ..default()
+        })
+    }
+
+    /// Spawns the root of a menu screen, whose children are laid out in a column.
+    fn spawn_menu_screen(
+        commands: &mut Commands,
+        marker: impl Component,
+        children: impl FnOnce(&mut ChildBuilder),
+    ) {
+        commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                },
+                marker,
+            ))
+            .with_children(|parent| {
+                parent
+                    .spawn((
+                        NodeBundle {
+                            style: scrolling_menu_style(),
+                            background_color: Color::BLACK.into(),
+                            ..default()
+                        },
+                        ScrollingMenu::default(),
+                    ))
+                    .with_children(children);
+            });
+    }
+
+    /// The style of the column of a menu screen, centered in the window unless it is
+    /// taller, then starting at the top to be scrolled.
+    pub fn scrolling_menu_style() -> Style {
+        Style {
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            // Unlike centering, the margins never push the top of the column out of the
+            // window
+            margin: UiRect::vertical(Val::Auto),
+            flex_shrink: 0.0,
+            ..default()
+        }
+    }
+
+    /// The column of a menu screen, which the mouse wheel and the focus scroll when it
+    /// is taller than the window, `offset` pixels up.
+    #[derive(Component, Default)]
+    pub struct ScrollingMenu {
+        offset: f32,
+    }
+
+    /// Scrolls the menus taller than the window with the mouse wheel, and to bring the
+    /// focused button into view.
+    pub fn scroll_menus(
+        mut wheel_events: EventReader<MouseWheel>,
+        focused: Query<(&GlobalTransform, &Node), Added<Focused>>,
+        mut menus: Query<(&mut ScrollingMenu, &mut Style, &Node, &Parent)>,
+        screens: Query<&Node, Without<ScrollingMenu>>,
+    ) {
+        let scrolled: f32 = wheel_events
+            .read()
+            .map(|event| match event.unit {
+                MouseScrollUnit::Line => event.y * MENU_SCROLL_SPEED,
+                MouseScrollUnit::Pixel => event.y,
+            })
+            .sum();
+        for (mut menu, mut style, node, parent) in &mut menus {
+            let Ok(screen) = screens.get(parent.get()) else {
+                continue;
+            };
+            let height = screen.size().y;
+            let mut offset = menu.offset - scrolled;
+            for (transform, button) in &focused {
+                let y = transform.translation().y;
+                let half = button.size().y / 2.0 + BUTTON_MARGIN;
+                if y - half < 0.0 {
+                    offset += y - half;
+                } else if y + half > height {
+                    offset += y + half - height;
+                }
+            }
+            let offset = offset.clamp(0.0, (node.size().y - height).max(0.0));
+            if offset != menu.offset {
+                menu.offset = offset;
+                style.top = Val::Px(-offset);
+            }
+        }
+    }
+
+    /// Spawns a labelled row of buttons, one for each value of an option.
+    fn spawn_option_row<T: MenuOption, S: AsRef<str>>(
+        parent: &mut ChildBuilder,
+        label: &str,
+        values: impl IntoIterator<Item = (T, S)>,
+        current: T,
+        asset_server: &AssetServer,
+    ) {
+        let text_style = TextStyle {
+            font_size: OPTION_TEXT_SIZE,
+            color: Color::WHITE,
+            font: asset_server.load("font.ttf"),
+        };
+        parent.spawn(TextBundle::from_section(
+            label.to_uppercase(),
+            TextStyle {
+                color: MENU_TEXT_COLOR,
+                ..text_style.clone()
+            },
+        ));
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_wrap: FlexWrap::Wrap,
+                    max_width: Val::Px(MENU_WIDTH),
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|parent| {
+                let style = Style {
+                    width: Val::Px(OPTION_BUTTON_WIDTH),
+                    height: Val::Px(OPTION_BUTTON_HEIGHT),
+                    margin: UiRect::all(Val::Px(OPTION_BUTTON_MARGIN)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                };
+                for (value, text) in values {
+                    let mut button =
+                        spawn_button(parent, &style, &text_style, text.as_ref(), value);
+                    if value == current {
+                        button.insert((SelectedOption, BackgroundColor(PRESSED_BUTTON)));
+                    }
+                }
+            });
+    }
+
+    /// Spawns a column for option rows laid out side by side.
+    fn spawn_option_column(parent: &mut ChildBuilder, children: impl FnOnce(&mut ChildBuilder)) {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(children);
+    }
+
+    fn main_menu_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        already_played: Res<AlreadyPlayed>,
+        last_replay: Res<LastReplay>,
+        save_slot: Res<SaveSlot>,
+    ) {
+        let button_style = button_style();
+        let button_text_style = button_text_style(&asset_server);
+
+        spawn_menu_screen(&mut commands, OnMainMenuScreen, |parent| {
+            // Game name
+            parent.spawn(title("Snake", &asset_server));
+
+            // Resume the saved game
+            if save_slot.is_some() {
+                spawn_button(
+                    parent,
+                    &button_style,
+                    &button_text_style,
+                    "Continue",
+                    MenuButtonAction::Continue,
+                );
+            }
+
+            let play_button_text = if already_played.0 { "Replay" } else { "Play" };
+            spawn_button(
+                parent,
+                &button_style,
+                &button_text_style,
+                play_button_text,
+                MenuButtonAction::Play,
+            );
+            spawn_button(
+                parent,
+                &button_style,
+                &button_text_style,
+                "Daily",
+                MenuButtonAction::Daily,
+            );
+
+            // Watch the last game
+            if last_replay.is_some() {
+                spawn_button(
+                    parent,
+                    &button_style,
+                    &button_text_style,
+                    "Watch",
+                    MenuButtonAction::Watch,
+                );
+            }
+
+            // Side by side so that the menu still fits the window
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                let half_button_style = Style {
+                    width: Val::Px(MENU_WIDTH / 2.0 - BUTTON_MARGIN * 3.0),
+                    ..button_style.clone()
+                };
+                spawn_button(
+                    parent,
+                    &half_button_style,
+                    &button_text_style,
+                    "Scores",
+                    MenuButtonAction::HighScores,
+                );
+                spawn_button(
+                    parent,
+                    &half_button_style,
+                    &button_text_style,
+                    "Controls",
+                    MenuButtonAction::Controls,
+                );
+            });
+            spawn_button(
+                parent,
+                &button_style,
+                &button_text_style,
+                "Settings",
+                MenuButtonAction::Settings,
+            );
+            spawn_button(
+                parent,
+                &button_style,
+                &button_text_style,
+                "Editor",
+                MenuButtonAction::Editor,
+            );
+            spawn_button(
+                parent,
+                &button_style,
+                &button_text_style,
+                "Quit",
+                MenuButtonAction::Quit,
+            );
+        });
+    }
+
+    fn new_game_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        mut options: ResMut<GameOptions>,
+        levels: Res<Levels>,
+        folders: Res<Assets<LoadedFolder>>,
+        level_assets: Res<Assets<LevelAsset>>,
+    ) {
+        let levels = levels.loaded(&folders, &level_assets);
+        // The picked level may have failed to reload
+        if options
+            .level
+            .is_some_and(|id| level_assets.get(id).is_none())
+        {
+            options.level = None;
+        }
+        let button_style = button_style();
+        let button_text_style = button_text_style(&asset_server);
+
+        spawn_menu_screen(&mut commands, OnNewGameScreen, |parent| {
+            parent.spawn(title("New game", &asset_server));
+
+            spawn_option_row(
+                parent,
+                "Players",
+                [(PlayersOption(false), "1"), (PlayersOption(true), "2")],
+                PlayersOption::get(&options),
+                &asset_server,
+            );
+
+            spawn_option_row(
+                parent,
+                "Bot",
+                std::iter::once((BotOption(None), "Off")).chain(
+                    [BotKind::Greedy, BotKind::Bfs, BotKind::Cycle]
+                        .map(|kind| (BotOption(Some(kind)), kind.name())),
+                ),
+                BotOption::get(&options),
+                &asset_server,
+            );
+
+            spawn_option_row(
+                parent,
+                "Rules",
+                Ruleset::ALL.map(|ruleset| {
+                    let name = match ruleset {
+                        Ruleset::TimeAttack => "Timed",
+                        ruleset => ruleset.name(),
+                    };
+                    (RulesetOption(ruleset), name)
+                }),
+                RulesetOption::get(&options),
+                &asset_server,
+            );
+
+            // The time only matters to time-attack games
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                spawn_option_column(parent, |parent| {
+                    spawn_option_row(
+                        parent,
+                        "Mode",
+                        [
+                            (BoundaryOption(Boundary::Walls), "Classic"),
+                            (BoundaryOption(Boundary::Wrap), "Wrap"),
+                        ],
+                        BoundaryOption::get(&options),
+                        &asset_server,
+                    );
+                });
+                spawn_option_column(parent, |parent| {
+                    spawn_option_row(
+                        parent,
+                        "Time",
+                        TIME_LIMITS
+                            .map(|seconds| (TimeLimitOption(seconds), format!("{seconds} s"))),
+                        TimeLimitOption::get(&options),
+                        &asset_server,
+                    );
+                });
+            });
+
+            spawn_option_row(
+                parent,
+                "Level",
+                std::iter::once((LevelOption(None), "Open".to_string())).chain(
+                    levels
+                        .into_iter()
+                        .map(|(id, name)| (LevelOption(Some(id)), name)),
+                ),
+                LevelOption::get(&options),
+                &asset_server,
+            );
+
+            spawn_option_row(
+                parent,
+                "Speed",
+                Difficulty::ALL.map(|difficulty| (DifficultyOption(difficulty), difficulty.name())),
+                DifficultyOption::get(&options),
+                &asset_server,
+            );
+
+            // Both toggles share a line
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                spawn_option_column(parent, |parent| {
+                    spawn_option_row(
+                        parent,
+                        "Accelerate",
+                        [
+                            (AccelerateOption(false), "Off"),
+                            (AccelerateOption(true), "On"),
+                        ],
+                        AccelerateOption::get(&options),
+                        &asset_server,
+                    );
+                });
+                spawn_option_column(parent, |parent| {
+                    spawn_option_row(
+                        parent,
+                        "Power-ups",
+                        [(PowerUpsOption(false), "Off"), (PowerUpsOption(true), "On")],
+                        PowerUpsOption::get(&options),
+                        &asset_server,
+                    );
+                });
+            });
+
+            // Side by side to leave room for the options
+            let button_style = Style {
+                width: Val::Px(MENU_WIDTH / 2.0 - BUTTON_MARGIN * 3.0),
+                ..button_style
+            };
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                spawn_button(
+                    parent,
+                    &button_style,
+                    &button_text_style,
+                    "Start",
+                    MenuButtonAction::Start,
+                );
+                spawn_button(
+                    parent,
+                    &button_style,
+                    &button_text_style,
+                    "Back",
+                    (MenuButtonAction::BackToMainMenu, BackButton),
+                );
+            });
+        });
+    }
+
+    /// The game modes of the score tables, side by side.
+    const SCORE_COLUMNS: [(Boundary, &str); 2] =
+        [(Boundary::Walls, "Classic"), (Boundary::Wrap, "Wrap")];
+
+    /// The list of scores of a game mode on a score screen.
+    #[derive(Component)]
+    struct ScoreColumn(Boundary);
+
+    /// The rankings of the leaderboard server, fetched in the background.
+    #[derive(Resource)]
+    struct RankingsTask(Task<Result<Vec<(Boundary, Vec<Ranking>)>, LeaderboardError>>);
+
+    /// The score tables shown are the ones of the grid size of the picked level, at the
+    /// picked pace.
+    fn score_category(
+        options: &GameOptions,
+        level_assets: &Assets<LevelAsset>,
+        config: &GameConfig,
+    ) -> ScoreCategory {
+        let (width, height) = options
+            .level
+            .and_then(|id| level_assets.get(id))
+            .map_or((config.grid_width, config.grid_height), |level| {
+                (level.width, level.height)
+            });
+        ScoreCategory {
+            boundary: Boundary::default(),
+            width,
+            height,
+            pace: options.pace.for_ruleset(options.ruleset),
+            power_ups: options.power_ups,
+            ruleset: options.ruleset,
+            time_limit: match options.ruleset {
+                Ruleset::TimeAttack => options.time_limit,
+                _ => 0,
+            },
+            refresh_rate: config.refresh_rate,
+        }
+    }
+
+    fn score_lines<'a>(scores: impl IntoIterator<Item = (&'a str, usize)>) -> String {
+        let lines: Vec<String> = scores
+            .into_iter()
+            .enumerate()
+            .map(|(i, (initials, score))| format!("{:>2}. {initials:<3} {score:>4}", i + 1))
+            .collect();
+        match lines.is_empty() {
+            true => "No score yet".to_uppercase(),
+            false => lines.join("\n").to_uppercase(),
+        }
+    }
+
+    /// Spawns a screen with the scores of each game mode, `lines` giving the text of
+    /// each, and a row of buttons at the bottom.
+    fn spawn_scores_screen(
+        commands: &mut Commands,
+        marker: impl Component,
+        title_text: &str,
+        category: &ScoreCategory,
+        lines: impl Fn(Boundary) -> String,
+        buttons: &[(&str, MenuButtonAction)],
+        asset_server: &AssetServer,
+    ) {
+        let button_style = Style {
+            width: Val::Px(MENU_WIDTH / 2.0 - BUTTON_MARGIN * 3.0),
+            ..button_style()
+        };
+        let button_text_style = button_text_style(asset_server);
+        let text_style = TextStyle {
+            font_size: HIGH_SCORE_TEXT_SIZE,
+            color: Color::WHITE,
+            font: asset_server.load("font.ttf"),
+        };
+
+        spawn_menu_screen(commands, marker, |parent| {
+            parent.spawn(title(title_text, asset_server));
+            parent.spawn(
+                TextBundle::from_section(
+                    format!(
+                        "{}x{} {}\n{}{}",
+                        category.width,
+                        category.height,
+                        category.ruleset_name(),
+                        category.pace_name(),
+                        if category.power_ups { " power-ups" } else { "" }
+                    )
+                    .to_uppercase(),
+                    TextStyle {
+                        color: MENU_TEXT_COLOR,
+                        ..text_style.clone()
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center),
+            );
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(HIGH_SCORE_COLUMN_GAP),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (boundary, name) in SCORE_COLUMNS {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::Column,
+                                    align_items: AlignItems::Center,
+                                    margin: UiRect::vertical(Val::Px(BUTTON_MARGIN)),
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    name.to_uppercase(),
+                                    TextStyle {
+                                        color: MENU_TEXT_COLOR,
+                                        ..text_style.clone()
+                                    },
+                                ));
+                                parent.spawn((
+                                    TextBundle::from_section(lines(boundary), text_style.clone()),
+                                    ScoreColumn(boundary),
+                                ));
+                            });
+                    }
+                });
+
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                for (text, action) in buttons {
+                    let mut button =
+                        spawn_button(parent, &button_style, &button_text_style, text, *action);
+                    if let MenuButtonAction::BackToMainMenu = action {
+                        button.insert(BackButton);
+                    }
+                }
+            });
+        });
+    }
+
+    /// Shows the tables of both game modes.
+    fn high_scores_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        options: Res<GameOptions>,
+        level_assets: Res<Assets<LevelAsset>>,
+        config: Res<GameConfig>,
+        high_scores: Res<HighScores>,
+    ) {
+        let category = score_category(&options, &level_assets, &config);
+        let mut buttons = vec![("Back", MenuButtonAction::BackToMainMenu)];
+        if config.leaderboard_url.is_some() {
+            buttons.push(("Global", MenuButtonAction::GlobalScores));
+        }
+        spawn_scores_screen(
+            &mut commands,
+            OnHighScoresScreen,
+            "High scores",
+            &category,
+            |boundary| {
+                let category = ScoreCategory {
+                    boundary,
+                    ..category
+                };
+                let scores = high_scores.scores(&category);
+                score_lines(
+                    scores
+                        .iter()
+                        .map(|entry| (entry.initials.as_str(), entry.score)),
+                )
+            },
+            &buttons,
+            &asset_server,
+        );
+    }
+
+    /// Shows the rankings of the leaderboard server once they are fetched.
+    fn global_scores_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        options: Res<GameOptions>,
+        level_assets: Res<Assets<LevelAsset>>,
+        config: Res<GameConfig>,
+    ) {
+        let category = score_category(&options, &level_assets, &config);
+        spawn_scores_screen(
+            &mut commands,
+            OnGlobalScoresScreen,
+            "Global",
+            &category,
+            |_| "Loading...".to_uppercase(),
+            &[
+                ("Back", MenuButtonAction::BackToMainMenu),
+                ("Local", MenuButtonAction::HighScores),
+            ],
+            &asset_server,
+        );
+
+        let Some(url) = config.leaderboard_url.clone() else {
+            return;
+        };
+        let task = IoTaskPool::get().spawn(async move {
+            SCORE_COLUMNS
+                .into_iter()
+                .map(|(boundary, _)| {
+                    let category = ScoreCategory {
+                        boundary,
+                        ..category
+                    };
+                    let rankings = leaderboard::rankings(&url, &category, RANKINGS_LIMIT)?;
+                    Ok((boundary, rankings))
+                })
+                .collect()
+        });
+        commands.insert_resource(RankingsTask(task));
+    }
+
+    fn show_rankings(
+        mut commands: Commands,
+        mut task: ResMut<RankingsTask>,
+        mut columns: Query<(&ScoreColumn, &mut Text)>,
+    ) {
+        if !task.0.is_finished() {
+            return;
+        }
+        commands.remove_resource::<RankingsTask>();
+        let rankings = match block_on(&mut task.0) {
+            Ok(rankings) => rankings,
+            Err(e) => {
+                error!("Cannot fetch the rankings: {e}");
+                for (_, mut text) in &mut columns {
+                    text.sections[0].value = "Unavailable".to_uppercase();
+                }
+                return;
+            }
+        };
+        for (boundary, rankings) in rankings {
+            for (column, mut text) in &mut columns {
+                if column.0 == boundary {
+                    let scores = rankings.iter().map(|r| (r.name.as_str(), r.score));
+                    text.sections[0].value = score_lines(scores);
+                }
+            }
+        }
+    }
+
+    fn stop_rankings(mut commands: Commands) {
+        commands.remove_resource::<RankingsTask>();
+    }
+
+    /// The action waiting for a key on the controls screen.
+    #[derive(Resource)]
+    pub struct Rebinding {
+        action: Action,
+        /// The key is added to the keys of the action instead of replacing them.
+        add: bool,
+    }
+
+    #[derive(Clone, Copy, Component)]
+    enum ControlsButton {
+        Bind(Action),
+        Add(Action),
+        Reset,
+    }
+
+    /// The keys of an action on the controls screen.
+    #[derive(Component)]
+    struct BindingText(Action);
+
+    #[derive(Component)]
+    struct ControlsStatus;
+
+    fn settings_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        settings: Res<Settings>,
+        game_state: Res<State<GameState>>,
+    ) {
+        spawn_menu_screen(&mut commands, OnSettingsScreen, |parent| {
+            parent.spawn(title("Settings", &asset_server));
+
+            spawn_option_row(
+                parent,
+                "Volume",
+                VOLUMES.map(|(volume, name)| (MasterOption(volume), name)),
+                MasterOption::get(&settings),
+                &asset_server,
+            );
+            spawn_option_row(
+                parent,
+                "Music",
+                VOLUMES.map(|(volume, name)| (MusicOption(volume), name)),
+                MusicOption::get(&settings),
+                &asset_server,
+            );
+            spawn_option_row(
+                parent,
+                "Sounds",
+                VOLUMES.map(|(volume, name)| (SoundsOption(volume), name)),
+                SoundsOption::get(&settings),
+                &asset_server,
+            );
+            spawn_option_row(
+                parent,
+                "Buttons",
+                VOLUMES.map(|(volume, name)| (ButtonsOption(volume), name)),
+                ButtonsOption::get(&settings),
+                &asset_server,
+            );
+
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                spawn_option_column(parent, |parent| {
+                    spawn_option_row(
+                        parent,
+                        "Display",
+                        [
+                            (FullscreenOption(false), "Window"),
+                            (FullscreenOption(true), "Full"),
+                        ],
+                        FullscreenOption::get(&settings),
+                        &asset_server,
+                    );
+                });
+                spawn_option_column(parent, |parent| {
+                    spawn_option_row(
+                        parent,
+                        "Vsync",
+                        [(VsyncOption(true), "On"), (VsyncOption(false), "Off")],
+                        VsyncOption::get(&settings),
+                        &asset_server,
+                    );
+                });
+            });
+
+            // The game in progress keeps the grid, the speed and the colors it started with
+            if *game_state.get() == GameState::Game {
+                parent.spawn(
+                    TextBundle::from_section(
+                        "Grid, speed and theme\ncan be changed between games".to_uppercase(),
+                        TextStyle {
+                            color: MENU_TEXT_COLOR,
+                            ..button_text_style(&asset_server)
+                        },
+                    )
+                    .with_text_alignment(TextAlignment::Center)
+                    .with_style(Style {
+                        margin: UiRect::all(Val::Px(BUTTON_MARGIN)),
+                        ..default()
+                    }),
+                );
+            } else {
+                // `Default` keeps the value of the configuration file
+                spawn_option_row(
+                    parent,
+                    "Grid",
+                    [
+                        (GridOption(None), "Default"),
+                        (GridOption(Some(GridSize::Small)), "Small"),
+                        (GridOption(Some(GridSize::Large)), "Large"),
+                    ],
+                    GridOption::get(&settings),
+                    &asset_server,
+                );
+                spawn_option_row(
+                    parent,
+                    "Base speed",
+                    [
+                        (SpeedOption(None), "Default"),
+                        (SpeedOption(Some(Speed::Slow)), "Slow"),
+                        (SpeedOption(Some(Speed::Fast)), "Fast"),
+                    ],
+                    SpeedOption::get(&settings),
+                    &asset_server,
+                );
+                spawn_option_row(
+                    parent,
+                    "Theme",
+                    [
+                        (ThemeOption(None), "Default"),
+                        (ThemeOption(Some(Theme::Ocean)), "Ocean"),
+                        (ThemeOption(Some(Theme::Contrast)), "Contrast"),
+                    ],
+                    ThemeOption::get(&settings),
+                    &asset_server,
+                );
+            }
+
+            spawn_button(
+                parent,
+                &button_style(),
+                &button_text_style(&asset_server),
+                "Back",
+                (MenuButtonAction::BackToMainMenu, BackButton),
+            );
+        });
+    }
+
+    fn controls_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        input_map: Res<InputMap>,
+    ) {
+        // Every action has a row, so the screen is packed tighter than the other menus
+        let button_style = Style {
+            width: Val::Px(MENU_WIDTH / 2.0 - BUTTON_MARGIN * 3.0),
+            height: Val::Px(CONTROL_MENU_BUTTON_HEIGHT),
+            margin: UiRect::all(Val::Px(OPTION_BUTTON_MARGIN)),
+            ..button_style()
+        };
+        let button_text_style = button_text_style(&asset_server);
+        let text_style = TextStyle {
+            font_size: OPTION_TEXT_SIZE,
+            color: Color::WHITE,
+            font: asset_server.load("font.ttf"),
+        };
+        let small_button_style = Style {
+            width: Val::Px(CONTROL_BUTTON_WIDTH),
+            height: Val::Px(CONTROL_BUTTON_HEIGHT),
+            margin: UiRect::all(Val::Px(CONTROL_BUTTON_MARGIN)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        };
+
+        spawn_menu_screen(&mut commands, OnControlsScreen, |parent| {
+            parent.spawn(title("Controls", &asset_server).with_style(Style {
+                margin: UiRect::all(Val::Px(CONTROL_TITLE_MARGIN)),
+                ..default()
+            }));
+
+            for action in Action::ALL {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(
+                            TextBundle::from_section(
+                                action.name().to_uppercase(),
+                                TextStyle {
+                                    color: MENU_TEXT_COLOR,
+                                    ..text_style.clone()
+                                },
+                            )
+                            .with_style(Style {
+                                width: Val::Px(CONTROL_LABEL_WIDTH),
+                                ..default()
+                            }),
+                        );
+                        parent.spawn((
+                            TextBundle::from_section(
+                                input_map.key_names(action).to_uppercase(),
+                                text_style.clone(),
+                            )
+                            .with_style(Style {
+                                width: Val::Px(CONTROL_KEYS_WIDTH),
+                                ..default()
+                            }),
+                            BindingText(action),
+                        ));
+                        spawn_button(
+                            parent,
+                            &small_button_style,
+                            &text_style,
+                            "Set",
+                            ControlsButton::Bind(action),
+                        );
+                        spawn_button(
+                            parent,
+                            &small_button_style,
+                            &text_style,
+                            "Add",
+                            ControlsButton::Add(action),
+                        );
+                    });
+            }
+
+            parent.spawn((
+                TextBundle::from_section(
+                    "Set replaces the keys of an action, Add adds one".to_uppercase(),
+                    TextStyle {
+                        font_size: CONTROL_STATUS_SIZE,
+                        color: RESULTS_TEXT_COLOR,
+                        font: asset_server.load("font.ttf"),
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::top(Val::Px(OPTION_BUTTON_MARGIN)),
+                    ..default()
+                }),
+                ControlsStatus,
+            ));
+
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                spawn_button(
+                    parent,
+                    &button_style,
+                    &button_text_style,
+                    "Back",
+                    (MenuButtonAction::BackToMainMenu, BackButton),
+                );
+                spawn_button(
+                    parent,
+                    &button_style,
+                    &button_text_style,
+                    "Reset",
+                    ControlsButton::Reset,
+                );
+            });
+        });
+    }
+
+    fn save_input_map(input_map: &InputMap) {
+        let path = input_map_path();
+        if let Err(e) = input_map.save(&path) {
+            error!("Cannot save the key bindings to {}: {e}", path.display());
+        }
+    }
+
+    fn controls_button(
+        mut commands: Commands,
+        interaction_query: Query<
+            (&Interaction, &ControlsButton),
+            (Changed<Interaction>, With<Button>),
+        >,
+        mut input_map: ResMut<InputMap>,
+        mut status: Query<&mut Text, With<ControlsStatus>>,
+    ) {
+        for (interaction, button) in &interaction_query {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            let message = match *button {
+                ControlsButton::Bind(action) | ControlsButton::Add(action) => {
+                    let add = matches!(button, ControlsButton::Add(_));
+                    commands.insert_resource(Rebinding { action, add });
+                    format!("Press a key for {}", action.name())
+                }
+                ControlsButton::Reset => {
+                    commands.remove_resource::<Rebinding>();
+                    *input_map = InputMap::default();
+                    save_input_map(&input_map);
+                    "Default keys restored".to_string()
+                }
+            };
+            status.single_mut().sections[0].value = message.to_uppercase();
+        }
+    }
+
+    /// Binds the next key pressed to the action waiting for one, unless the key belongs
+    /// to another action.
+    fn rebind_key(
+        mut commands: Commands,
+        keyboard_input: Res<Input<KeyCode>>,
+        rebinding: Res<Rebinding>,
+        mut input_map: ResMut<InputMap>,
+        mut status: Query<&mut Text, With<ControlsStatus>>,
+    ) {
+        let Some(key) = keyboard_input.get_just_pressed().next().copied() else {
+            return;
+        };
+        commands.remove_resource::<Rebinding>();
+        let action = rebinding.action;
+        let result = match rebinding.add {
+            true => input_map.add(action, key),
+            false => input_map.bind(action, key),
+        };
+        let message = match result {
+            Ok(()) => {
+                save_input_map(&input_map);
+                format!("{}: {}", action.name(), input_map.key_names(action))
+            }
+            Err(conflict) => conflict.to_string(),
+        };
+        status.single_mut().sections[0].value = message.to_uppercase();
+    }
+
+    fn show_bindings(input_map: Res<InputMap>, mut texts: Query<(&BindingText, &mut Text)>) {
+        for (binding, mut text) in &mut texts {
+            text.sections[0].value = input_map.key_names(binding.0).to_uppercase();
+        }
+    }
+
+    fn stop_rebinding(mut commands: Commands) {
+        commands.remove_resource::<Rebinding>();
+    }
+
+    /// Goes back to the pause screen from the screen shown over it.
+    fn back_to_pause(
+        interaction_query: Query<
+            (&Interaction, &MenuButtonAction),
+            (Changed<Interaction>, With<Button>),
+        >,
+        mut menu_state: ResMut<NextState<MenuState>>,
+    ) {
+        for (interaction, menu_button_action) in &interaction_query {
+            if *interaction == Interaction::Pressed
+                && matches!(menu_button_action, MenuButtonAction::BackToMainMenu)
+            {
+                menu_state.set(MenuState::Disabled);
+            }
+        }
+    }
+
+    pub(super) fn button_system(
+        mut commands: Commands,
+        mut interaction_query: Query<
+            (
+                &Interaction,
+                &mut BackgroundColor,
+                Has<SelectedOption>,
+                Has<Focused>,
+            ),
+            (Changed<Interaction>, With<Button>),
+        >,
+        hovered_sound: Res<ButtonHoveredSound>,
+        pressed_sound: Res<ButtonPressedSound>,
+        mixer: Res<AudioMixer>,
+    ) {
+        for (interaction, mut color, selected, focused) in &mut interaction_query {
+            let sound = match *interaction {
+                Interaction::Hovered => Some(&hovered_sound.0),
+                Interaction::Pressed => Some(&pressed_sound.0),
+                Interaction::None => None,
+            };
+            if let Some(source) = sound {
+                mixer.play(&mut commands, source.clone(), Bus::Ui);
+            }
+            *color = button_color(*interaction, selected, focused).into();
+        }
+    }
+
+    /// The button picked with the keyboard or a gamepad.
+    #[derive(Component)]
+    pub struct Focused;
+
+    /// Marks the button that `⌫` and the `B` button of gamepads press, to go back.
+    #[derive(Component)]
+    pub struct BackButton;
+
+    /// The color of a button, the focused button being drawn as hovered by the mouse.
+    fn button_color(interaction: Interaction, selected: bool, focused: bool) -> Color {
+        match (interaction, selected, focused) {
+            (Interaction::Pressed, _, _) | (Interaction::None, true, false) => PRESSED_BUTTON,
+            (_, _, true) | (Interaction::Hovered, true, _) => HOVERED_PRESSED_BUTTON,
+            (Interaction::Hovered, false, false) => HOVERED_BUTTON,
+            (Interaction::None, false, false) => NORMAL_BUTTON,
+        }
+    }
+
+    /// The button `direction` leads to from the button at `from`, `buttons` being
+    /// sorted row by row. Up and down go to the nearest button of the row above or
+    /// below, left and right to the previous or next button, wrapping at the ends.
+    fn neighbour(buttons: &[(Entity, Vec2)], from: usize, direction: SnakeDirection) -> usize {
+        let position = buttons[from].1;
+        let above = match direction {
+            SnakeDirection::Left => return (from + buttons.len() - 1) % buttons.len(),
+            SnakeDirection::Right => return (from + 1) % buttons.len(),
+            SnakeDirection::Up => true,
+            SnakeDirection::Down => false,
+        };
+        // UI coordinates go down the screen. Past the first or last row, the focus goes
+        // round to the other end.
+        let rows = buttons.iter().map(|(_, other)| other.y);
+        let next_row = rows
+            .clone()
+            .filter(|y| match above {
+                true => *y < position.y - 1.0,
+                false => *y > position.y + 1.0,
+            })
+            .reduce(|a, b| if above { a.max(b) } else { a.min(b) });
+        let row = next_row
+            .or_else(|| rows.reduce(|a, b| if above { a.max(b) } else { a.min(b) }))
+            .unwrap_or(position.y);
+        (0..buttons.len())
+            .filter(|i| (buttons[*i].1.y - row).abs() <= 1.0)
+            .min_by(|a, b| {
+                let distance = |i: &usize| (buttons[*i].1.x - position.x).abs();
+                distance(a).total_cmp(&distance(b))
+            })
+            .unwrap_or(from)
+    }
+
+    /// Moves the focus between the visible buttons with the arrow keys, `Tab` and
+    /// `Shift` `Tab`, or with the D-pad and the left stick of gamepads.
+    pub fn navigate_focus(
+        mut commands: Commands,
+        keyboard_input: Res<Input<KeyCode>>,
+        game_state: Res<State<GameState>>,
+        mut presses: Local<DirectionPresses>,
+        gamepads: Res<Gamepads>,
+        gamepad_buttons: Res<Input<GamepadButton>>,
+        axes: Res<Axis<GamepadAxis>>,
+        mut buttons: Query<
+            (
+                Entity,
+                &GlobalTransform,
+                &InheritedVisibility,
+                &Interaction,
+                &mut BackgroundColor,
+                Has<SelectedOption>,
+                Has<Focused>,
+            ),
+            With<Button>,
+        >,
+        hovered_sound: Res<ButtonHoveredSound>,
+        mixer: Res<AudioMixer>,
+    ) {
+        use SnakeDirection::*;
+        let arrows = [
+            (KeyCode::Up, Up),
+            (KeyCode::Down, Down),
+            (KeyCode::Left, Left),
+            (KeyCode::Right, Right),
+        ];
+        let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        let key = match keyboard_input.just_pressed(KeyCode::Tab) {
+            true => Some(if shift { Left } else { Right }),
+            // The editor keeps the arrow keys to turn the start of the snake
+            false if *game_state.get() != GameState::Editor => arrows
+                .into_iter()
+                .find(|(key, _)| keyboard_input.just_pressed(*key))
+                .map(|(_, direction)| direction),
+            false => None,
+        };
+        let gamepad = presses.read(&gamepads, &gamepad_buttons, &axes);
+        let Some(direction) = key.or(gamepad) else {
+            return;
+        };
+
+        let mut positions: Vec<(Entity, Vec2)> = buttons
+            .iter()
+            .filter(|(_, _, visibility, ..)| visibility.get())
+            .map(|(entity, transform, ..)| (entity, transform.translation().truncate()))
+            .collect();
+        if positions.is_empty() {
+            return;
+        }
+        positions.sort_by(|(_, a), (_, b)| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+        let focused = positions
+            .iter()
+            .position(|(entity, _)| buttons.get(*entity).is_ok_and(|button| button.6));
+        // Without a focused button, going back starts from the end
+        let next = match (focused, direction) {
+            (Some(from), _) => neighbour(&positions, from, direction),
+            (None, Up | Left) => positions.len() - 1,
+            (None, Down | Right) => 0,
+        };
+        if Some(next) == focused {
+            return;
+        }
+
+        for (index, is_focused) in [(focused, false), (Some(next), true)] {
+            let Some(entity) = index.map(|index| positions[index].0) else {
+                continue;
+            };
+            if let Ok((_, _, _, interaction, mut color, selected, _)) = buttons.get_mut(entity) {
+                *color = button_color(*interaction, selected, is_focused).into();
+            }
+            match is_focused {
+                true => commands.entity(entity).insert(Focused),
+                false => commands.entity(entity).remove::<Focused>(),
+            };
+        }
+        mixer.play(&mut commands, hovered_sound.0.clone(), Bus::Ui);
+    }
+
+    /// Presses the focused button with `⏎`, `␣` or the `A` button of gamepads, or the
+    /// back button of the screen with `⌫` or `B`, the way a click would. The button is
+    /// let go on the next frame.
+    pub fn press_focused(
+        mut released: Local<Vec<Entity>>,
+        keyboard_input: Res<Input<KeyCode>>,
+        gamepads: Res<Gamepads>,
+        gamepad_buttons: Res<Input<GamepadButton>>,
+        mut buttons: Query<
+            (
+                Entity,
+                &mut Interaction,
+                &InheritedVisibility,
+                Has<Focused>,
+                Has<BackButton>,
+            ),
+            With<Button>,
+        >,
+    ) {
+        for entity in released.drain(..) {
+            if let Ok((_, mut interaction, ..)) = buttons.get_mut(entity) {
+                interaction.set_if_neq(Interaction::None);
+            }
+        }
+        let confirm =
+            keyboard_input.any_just_pressed([
+                KeyCode::Return,
+                KeyCode::NumpadEnter,
+                KeyCode::Space,
+            ]) || gamepad::just_pressed(&gamepads, &gamepad_buttons, GamepadButtonType::South);
+        let back = keyboard_input.just_pressed(KeyCode::Back)
+            || gamepad::just_pressed(&gamepads, &gamepad_buttons, GamepadButtonType::East);
+        for (entity, mut interaction, visibility, focused, back_button) in &mut buttons {
+            if visibility.get() && ((confirm && focused) || (back && back_button)) {
+                *interaction = Interaction::Pressed;
+                released.push(entity);
+            }
+        }
+    }
+
+    /// Moves the selection of an option row to the pressed button.
+    fn option_button<T: MenuOption>(
+        mut commands: Commands,
+        interaction_query: Query<(&Interaction, &T, Entity), (Changed<Interaction>, With<Button>)>,
+        mut selected_query: Query<(Entity, &mut BackgroundColor), (With<SelectedOption>, With<T>)>,
+        mut options: ResMut<T::Target>,
+    ) {
+        for (interaction, value, entity) in &interaction_query {
+            if *interaction == Interaction::Pressed && T::get(&options) != *value {
+                for (previous_button, mut previous_color) in &mut selected_query {
+                    *previous_color = NORMAL_BUTTON.into();
+                    commands.entity(previous_button).remove::<SelectedOption>();
+                }
+                commands.entity(entity).insert(SelectedOption);
+                value.set(&mut options);
+            }
+        }
+    }
+
+    fn menu_action(
+        mut commands: Commands,
+        interaction_query: Query<
+            (&Interaction, &MenuButtonAction),
+            (Changed<Interaction>, With<Button>),
+        >,
+        mut app_exit_events: EventWriter<AppExit>,
+        mut game_state: ResMut<NextState<GameState>>,
+        mut menu_state: ResMut<NextState<MenuState>>,
+        last_replay: Res<LastReplay>,
+        levels: Res<Levels>,
+        folders: Res<Assets<LoadedFolder>>,
+        level_assets: Res<Assets<LevelAsset>>,
+        mut options: ResMut<GameOptions>,
+        mut save_slot: ResMut<SaveSlot>,
+    ) {
+        for (interaction, menu_button_action) in &interaction_query {
+            if *interaction == Interaction::Pressed {
+                match menu_button_action {
+                    MenuButtonAction::Continue => {
+                        let Some(saved) = save_slot.take() else {
+                            continue;
+                        };
+                        // Restarting plays the same kind of game again
+                        let replay = &saved.replay;
+                        let level = replay.level.as_ref().and_then(|level| {
+                            level_assets
+                                .iter()
+                                .find(|(_, asset)| asset.0 == *level)
+                                .map(|(id, _)| id)
+                        });
+                        *options = GameOptions {
+                            boundary: replay.boundary,
+                            level,
+                            versus: replay.versus,
+                            bot: saved.bot,
+                            pace: replay.pace,
+                            power_ups: replay.power_ups,
+                            ruleset: replay.ruleset,
+                            time_limit: replay
+                                .time_limit
+                                .map_or(options.time_limit, |limit| limit.seconds),
+                        };
+                        if let Some(challenge) = saved.daily {
+                            commands.insert_resource(DailyGame {
+                                challenge,
+                                options: challenge.options(level),
+                            });
+                        }
+                        // A save is resumed once
+                        let path = save_path();
+                        if let Err(e) = std::fs::remove_file(&path) {
+                            error!("Cannot remove {}: {e}", path.display());
+                        }
+                        commands.insert_resource(Resume(saved));
+                        game_state.set(GameState::Game);
+                    }
+                    MenuButtonAction::Play => menu_state.set(MenuState::NewGame),
+                    MenuButtonAction::Daily => {
+                        // Levels made in the editor differ from one player to the other
+                        let bundled: Vec<_> = levels
+                            .loaded(&folders, &level_assets)
+                            .into_iter()
+                            .filter(|(id, _)| !levels.is_custom(*id))
+                            .collect();
+                        let challenge = DailyChallenge::of(Date::today(), bundled.len());
+                        let level = challenge.level.map(|index| bundled[index].0);
+                        info!(
+                            "Daily challenge of {}: {}",
+                            challenge.date,
+                            challenge.modifier.name()
+                        );
+                        commands.insert_resource(DailyGame {
+                            challenge,
+                            options: challenge.options(level),
+                        });
+                        game_state.set(GameState::Game);
+                    }
+                    MenuButtonAction::Start => game_state.set(GameState::Game),
+                    MenuButtonAction::Watch => {
+                        let Some(path) = last_replay.0.as_ref() else {
+                            continue;
+                        };
+                        match Replay::load(path) {
+                            Ok(replay) => match replay.validate() {
+                                Ok(()) => {
+                                    commands.insert_resource(ReplayPlayer::new(replay));
+                                    game_state.set(GameState::Replay);
+                                }
+                                Err(e) => error!("Cannot play {}: {e}", path.display()),
+                            },
+                            Err(e) => error!("Cannot load {}: {e}", path.display()),
+                        }
+                    }
+                    MenuButtonAction::HighScores => menu_state.set(MenuState::HighScores),
+                    MenuButtonAction::GlobalScores => menu_state.set(MenuState::GlobalScores),
+                    MenuButtonAction::Controls => menu_state.set(MenuState::Controls),
+                    MenuButtonAction::Settings => menu_state.set(MenuState::Settings),
+                    MenuButtonAction::Editor => game_state.set(GameState::Editor),
+                    MenuButtonAction::BackToMainMenu => menu_state.set(MenuState::Main),
+                    MenuButtonAction::Quit => app_exit_events.send(AppExit),
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn focus_wraps_around() {
+            // Two buttons side by side above a wide one, in UI coordinates
+            let buttons = [
+                (Entity::from_raw(0), Vec2::new(100.0, 50.0)),
+                (Entity::from_raw(1), Vec2::new(300.0, 50.0)),
+                (Entity::from_raw(2), Vec2::new(250.0, 150.0)),
+            ];
+            assert_eq!(neighbour(&buttons, 0, SnakeDirection::Right), 1);
+            assert_eq!(neighbour(&buttons, 2, SnakeDirection::Right), 0);
+            assert_eq!(neighbour(&buttons, 0, SnakeDirection::Left), 2);
+            assert_eq!(neighbour(&buttons, 1, SnakeDirection::Down), 2);
+            assert_eq!(neighbour(&buttons, 2, SnakeDirection::Up), 1);
+            assert_eq!(neighbour(&buttons, 2, SnakeDirection::Down), 1);
+            assert_eq!(neighbour(&buttons, 0, SnakeDirection::Up), 2);
+        }
+    }
+}
+
+pub mod results {
+    use super::editor::Playtest;
+    use super::{despawn_screen, GameState};
+    use crate::config::GameConfig;
+    use crate::constants::{RESULTS_TEXT_COLOR, RESULTS_TEXT_SIZE};
+    use crate::daily::{DailyGame, DailyResult};
+    use crate::difficulty::GamePace;
+    use crate::gamepad::{self, DirectionPresses};
+    use crate::input::{Action, InputMap};
+    use crate::leaderboard::{self, Submission};
+    use crate::logic::{Game, GameEvent, SnakeDirection};
+    use crate::scores::*;
+    use bevy::prelude::*;
+    use bevy::tasks::IoTaskPool;
+
+    pub struct ResultsPlugin;
+
+    impl Plugin for ResultsPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(OnEnter(GameState::Results), results_setup)
+                .add_systems(
+                    Update,
+                    (
+                        countdown.run_if(not(resource_exists::<NewHighScore>())),
+                        enter_initials.run_if(resource_exists::<NewHighScore>()),
+                    )
+                        .run_if(in_state(GameState::Results)),
+                )
+                .add_systems(
+                    OnExit(GameState::Results),
+                    despawn_screen::<OnResultsScreen>,
+                );
+        }
+    }
+
+    #[derive(Component)]
+    struct OnResultsScreen;
+
+    #[derive(Resource, Deref, DerefMut)]
+    pub struct ResultsTimer(pub Timer);
+
+    /// The initials being typed for a new high score.
+    #[derive(Component)]
+    struct InitialsText;
+
+    fn results_setup(
+        mut commands: Commands,
+        mut events: EventReader<GameEvent>,
+        asset_server: Res<AssetServer>,
+        game: Res<Game>,
+        high_scores: Res<HighScores>,
+        new_high_score: Option<Res<NewHighScore>>,
+        config: Res<GameConfig>,
+        pace: Res<GamePace>,
+        daily: Option<Res<DailyGame>>,
+        daily_result: Option<Res<DailyResult>>,
+        input_map: Res<InputMap>,
+    ) {
+        // should not be empty
+        assert!(!events.is_empty());
+
+        let event = events.read().last().unwrap();
+        let (results, text, color) = match event {
+            GameEvent::GameOver(why) => ("Game over!".to_string(), why.clone(), Color::RED),
+            GameEvent::GameWon => (
+                "Good job!".to_string(),
+                "Congratulations!".to_string(),
+                Color::GREEN,
+            ),
+            GameEvent::TimeUp => (
+                "Time's up!".to_string(),
+                format!("You scored {}!", game.score()),
+                Color::GREEN,
+            ),
+            GameEvent::Finished => (
+                "Well played!".to_string(),
+                format!("You scored {}!", game.score()),
+                Color::GREEN,
+            ),
+            GameEvent::Survived(why) => (
+                format!("You lasted {} moves!", game.ticks()),
+                why.clone(),
+                Color::RED,
+            ),
+            GameEvent::VersusOver {
+                winner: Some(winner),
+                why,
+            } => (
+                format!("Player {} wins!", winner + 1),
+                why.clone(),
+                config.colors.snake(*winner).0,
+            ),
+            GameEvent::VersusOver { winner: None, why } => {
+                ("Draw!".to_string(), why.clone(), RESULTS_TEXT_COLOR)
+            }
+        };
+
+        commands
+            .spawn((
                 NodeBundle {
                     style: Style {
                         width: Val::Percent(100.0),
@@ -538,17 +2796,746 @@ pub mod results {
                                 ..default()
                             }),
                         );
+                    })
+                    .with_children(|parent| {
+                        // The best score is the one of the rules and the pace of the game
+                        let category = ScoreCategory::of(&game, pace.0, config.refresh_rate);
+                        let scores = if game.is_versus() {
+                            let scores = game.snakes().iter().map(|snake| snake.score());
+                            let scores: Vec<String> = scores
+                                .enumerate()
+                                .map(|(i, score)| format!("P{} = {score}", i + 1))
+                                .collect();
+                            scores.join("  ")
+                        } else if let Some(daily_result) = &daily_result {
+                            format!(
+                                "Score = {}  Best today = {}",
+                                game.score(),
+                                daily_result.best
+                            )
+                        } else {
+                            let best = high_scores.best(&category);
+                            let new_best = new_high_score.as_ref().filter(|new| new.local);
+                            let best = best.max(new_best.map_or(0, |new| new.score));
+                            format!("Score = {}  Best = {best}", game.score())
+                        };
+                        let power_ups = if game.has_power_ups() {
+                            " power-ups"
+                        } else {
+                            ""
+                        };
+                        let scores = match &daily {
+                            Some(daily) => format!(
+                                "{scores}\nDaily {} {}",
+                                daily.challenge.date,
+                                daily.challenge.modifier.name()
+                            ),
+                            None => format!(
+                                "{scores}\n{} {}{power_ups}",
+                                category.ruleset_name(),
+                                category.pace_name()
+                            ),
+                        };
+                        parent.spawn(
+                            TextBundle::from_section(
+                                scores.to_uppercase(),
+                                TextStyle {
+                                    font_size: RESULTS_TEXT_SIZE,
+                                    color: RESULTS_TEXT_COLOR,
+                                    font: asset_server.load("font.ttf"),
+                                },
+                            )
+                            .with_text_alignment(TextAlignment::Center)
+                            .with_style(Style {
+                                margin: UiRect::all(Val::Px(50.0)),
+                                ..default()
+                            }),
+                        );
+                    })
+                    .with_children(|parent| {
+                        let Some(daily_result) = &daily_result else {
+                            return;
+                        };
+                        let text = match daily_result.new_best {
+                            true => format!("New best today!\nShare {}", daily_result.code),
+                            false => format!("Share {}", daily_result.code),
+                        };
+                        parent.spawn(
+                            TextBundle::from_section(
+                                text.to_uppercase(),
+                                TextStyle {
+                                    font_size: RESULTS_TEXT_SIZE,
+                                    color: Color::GREEN,
+                                    font: asset_server.load("font.ttf"),
+                                },
+                            )
+                            .with_text_alignment(TextAlignment::Center),
+                        );
+                    })
+                    .with_children(|parent| {
+                        let Some(new_high_score) = &new_high_score else {
+                            return;
+                        };
+                        let style = TextStyle {
+                            font_size: RESULTS_TEXT_SIZE,
+                            color: Color::GREEN,
+                            font: asset_server.load("font.ttf"),
+                        };
+                        let prompt = match new_high_score.local {
+                            true => "New high score! Your initials:",
+                            false => "Rank online! Your initials:",
+                        };
+                        parent.spawn(TextBundle::from_section(
+                            prompt.to_uppercase(),
+                            style.clone(),
+                        ));
+                        parent.spawn((
+                            TextBundle::from_section(initials_text(""), style.clone()).with_style(
+                                Style {
+                                    margin: UiRect::all(Val::Px(20.0)),
+                                    ..default()
+                                },
+                            ),
+                            InitialsText,
+                        ));
+                        let hint = format!(
+                            "Press enter to save\n{} to skip",
+                            input_map.key_names(Action::Quit)
+                        );
+                        parent.spawn(
+                            TextBundle::from_section(
+                                hint.to_uppercase(),
+                                TextStyle {
+                                    color: RESULTS_TEXT_COLOR,
+                                    ..style
+                                },
+                            )
+                            .with_text_alignment(TextAlignment::Center),
+                        );
                     });
             });
     }
 
+    /// The initials typed so far, blanks standing for the missing letters.
+    fn initials_text(initials: &str) -> String {
+        format!("{initials:_<INITIALS_LEN$}")
+    }
+
+    /// The characters the D-pad goes through when entering initials with a gamepad.
+    const INITIALS_CHARACTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+    /// Turns the last letter of `initials` to the next or the previous character,
+    /// starting a letter if there is none.
+    fn cycle_last_letter(initials: &mut String, forward: bool) {
+        let count = INITIALS_CHARACTERS.len();
+        let next = match initials.pop() {
+            Some(letter) => {
+                let index = INITIALS_CHARACTERS.find(letter).unwrap_or(0);
+                match forward {
+                    true => (index + 1) % count,
+                    false => (index + count - 1) % count,
+                }
+            }
+            None => 0,
+        };
+        initials.push(INITIALS_CHARACTERS.as_bytes()[next] as char);
+    }
+
+    /// Initials are typed on the keyboard, or entered with the D-pad of a gamepad: up and
+    /// down change the last letter, right adds a letter and left removes one. The quit
+    /// key or the `B` button of gamepads leave the score out, and the countdown goes on.
+    fn enter_initials(
+        mut commands: Commands,
+        mut characters: EventReader<ReceivedCharacter>,
+        keyboard_input: Res<Input<KeyCode>>,
+        input_map: Res<InputMap>,
+        mut presses: Local<DirectionPresses>,
+        gamepads: Res<Gamepads>,
+        gamepad_buttons: Res<Input<GamepadButton>>,
+        axes: Res<Axis<GamepadAxis>>,
+        mut new_high_score: ResMut<NewHighScore>,
+        mut high_scores: ResMut<HighScores>,
+        mut timer: ResMut<ResultsTimer>,
+        mut text: Query<&mut Text, With<InitialsText>>,
+        config: Res<GameConfig>,
+    ) {
+        if input_map.just_pressed(Action::Quit, &keyboard_input)
+            || gamepad::just_pressed(&gamepads, &gamepad_buttons, GamepadButtonType::East)
+        {
+            commands.remove_resource::<NewHighScore>();
+            timer.reset();
+            return;
+        }
+        let new_high_score = &mut *new_high_score;
+        let initials = &mut new_high_score.initials;
+        for event in characters.read() {
+            *initials = clean_initials(&format!("{initials}{}", event.char));
+        }
+        if keyboard_input.just_pressed(KeyCode::Back) {
+            initials.pop();
+        }
+        match presses.read(&gamepads, &gamepad_buttons, &axes) {
+            Some(SnakeDirection::Up) => cycle_last_letter(initials, true),
+            Some(SnakeDirection::Down) => cycle_last_letter(initials, false),
+            Some(SnakeDirection::Right) if initials.len() < INITIALS_LEN => {
+                initials.push(INITIALS_CHARACTERS.as_bytes()[0] as char);
+            }
+            Some(SnakeDirection::Left) => {
+                initials.pop();
+            }
+            _ => {}
+        }
+        if let Ok(mut text) = text.get_single_mut() {
+            text.sections[0].value = initials_text(initials);
+        }
+
+        let confirm = keyboard_input.just_pressed(KeyCode::Return)
+            || gamepad::just_pressed(&gamepads, &gamepad_buttons, GamepadButtonType::South);
+        if confirm && !initials.is_empty() {
+            if new_high_score.local {
+                let entry = HighScore {
+                    initials: initials.clone(),
+                    score: new_high_score.score,
+                };
+                high_scores.insert(new_high_score.category, entry);
+                let path = high_scores_path();
+                if let Err(e) = high_scores.save(&path) {
+                    error!("Cannot save the high scores to {}: {e}", path.display());
+                }
+            }
+            if let (Some(url), Some(replay)) =
+                (config.leaderboard_url.clone(), new_high_score.replay.take())
+            {
+                let submission = Submission {
+                    name: new_high_score.initials.clone(),
+                    score: new_high_score.score,
+                    replay,
+                };
+                // The server plays the game again, which may take a while
+                IoTaskPool::get()
+                    .spawn(async move {
+                        match leaderboard::submit(&url, &submission) {
+                            Ok(rank) => info!("Ranked {rank} on the leaderboard"),
+                            Err(e) => error!("Cannot send the score to {url}: {e}"),
+                        }
+                    })
+                    .detach();
+            }
+            commands.remove_resource::<NewHighScore>();
+            timer.reset();
+        }
+    }
+
     fn countdown(
         mut game_state: ResMut<NextState<GameState>>,
         time: Res<Time>,
         mut timer: ResMut<ResultsTimer>,
+        playtest: Option<Res<Playtest>>,
     ) {
         if timer.tick(time.delta()).finished() {
-            game_state.set(GameState::Menu);
+            // A test goes back to the level being edited
+            game_state.set(match playtest {
+                Some(_) => GameState::Editor,
+                None => GameState::Menu,
+            });
+        }
+    }
+}
+
+pub mod editor {
+    use super::game::GameMode;
+    use super::menu::{button_system, BackButton, SelectedOption};
+    use super::{despawn_screen, spawn_button, GameState};
+    use crate::config::GameConfig;
+    use crate::constants::*;
+    use crate::get_window_resolution;
+    use crate::input::{Action, InputMap};
+    use crate::level::{Level, LevelAsset, Levels, MAX_PORTALS};
+    use crate::logic::WallLocation;
+    use crate::logic::{cell_to_world, portal_color, world_to_cell, GameOptions, WallBundle};
+    use crate::rules::{Boundary, Position};
+    use bevy::asset::LoadedFolder;
+    use bevy::prelude::*;
+
+    pub struct EditorPlugin;
+
+    impl Plugin for EditorPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(OnEnter(GameState::Editor), editor_setup)
+                .add_systems(
+                    Update,
+                    (
+                        button_system,
+                        tool_button,
+                        editor_action,
+                        paint,
+                        turn_start,
+                        draw_level,
+                    )
+                        .chain()
+                        .run_if(in_state(GameState::Editor)),
+                )
+                .add_systems(
+                    Update,
+                    leave_playtest.run_if(
+                        in_state(GameState::Game)
+                            .and_then(in_state(GameMode::Running))
+                            .and_then(resource_exists::<Playtest>()),
+                    ),
+                )
+                .add_systems(OnExit(GameState::Editor), despawn_screen::<OnEditorScreen>);
+        }
+    }
+
+    /// The level being edited, kept while it is test-played.
+    #[derive(Resource)]
+    pub struct Editor {
+        pub level: Level,
+        tool: Tool,
+        /// The first end of the portal being placed.
+        portal_start: Option<Position>,
+        status: String,
+    }
+
+    /// The level being test-played from the editor.
+    #[derive(Resource)]
+    pub struct Playtest(pub Level);
+
+    #[derive(Component, Clone, Copy, PartialEq)]
+    enum Tool {
+        Wall,
+        Start,
+        Portal,
+        Apple,
+        Erase,
+    }
+
+    #[derive(Component)]
+    enum EditorButtonAction {
+        Play,
+        Save,
+        Back,
+    }
+
+    #[derive(Component)]
+    struct OnEditorScreen;
+
+    #[derive(Component)]
+    struct EditorCell(Position);
+
+    /// Shows which way the snake starts.
+    #[derive(Component)]
+    struct StartArrow;
+
+    #[derive(Component)]
+    struct StatusText;
+
+    impl Editor {
+        fn new(level: Level) -> Self {
+            Editor {
+                level,
+                tool: Tool::Wall,
+                portal_start: None,
+                status: String::new(),
+            }
+        }
+
+        /// Empties `position`, which cannot be the start.
+        fn clear(&mut self, position: Position) {
+            let level = &mut self.level;
+            level.obstacles.remove(&position);
+            level.apple_spawns.remove(&position);
+            if let Some(index) = level.portal_at(position) {
+                level.portals.remove(index);
+            }
+            if self.portal_start == Some(position) {
+                self.portal_start = None;
+            }
+        }
+
+        fn apply(&mut self, tool: Tool, position: Position, just_pressed: bool) {
+            if just_pressed {
+                self.status.clear();
+            }
+            if position == self.level.start {
+                if tool == Tool::Start && just_pressed {
+                    self.level.direction = self.level.direction.clockwise();
+                }
+                return;
+            }
+            match tool {
+                Tool::Wall => {
+                    self.clear(position);
+                    self.level.obstacles.insert(position);
+                }
+                Tool::Apple => {
+                    self.clear(position);
+                    self.level.apple_spawns.insert(position);
+                }
+                Tool::Erase => self.clear(position),
+                Tool::Start if just_pressed => {
+                    self.clear(position);
+                    self.level.start = position;
+                }
+                Tool::Portal if just_pressed => self.place_portal(position),
+                Tool::Start | Tool::Portal => {}
+            }
+        }
+
+        fn place_portal(&mut self, position: Position) {
+            if self.level.portal_at(position).is_some() {
+                self.clear(position);
+                return;
+            }
+            match self.portal_start.take() {
+                None if self.level.portals.len() >= MAX_PORTALS => {
+                    self.status = format!("At most {MAX_PORTALS} portals");
+                }
+                None => {
+                    self.clear(position);
+                    self.portal_start = Some(position);
+                }
+                Some(start) if start == position => {}
+                Some(start) => {
+                    self.clear(position);
+                    self.level.portals.push([start, position]);
+                }
+            }
+        }
+    }
+
+    fn editor_setup(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        editor: Option<Res<Editor>>,
+        options: Res<GameOptions>,
+        levels: Res<Levels>,
+        folders: Res<Assets<LoadedFolder>>,
+        level_assets: Res<Assets<LevelAsset>>,
+        config: Res<GameConfig>,
+        mut windows: Query<&mut Window>,
+    ) {
+        commands.remove_resource::<Playtest>();
+        // Back from a test, the level is still being edited
+        let level = match editor {
+            Some(editor) => editor.level.clone(),
+            None => {
+                let mut level = options
+                    .level
+                    .and_then(|id| level_assets.get(id))
+                    .map(|level| level.0.clone())
+                    .unwrap_or_else(|| {
+                        Level::open(
+                            config.grid_width,
+                            config.grid_height,
+                            INITIAL_SNAKE_DIRECTION,
+                        )
+                    });
+                // Only custom levels are overwritten, the others are saved as new ones
+                if !options.level.is_some_and(|id| levels.is_custom(id)) {
+                    let names: Vec<String> = levels
+                        .loaded(&folders, &level_assets)
+                        .into_iter()
+                        .map(|(_, name)| name)
+                        .collect();
+                    level.name = (1..)
+                        .map(|i| format!("Custom {i}"))
+                        .find(|name| !names.contains(name))
+                        .unwrap();
+                }
+                commands.insert_resource(Editor::new(level.clone()));
+                level
+            }
+        };
+
+        let mut window = windows.single_mut();
+        let (width, height) = get_window_resolution(&config, level.width, level.height);
+        window.resolution.set(width, height);
+
+        for location in [
+            WallLocation::Top,
+            WallLocation::Bottom,
+            WallLocation::Left,
+            WallLocation::Right,
+        ] {
+            commands.spawn((
+                WallBundle::new(location, &level, Boundary::Walls, &config),
+                OnEditorScreen,
+            ));
+        }
+
+        // The cells, colored by `draw_level`
+        for position in level.cells() {
+            commands.spawn((
+                SpriteBundle {
+                    transform: Transform {
+                        translation: cell_to_world(position, &level, &config).extend(0.0),
+                        scale: (config.tile_size * EDITOR_CELL_SCALE).extend(0.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                EditorCell(position),
+                OnEditorScreen,
+            ));
+        }
+        commands.spawn((
+            SpriteBundle {
+                transform: Transform::from_scale((config.tile_size / 4.0).extend(0.0)),
+                sprite: Sprite {
+                    color: config.colors.tail,
+                    ..default()
+                },
+                ..default()
+            },
+            StartArrow,
+            OnEditorScreen,
+        ));
+
+        let text_style = TextStyle {
+            font_size: EDITOR_TEXT_SIZE,
+            color: Color::WHITE,
+            font: asset_server.load("font.ttf"),
+        };
+        let button_style = Style {
+            width: Val::Px(EDITOR_BUTTON_WIDTH),
+            height: Val::Px(EDITOR_BUTTON_HEIGHT),
+            margin: UiRect::all(Val::Px(EDITOR_BUTTON_MARGIN)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        };
+        commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        flex_direction: FlexDirection::Column,
+                        justify_content: JustifyContent::SpaceBetween,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                },
+                OnEditorScreen,
+            ))
+            .with_children(|parent| {
+                parent.spawn(NodeBundle::default()).with_children(|parent| {
+                    for (tool, text) in [
+                        (Tool::Wall, "Wall"),
+                        (Tool::Start, "Start"),
+                        (Tool::Portal, "Portal"),
+                        (Tool::Apple, "Apple"),
+                        (Tool::Erase, "Erase"),
+                    ] {
+                        let mut button =
+                            spawn_button(parent, &button_style, &text_style, text, tool);
+                        if tool == Tool::Wall {
+                            button.insert((SelectedOption, BackgroundColor(PRESSED_BUTTON)));
+                        }
+                    }
+                });
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        for (action, text) in [
+                            (EditorButtonAction::Play, "Play"),
+                            (EditorButtonAction::Save, "Save"),
+                            (EditorButtonAction::Back, "Back"),
+                        ] {
+                            let back = matches!(action, EditorButtonAction::Back);
+                            let mut button =
+                                spawn_button(parent, &button_style, &text_style, text, action);
+                            if back {
+                                button.insert(BackButton);
+                            }
+                        }
+                        parent.spawn((
+                            TextBundle::from_section(
+                                "",
+                                TextStyle {
+                                    color: RESULTS_TEXT_COLOR,
+                                    ..text_style.clone()
+                                },
+                            )
+                            .with_style(Style {
+                                max_width: Val::Px(EDITOR_BUTTON_WIDTH * 2.5),
+                                margin: UiRect::left(Val::Px(EDITOR_BUTTON_MARGIN)),
+                                ..default()
+                            }),
+                            StatusText,
+                        ));
+                    });
+            });
+    }
+
+    /// Moves the selection of the toolbar to the pressed tool.
+    fn tool_button(
+        mut commands: Commands,
+        interaction_query: Query<
+            (&Interaction, &Tool, Entity),
+            (Changed<Interaction>, With<Button>),
+        >,
+        mut selected_query: Query<
+            (Entity, &mut BackgroundColor),
+            (With<SelectedOption>, With<Tool>),
+        >,
+        mut editor: ResMut<Editor>,
+    ) {
+        for (interaction, tool, entity) in &interaction_query {
+            if *interaction == Interaction::Pressed && editor.tool != *tool {
+                for (previous_button, mut previous_color) in &mut selected_query {
+                    *previous_color = NORMAL_BUTTON.into();
+                    commands.entity(previous_button).remove::<SelectedOption>();
+                }
+                commands.entity(entity).insert(SelectedOption);
+                editor.tool = *tool;
+                editor.portal_start = None;
+            }
+        }
+    }
+
+    fn editor_action(
+        mut commands: Commands,
+        interaction_query: Query<
+            (&Interaction, &EditorButtonAction),
+            (Changed<Interaction>, With<Button>),
+        >,
+        mut editor: ResMut<Editor>,
+        mut levels: ResMut<Levels>,
+        mut level_assets: ResMut<Assets<LevelAsset>>,
+        mut game_state: ResMut<NextState<GameState>>,
+    ) {
+        for (interaction, action) in &interaction_query {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            if let EditorButtonAction::Back = action {
+                commands.remove_resource::<Editor>();
+                game_state.set(GameState::Menu);
+                continue;
+            }
+            if let Err(e) = editor.level.validate() {
+                editor.status = format!("Invalid level: {e}");
+                continue;
+            }
+            match action {
+                EditorButtonAction::Play => {
+                    commands.insert_resource(Playtest(editor.level.clone()));
+                    game_state.set(GameState::Game);
+                }
+                EditorButtonAction::Save => {
+                    let path = editor.level.custom_path();
+                    editor.status = match editor.level.save(&path) {
+                        Ok(()) => {
+                            levels.save_custom(editor.level.clone(), &mut level_assets);
+                            format!("Saved as {:?}", editor.level.name)
+                        }
+                        Err(e) => {
+                            error!("Cannot save the level to {}: {e}", path.display());
+                            format!("Cannot save the level: {e}")
+                        }
+                    };
+                }
+                EditorButtonAction::Back => {}
+            }
+        }
+    }
+
+    /// Applies the current tool to the cell under the cursor, the right button erasing.
+    fn paint(
+        mouse: Res<Input<MouseButton>>,
+        windows: Query<&Window>,
+        cameras: Query<(&Camera, &GlobalTransform)>,
+        buttons: Query<&Interaction, With<Button>>,
+        config: Res<GameConfig>,
+        mut editor: ResMut<Editor>,
+    ) {
+        let (tool, button) = if mouse.pressed(MouseButton::Left) {
+            (editor.tool, MouseButton::Left)
+        } else if mouse.pressed(MouseButton::Right) {
+            (Tool::Erase, MouseButton::Right)
+        } else {
+            return;
+        };
+        if buttons
+            .iter()
+            .any(|interaction| *interaction != Interaction::None)
+        {
+            return;
+        }
+        let (camera, camera_transform) = cameras.single();
+        let Some(position) = windows
+            .single()
+            .cursor_position()
+            .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
+            .and_then(|world| world_to_cell(world, &editor.level, &config))
+        else {
+            return;
+        };
+        editor.apply(tool, position, mouse.just_pressed(button));
+    }
+
+    /// The keys of the moves set the direction the snake starts in.
+    fn turn_start(
+        keyboard_input: Res<Input<KeyCode>>,
+        input_map: Res<InputMap>,
+        mut editor: ResMut<Editor>,
+    ) {
+        for action in Action::MOVES.into_iter().flatten() {
+            if input_map.just_pressed(action, &keyboard_input) {
+                editor.level.direction = action.direction().unwrap();
+            }
+        }
+    }
+
+    fn draw_level(
+        editor: Res<Editor>,
+        config: Res<GameConfig>,
+        mut cells: Query<(&EditorCell, &mut Sprite)>,
+        mut arrow: Query<&mut Transform, With<StartArrow>>,
+        mut status: Query<&mut Text, With<StatusText>>,
+    ) {
+        if !editor.is_changed() {
+            return;
+        }
+        let level = &editor.level;
+        for (cell, mut sprite) in &mut cells {
+            let position = cell.0;
+            sprite.color = if position == level.start {
+                config.colors.head
+            } else if level.obstacles.contains(&position) {
+                config.colors.wall
+            } else if level.apple_spawns.contains(&position) {
+                config.colors.apple
+            } else if let Some(index) = level.portal_at(position) {
+                portal_color(index)
+            } else if editor.portal_start == Some(position) {
+                portal_color(level.portals.len())
+            } else {
+                EDITOR_GRID_COLOR
+            };
+        }
+        let offset = Vec2::from(level.direction) * config.tile_size / 4.0;
+        arrow.single_mut().translation =
+            (cell_to_world(level.start, level, &config) + offset).extend(1.0);
+        status.single_mut().sections[0].value = editor.status.to_uppercase();
+    }
+
+    /// Backspace ends a test and goes back to the editor.
+    fn leave_playtest(
+        keyboard_input: Res<Input<KeyCode>>,
+        mut game_state: ResMut<NextState<GameState>>,
+    ) {
+        if keyboard_input.just_pressed(KeyCode::Back) {
+            game_state.set(GameState::Editor);
         }
     }
 }
@@ -559,8 +3546,33 @@ fn despawn_screen<T: Component>(to_despawn: Query<Entity, With<T>>, mut commands
     }
 }
 
-fn get_scoreboard_position() -> f32 {
-    let window_height = get_window_resolution().1;
-    let top_wall_height = WallLocation::position(&WallLocation::Top).y;
+/// Spawns a button with a single line of text.
+fn spawn_button<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    style: &Style,
+    text_style: &TextStyle,
+    text: &str,
+    bundle: impl Bundle,
+) -> bevy::ecs::system::EntityCommands<'w, 's, 'a> {
+    let mut button = parent.spawn((
+        ButtonBundle {
+            style: style.clone(),
+            background_color: crate::constants::NORMAL_BUTTON.into(),
+            ..default()
+        },
+        bundle,
+    ));
+    button.with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            text.to_uppercase(),
+            text_style.clone(),
+        ));
+    });
+    button
+}
+
+fn get_scoreboard_position(level: &Level, config: &GameConfig) -> f32 {
+    let window_height = get_window_resolution(config, level.width, level.height).1;
+    let top_wall_height = WallLocation::Top.position(level, config).y;
     window_height / 2.0 - top_wall_height - 10.0
 }